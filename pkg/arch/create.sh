@@ -73,6 +73,7 @@ package() {
   _bin="\${CARGO_TARGET_DIR:-$ROOT/target}/${MUSL_TARGET}/release/sinkd"
   install -Dm755 "\${_bin}" "\${pkgdir}/usr/bin/sinkd"
   install -Dm644 "$ROOT/cfg/system/sinkd.conf" "\${pkgdir}/etc/sinkd.conf"
+  install -Dm644 "$ROOT/cfg/server/sinkd-server.conf" "\${pkgdir}/etc/sinkd-server.conf"
   install -Dm644 "$ROOT/cfg/user/sinkd.conf" "\${pkgdir}/usr/share/sinkd/sinkd.user.conf"
   # install -Dm644 "$ROOT/cfg/user/sinkd.conf" "\${pkgdir}/etc/skel/.config/sinkd/sinkd.conf"
   if [[ -f "$ROOT/LICENSE" ]]; then
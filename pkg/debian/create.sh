@@ -44,6 +44,7 @@ fi
 install "${BIN}" "${STAGE}/usr/bin/sinkd"
 install -m 644 LICENSE "${STAGE}/usr/share/doc/sinkd/copyright"
 install -m 644 "${ROOT}/cfg/system/sinkd.conf" "${STAGE}/etc/sinkd.conf"
+install -m 644 "${ROOT}/cfg/server/sinkd-server.conf" "${STAGE}/etc/sinkd-server.conf"
 install -m 644 "${ROOT}/cfg/user/sinkd.conf" "${STAGE}/usr/share/sinkd/sinkd.user.conf"
 # install -m 644 "${ROOT}/cfg/user/sinkd.conf" "${STAGE}/etc/skel/.config/sinkd/sinkd.conf"
 
@@ -0,0 +1,186 @@
+//! The server's apply pipeline: a pool of workers that run pushes side by side when their
+//! anchors don't overlap.
+//!
+//! A push claims its `src_paths` for as long as its rsync runs. A waiting push never overtakes
+//! an earlier one it overlaps, running or still queued, so pushes to the same tree are applied
+//! in arrival order while unrelated anchors and users keep moving.
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// `[apply]` table in the server config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApplyConfig {
+    /// How many pushes may be applied at once (at least one).
+    pub workers: usize,
+}
+
+impl Default for ApplyConfig {
+    fn default() -> Self {
+        ApplyConfig { workers: 4 }
+    }
+}
+
+/// Whether two path sets share a tree: one path equals or contains another.
+#[must_use]
+pub fn overlaps(a: &[PathBuf], b: &[PathBuf]) -> bool {
+    a.iter()
+        .any(|x| b.iter().any(|y| x.starts_with(y) || y.starts_with(x)))
+}
+
+/// A push handed to a worker; give the ticket back with [`ApplyQueue::finish`].
+#[derive(Debug)]
+pub struct Claim<J> {
+    pub ticket: u64,
+    pub paths: Vec<PathBuf>,
+    pub job: J,
+}
+
+#[derive(Debug)]
+struct Waiting<J> {
+    paths: Vec<PathBuf>,
+    job: J,
+}
+
+#[derive(Debug)]
+struct State<J> {
+    waiting: VecDeque<Waiting<J>>,
+    running: Vec<(u64, Vec<PathBuf>)>,
+    next_ticket: u64,
+}
+
+#[derive(Debug)]
+pub struct ApplyQueue<J> {
+    state: Mutex<State<J>>,
+    changed: Condvar,
+}
+
+impl<J> Default for ApplyQueue<J> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<J> ApplyQueue<J> {
+    #[must_use]
+    pub fn new() -> Self {
+        ApplyQueue {
+            state: Mutex::new(State {
+                waiting: VecDeque::new(),
+                running: Vec::new(),
+                next_ticket: 0,
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    // the state is only a queue: a panicking holder cannot leave it half-updated
+    fn lock(&self) -> MutexGuard<'_, State<J>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn push(&self, paths: Vec<PathBuf>, job: J) {
+        self.lock().waiting.push_back(Waiting { paths, job });
+        self.changed.notify_one();
+    }
+
+    /// The oldest waiting push that overlaps nothing running and no earlier waiting push;
+    /// `None` if none turns up within `timeout`.
+    pub fn next(&self, timeout: Duration) -> Option<Claim<J>> {
+        let mut st = self.lock();
+        loop {
+            if let Some(i) = st.startable() {
+                let Waiting { paths, job } = st.waiting.remove(i)?;
+                let ticket = st.next_ticket;
+                st.next_ticket += 1;
+                st.running.push((ticket, paths.clone()));
+                return Some(Claim { ticket, paths, job });
+            }
+            let (guard, wait) = self
+                .changed
+                .wait_timeout(st, timeout)
+                .unwrap_or_else(PoisonError::into_inner);
+            st = guard;
+            if wait.timed_out() {
+                return None;
+            }
+        }
+    }
+
+    /// Releases a claim's paths so overlapping pushes behind it can start.
+    pub fn finish(&self, ticket: u64) {
+        self.lock().running.retain(|(t, _)| *t != ticket);
+        self.changed.notify_all();
+    }
+
+    /// Anchors being applied right now.
+    #[must_use]
+    pub fn busy_paths(&self) -> Vec<PathBuf> {
+        self.lock()
+            .running
+            .iter()
+            .flat_map(|(_, p)| p.iter().cloned())
+            .collect()
+    }
+
+    /// Pushes accepted but not started yet.
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.lock().waiting.len()
+    }
+}
+
+impl<J> State<J> {
+    fn startable(&self) -> Option<usize> {
+        self.waiting.iter().enumerate().position(|(i, w)| {
+            !self.running.iter().any(|(_, p)| overlaps(p, &w.paths))
+                && !self
+                    .waiting
+                    .iter()
+                    .take(i)
+                    .any(|earlier| overlaps(&earlier.paths, &w.paths))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::ApplyQueue;
+
+    fn paths(p: &[&str]) -> Vec<PathBuf> {
+        p.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn disjoint_pushes_run_together_and_overlapping_ones_keep_order() {
+        let queue = ApplyQueue::new();
+        queue.push(paths(&["/home/a/docs"]), 1);
+        queue.push(paths(&["/home/b"]), 2);
+        queue.push(paths(&["/home/a/docs/sub"]), 3);
+        queue.push(paths(&["/home/a"]), 4);
+        let t = Duration::from_millis(10);
+
+        let first = queue.next(t).expect("first");
+        let second = queue.next(t).expect("second");
+        assert_eq!((first.job, second.job), (1, 2));
+        assert_eq!(queue.busy_paths(), paths(&["/home/a/docs", "/home/b"]));
+        // 3 waits for 1, and 4 may not overtake 3
+        assert!(queue.next(t).is_none());
+        assert_eq!(queue.depth(), 2);
+
+        queue.finish(first.ticket);
+        assert_eq!(queue.next(t).expect("third").job, 3);
+        assert!(queue.next(t).is_none());
+        queue.finish(second.ticket);
+        assert_eq!(queue.busy_paths(), paths(&["/home/a/docs/sub"]));
+    }
+}
@@ -0,0 +1,360 @@
+//! Who may push to and pull from the server.
+//!
+//! With `[auth] enabled`, a payload must come from an approved user (the names clients list
+//! under `users` in their system config) and, when `clients` is set, from an approved device.
+//! Each anchor or share is then opened by a [`Grant`]: `write` lets pushes land under it,
+//! `read` lets the `Behind` answer tell about changes under it (or, when the server has no
+//! change record, which grant paths to pull in full). Paths no grant covers are refused. Every
+//! decision goes to `audit.jsonl` in the sync root.
+//!
+//! Read grants only shape what the server tells a client to pull. The rsync transport itself
+//! (ssh, or direct access to the sync root) is not restricted by sinkd; lock that down with the
+//! transport's own accounts and permissions.
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{changes::ChangeSet, ipc, outcome::Outcome};
+
+pub const AUDIT_FILE: &str = "audit.jsonl";
+
+/// `[auth]` table in the server config.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Off: anyone who reaches the Zenoh network may push.
+    pub enabled: bool,
+    /// Usernames allowed to sync.
+    pub users: Vec<String>,
+    /// Device `client_id`s allowed to sync; empty: any device of an approved user.
+    pub clients: Vec<String>,
+    pub grants: Vec<Grant>,
+}
+
+/// Opens `path` (an anchor or a share) to `user`, `client`, or both; neither: every approved
+/// client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Grant {
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub client: Option<String>,
+    pub path: PathBuf,
+    pub access: Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    Read,
+    /// Implies read.
+    Write,
+}
+
+impl Access {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+        }
+    }
+}
+
+impl Grant {
+    fn applies_to(&self, client_id: &str, username: &str) -> bool {
+        self.user.as_deref().is_none_or(|u| u == username)
+            && self.client.as_deref().is_none_or(|c| c == client_id)
+    }
+
+    fn allows(&self, access: Access) -> bool {
+        access == Access::Read || self.access == Access::Write
+    }
+}
+
+impl AuthConfig {
+    fn approved(&self, client_id: &str, username: &str) -> Result<(), String> {
+        if client_id.is_empty() {
+            return Err("no client_id".to_string());
+        }
+        if !self.users.iter().any(|u| u == username) {
+            return Err(format!("user '{username}' is not approved"));
+        }
+        if !self.clients.is_empty() && !self.clients.iter().any(|c| c == client_id) {
+            return Err(format!("client '{client_id}' is not approved"));
+        }
+        Ok(())
+    }
+
+    fn covers(&self, client_id: &str, username: &str, access: Access, path: &Path) -> bool {
+        self.grants.iter().any(|g| {
+            g.applies_to(client_id, username) && g.allows(access) && path.starts_with(&g.path)
+        })
+    }
+
+    /// `Err` with the reason when `payload` may not `access` every one of `paths`.
+    pub fn check(
+        &self,
+        payload: &ipc::Payload,
+        access: Access,
+        paths: &[PathBuf],
+    ) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let (id, user) = (payload.client_id.as_str(), payload.username.as_str());
+        self.approved(id, user)?;
+        match paths.iter().find(|p| !self.covers(id, user, access, p)) {
+            Some(p) => Err(format!(
+                "no {} grant covers {}",
+                access.as_str(),
+                p.display()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// What of `set` (`None`: no change record, so everything) `payload` may pull; `Err` when
+    /// it may read nothing at all.
+    pub fn readable(&self, payload: &ipc::Payload, set: Option<ChangeSet>) -> Result<Pull, String> {
+        if !self.enabled {
+            return Ok(set.map_or(Pull::Full(Vec::new()), Pull::Changes));
+        }
+        let (id, user) = (payload.client_id.as_str(), payload.username.as_str());
+        self.approved(id, user)?;
+        let roots: Vec<PathBuf> = self
+            .grants
+            .iter()
+            .filter(|g| g.applies_to(id, user))
+            .map(|g| g.path.clone())
+            .collect();
+        if roots.is_empty() {
+            return Err("no read grant".to_string());
+        }
+        Ok(match set {
+            Some(set) => Pull::Changes(set.within(&roots)),
+            None => Pull::Full(roots),
+        })
+    }
+}
+
+/// What a `Behind` client is told to pull.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pull {
+    /// Just these changes.
+    Changes(ChangeSet),
+    /// No change record covers the range: everything under these paths (empty: every anchor).
+    Full(Vec<PathBuf>),
+}
+
+/// [`AuthConfig`] plus the audit log its decisions go to.
+#[derive(Debug)]
+pub struct Authorizer {
+    cfg: AuthConfig,
+    audit_path: PathBuf,
+}
+
+impl Authorizer {
+    #[must_use]
+    pub fn new(cfg: AuthConfig, srv_dir: &Path) -> Self {
+        if cfg.enabled && cfg.users.is_empty() {
+            warn!("auth: enabled with no approved users; every client will be refused");
+        }
+        Authorizer {
+            cfg,
+            audit_path: srv_dir.join(AUDIT_FILE),
+        }
+    }
+
+    /// May `payload` land its `src_paths`?
+    pub fn push(&self, payload: &ipc::Payload, now_unix: i64) -> Result<(), String> {
+        let decision = self.cfg.check(payload, Access::Write, &payload.src_paths);
+        self.record(now_unix, payload, "push", &decision);
+        decision
+    }
+
+    /// What of `set` may `payload` hear about?
+    pub fn pull(
+        &self,
+        payload: &ipc::Payload,
+        set: Option<ChangeSet>,
+        now_unix: i64,
+    ) -> Result<Pull, String> {
+        let readable = self.cfg.readable(payload, set);
+        let decision = readable.as_ref().map(|_| ()).map_err(Clone::clone);
+        self.record(now_unix, payload, "pull", &decision);
+        readable
+    }
+
+    fn record(
+        &self,
+        now_unix: i64,
+        payload: &ipc::Payload,
+        action: &str,
+        decision: &Result<(), String>,
+    ) {
+        if !self.cfg.enabled {
+            return;
+        }
+        if let Err(reason) = decision {
+            info!(
+                "auth: refused {action} from {}@{} ({}): {reason}",
+                payload.username, payload.hostname, payload.client_id
+            );
+        }
+        let entry = AuditEntry::new(now_unix, payload, action, decision);
+        if let Err(e) = audit(&self.audit_path, &entry) {
+            warn!("auth: unable to write audit log: {e}");
+        }
+    }
+}
+
+/// One line of `audit.jsonl`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditEntry {
+    pub at_unix: i64,
+    pub client_id: String,
+    pub hostname: String,
+    pub username: String,
+    /// `push` or `pull`.
+    pub action: String,
+    pub paths: Vec<PathBuf>,
+    pub allowed: bool,
+    /// Why it was refused; empty when allowed.
+    pub reason: String,
+}
+
+impl AuditEntry {
+    #[must_use]
+    pub fn new(
+        at_unix: i64,
+        payload: &ipc::Payload,
+        action: &str,
+        decision: &Result<(), String>,
+    ) -> Self {
+        AuditEntry {
+            at_unix,
+            client_id: payload.client_id.clone(),
+            hostname: payload.hostname.clone(),
+            username: payload.username.clone(),
+            action: action.to_string(),
+            paths: payload.src_paths.clone(),
+            allowed: decision.is_ok(),
+            reason: decision.as_ref().err().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// Appends `entry` as one line.
+pub fn audit(path: &Path, entry: &AuditEntry) -> Outcome<()> {
+    let mut line = serde_json::to_string(entry).map_err(|e| format!("encode audit entry: {e}"))?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("open audit log '{}': {e}", path.display()))?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Access, AuthConfig, Grant, Pull};
+    use crate::{changes::ChangeSet, ipc};
+
+    fn payload(client_id: &str, username: &str) -> ipc::Payload {
+        ipc::Payload::from(
+            "laptop".to_string(),
+            username.to_string(),
+            vec![],
+            PathBuf::new(),
+            String::new(),
+            client_id.to_string(),
+            0,
+            0,
+            String::new(),
+            ipc::Status::Ready,
+            None,
+            None,
+        )
+    }
+
+    fn grant(user: Option<&str>, path: &str, access: Access) -> Grant {
+        Grant {
+            user: user.map(str::to_string),
+            client: None,
+            path: PathBuf::from(path),
+            access,
+        }
+    }
+
+    #[test]
+    fn grants_gate_pushes_and_filter_pulls() {
+        let cfg = AuthConfig {
+            enabled: true,
+            users: vec!["alice".to_string(), "bob".to_string()],
+            clients: vec![],
+            grants: vec![
+                grant(Some("alice"), "/home/alice", Access::Write),
+                grant(None, "/srv/share", Access::Read),
+            ],
+        };
+        let alice = payload("id-a", "alice");
+        let home = [PathBuf::from("/home/alice/docs")];
+        let share = [PathBuf::from("/srv/share/team")];
+        assert!(cfg.check(&alice, Access::Write, &home).is_ok());
+        assert!(
+            cfg.check(&alice, Access::Write, &share).is_err(),
+            "read only"
+        );
+        assert!(cfg.check(&alice, Access::Read, &share).is_ok());
+        let bob = payload("id-b", "bob");
+        assert!(cfg.check(&bob, Access::Write, &home).is_err());
+        assert!(cfg
+            .check(&payload("id-m", "mallory"), Access::Read, &share)
+            .is_err());
+        assert!(cfg
+            .check(&payload("", "alice"), Access::Read, &share)
+            .is_err());
+
+        let mut set = ChangeSet::default();
+        set.record_changed(PathBuf::from("/home/alice/docs/a.txt"));
+        set.record_changed(PathBuf::from("/srv/share/team/b.txt"));
+        let Ok(Pull::Changes(for_bob)) = cfg.readable(&bob, Some(set.clone())) else {
+            panic!("share is readable");
+        };
+        assert_eq!(
+            for_bob.changed.into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/srv/share/team/b.txt")]
+        );
+        let Ok(Pull::Changes(for_alice)) = cfg.readable(&alice, Some(set)) else {
+            panic!("both are readable");
+        };
+        assert_eq!(for_alice.changed.len(), 2);
+        assert_eq!(
+            cfg.readable(&bob, None),
+            Ok(Pull::Full(vec![PathBuf::from("/srv/share")])),
+            "a full pull only covers bob's grants"
+        );
+
+        let pinned = AuthConfig {
+            clients: vec!["id-a".to_string()],
+            ..cfg
+        };
+        assert!(pinned.check(&alice, Access::Write, &home).is_ok());
+        assert!(pinned
+            .check(&payload("id-x", "alice"), Access::Write, &home)
+            .is_err());
+    }
+}
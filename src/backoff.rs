@@ -0,0 +1,150 @@
+//! Exponential backoff with jitter for retrying pushes the server turned away.
+//!
+//! Nothing here sleeps: callers ask [`Backoff::ready`] from their own loop, so the Zenoh
+//! thread keeps handling reloads, terminate and server traffic while a retry is pending.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// `[backoff]` table in the system config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackoffConfig {
+    /// First retry delay in milliseconds (doubles per failed attempt).
+    pub base_ms: u64,
+    /// Upper bound for any single delay, in seconds.
+    pub ceiling_secs: u64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        BackoffConfig {
+            base_ms: 1000,
+            ceiling_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    ceiling: Duration,
+    attempt: u32,
+    next_at: Option<Instant>,
+}
+
+impl Backoff {
+    #[must_use]
+    pub fn new(cfg: BackoffConfig) -> Self {
+        let base = Duration::from_millis(cfg.base_ms.max(1));
+        Backoff {
+            base,
+            ceiling: Duration::from_secs(cfg.ceiling_secs).max(base),
+            attempt: 0,
+            next_at: None,
+        }
+    }
+
+    /// Keeps the current attempt count but adopts new limits (config reload).
+    pub fn reconfigure(&mut self, cfg: BackoffConfig) {
+        let fresh = Backoff::new(cfg);
+        self.base = fresh.base;
+        self.ceiling = fresh.ceiling;
+    }
+
+    #[must_use]
+    pub fn ready(&self, now: Instant) -> bool {
+        self.next_at.is_none_or(|at| now >= at)
+    }
+
+    #[must_use]
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Time left until the next attempt is allowed.
+    #[must_use]
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.next_at
+            .map_or(Duration::ZERO, |at| at.saturating_duration_since(now))
+    }
+
+    /// Records a failed attempt and schedules the next one; returns the chosen delay.
+    pub fn fail(&mut self, now: Instant) -> Duration {
+        let delay = self.delay(self.attempt, random_unit());
+        self.attempt = self.attempt.saturating_add(1);
+        self.next_at = Some(now + delay);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+        self.next_at = None;
+    }
+
+    /// "Equal jitter": half of the capped exponential delay is fixed, the other half random.
+    fn delay(&self, attempt: u32, unit: f64) -> Duration {
+        let exp = self
+            .base
+            .checked_mul(1u32.checked_shl(attempt.min(31)).unwrap_or(u32::MAX))
+            .unwrap_or(self.ceiling)
+            .min(self.ceiling);
+        let half = exp / 2;
+        half + half.mul_f64(unit.clamp(0.0, 1.0))
+    }
+}
+
+/// Uniform value in `[0, 1)` from the v4 UUID generator (avoids pulling in `rand`).
+fn random_unit() -> f64 {
+    let bits = uuid::Uuid::new_v4().as_u64_pair().0 >> 11;
+    #[allow(clippy::cast_precision_loss)]
+    let unit = bits as f64 / (1u64 << 53) as f64;
+    unit
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Backoff, BackoffConfig};
+
+    fn backoff(base_ms: u64, ceiling_secs: u64) -> Backoff {
+        Backoff::new(BackoffConfig {
+            base_ms,
+            ceiling_secs,
+        })
+    }
+
+    #[test]
+    fn delay_doubles_until_ceiling() {
+        let b = backoff(1000, 5);
+        assert_eq!(b.delay(0, 1.0), Duration::from_secs(1));
+        assert_eq!(b.delay(1, 1.0), Duration::from_secs(2));
+        assert_eq!(b.delay(2, 1.0), Duration::from_secs(4));
+        assert_eq!(b.delay(3, 1.0), Duration::from_secs(5));
+        assert_eq!(b.delay(40, 1.0), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn jitter_stays_within_upper_half() {
+        let b = backoff(1000, 60);
+        assert_eq!(b.delay(2, 0.0), Duration::from_secs(2));
+        assert_eq!(b.delay(2, 0.5), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn fail_schedules_and_reset_clears() {
+        let mut b = backoff(1000, 60);
+        let now = Instant::now();
+        assert!(b.ready(now));
+        let delay = b.fail(now);
+        assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(1));
+        assert!(!b.ready(now));
+        assert!(b.ready(now + delay));
+        assert_eq!(b.attempt(), 1);
+        b.reset();
+        assert!(b.ready(now));
+        assert_eq!(b.attempt(), 0);
+    }
+}
@@ -0,0 +1,252 @@
+//! Which paths a server apply touched, so a `Behind` client pulls only those.
+//!
+//! The server runs its applies with `--out-format='%i %l %b %n'` and journals the parsed
+//! [`ChangeSet`] for every generation. Paths are absolute, as the client sees them: rsync `-R`
+//! reproduces the client path under the sync root, so the server's `home/u/docs/a.txt` is the
+//! client's `/home/u/docs/a.txt`. rsync writes a character it won't print as `\#ooo` (its byte
+//! in octal); `--8-bit-output` keeps that to control characters, and the parser decodes it.
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// rsync flag that makes every transferred file and deletion show up on stdout, with its size
+/// and the bytes actually sent for it.
+pub const ITEMIZE_ARG: &str = "--out-format=%i %l %b %n";
+/// Goes with [`ITEMIZE_ARG`]: names with non-ASCII characters are printed as they are rather
+/// than escaped.
+pub const EIGHT_BIT_ARG: &str = "--8-bit-output";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChangeSet {
+    /// Files (and symlinks) created or updated.
+    pub changed: BTreeSet<PathBuf>,
+    /// Files and directories removed; directories come after their contents.
+    pub deleted: Vec<PathBuf>,
+}
+
+impl ChangeSet {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.deleted.is_empty()
+    }
+
+    pub fn record_changed(&mut self, path: PathBuf) {
+        self.deleted.retain(|p| p != &path);
+        self.changed.insert(path);
+    }
+
+    pub fn record_deleted(&mut self, path: PathBuf) {
+        self.changed.remove(&path);
+        if !self.deleted.contains(&path) {
+            self.deleted.push(path);
+        }
+    }
+
+    /// Folds a later generation into this one; the later state of a path wins.
+    pub fn merge(&mut self, later: &ChangeSet) {
+        for path in &later.deleted {
+            self.record_deleted(path.clone());
+        }
+        for path in &later.changed {
+            self.record_changed(path.clone());
+        }
+    }
+
+    /// Whether anything here lies under one of `anchors`, or removed a directory holding one.
+    #[must_use]
+    pub fn touches(&self, anchors: &[PathBuf]) -> bool {
+        let hit = |p: &PathBuf| anchors.iter().any(|a| p.starts_with(a) || a.starts_with(p));
+        self.changed.iter().any(hit) || self.deleted.iter().any(hit)
+    }
+
+    /// Only the paths under one of `anchors`.
+    #[must_use]
+    pub fn within<'a, I>(&self, anchors: I) -> ChangeSet
+    where
+        I: IntoIterator<Item = &'a PathBuf>,
+    {
+        let anchors: Vec<&PathBuf> = anchors.into_iter().collect();
+        let covered = |p: &&PathBuf| anchors.iter().any(|a| p.starts_with(a));
+        ChangeSet {
+            changed: self.changed.iter().filter(covered).cloned().collect(),
+            deleted: self.deleted.iter().filter(covered).cloned().collect(),
+        }
+    }
+}
+
+/// What one rsync run did, parsed from its [`ITEMIZE_ARG`] output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Itemized {
+    pub changes: ChangeSet,
+    /// Total size of the files created or updated.
+    pub size_bytes: u64,
+    /// Bytes rsync actually sent for them (the delta).
+    pub sent_bytes: u64,
+}
+
+/// Parses the stdout of an rsync run with [`ITEMIZE_ARG`]; other lines (`--stats`) are skipped.
+#[must_use]
+pub fn parse_itemized(output: &str) -> Itemized {
+    let mut out = Itemized::default();
+    for line in output.lines() {
+        if let Some(rest) = line.strip_prefix("*deleting") {
+            if let Some((_, _, name)) = sized(rest.trim_start()) {
+                out.changes.record_deleted(client_path(name));
+            }
+            continue;
+        }
+        let Some((code, rest)) = line.split_once(' ') else {
+            continue;
+        };
+        let Some((size, sent, name)) = sized(rest) else {
+            continue;
+        };
+        let mut flags = code.chars();
+        let (Some(update), Some(kind)) = (flags.next(), flags.next()) else {
+            continue;
+        };
+        // directories only change attributes here; their files are listed on their own
+        if code.len() == 11 && "<>ch.".contains(update) && matches!(kind, 'f' | 'L') {
+            out.changes.record_changed(client_path(name));
+            out.size_bytes += size;
+            out.sent_bytes += sent;
+        }
+    }
+    out
+}
+
+/// Splits `"%l %b %n"` into its two byte counts and the name.
+fn sized(rest: &str) -> Option<(u64, u64, &str)> {
+    let (size, rest) = rest.split_once(' ')?;
+    let (sent, name) = rest.split_once(' ')?;
+    Some((size.parse().ok()?, sent.parse().ok()?, name))
+}
+
+fn client_path(name: &str) -> PathBuf {
+    Path::new("/").join(unescape(name.trim_end_matches('/')))
+}
+
+/// Undoes rsync's `\#ooo` escapes; anything else, a lone backslash included, is kept.
+fn unescape(name: &str) -> OsString {
+    let raw = name.as_bytes();
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        if let Some(byte) = raw.get(i..i + 5).and_then(escaped_byte) {
+            bytes.push(byte);
+            i += 5;
+        } else {
+            bytes.push(raw[i]);
+            i += 1;
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        OsString::from_vec(bytes)
+    }
+    #[cfg(not(unix))]
+    {
+        OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+/// The byte `\#ooo` stands for.
+fn escaped_byte(escape: &[u8]) -> Option<u8> {
+    let [b'\\', b'#', digits @ ..] = escape else {
+        return None;
+    };
+    if !digits.iter().all(|d| (b'0'..=b'7').contains(d)) {
+        return None;
+    }
+    u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{parse_itemized, ChangeSet};
+
+    #[cfg(unix)]
+    #[test]
+    fn parse_itemized_decodes_escaped_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let out = "\
+>f+++++++++ 5 5 home/u/docs/two\\#012lines.txt
+>f+++++++++ 5 5 home/u/docs/caf\u{e9} \\#377\\x\\#8.txt
+*deleting   0 0 home/u/docs/tab\\#011ed/
+";
+        let set = parse_itemized(out).changes;
+        assert_eq!(
+            set.changed.into_iter().collect::<Vec<_>>(),
+            vec![
+                PathBuf::from(OsStr::from_bytes(
+                    b"/home/u/docs/caf\xc3\xa9 \xff\\x\\#8.txt"
+                )),
+                PathBuf::from("/home/u/docs/two\nlines.txt"),
+            ]
+        );
+        assert_eq!(set.deleted, vec![PathBuf::from("/home/u/docs/tab\ted")]);
+    }
+
+    #[test]
+    fn parse_itemized_keeps_files_and_deletions() {
+        let out = "\
+cd+++++++++ 4096 0 home/u/docs/new/
+>f+++++++++ 120 120 home/u/docs/new/a.txt
+>f.st...... 3000 48 home/u/docs/b with space.txt
+cL+++++++++ 7 0 home/u/docs/link
+.d..t...... 4096 0 home/u/docs/
+*deleting   0 0 home/u/docs/old/c.txt
+*deleting   0 0 home/u/docs/old/
+
+Number of files: 4
+";
+        let itemized = parse_itemized(out);
+        assert_eq!((itemized.size_bytes, itemized.sent_bytes), (3127, 168));
+        let set = itemized.changes;
+        let changed: Vec<_> = set.changed.iter().cloned().collect();
+        assert_eq!(
+            changed,
+            vec![
+                PathBuf::from("/home/u/docs/b with space.txt"),
+                PathBuf::from("/home/u/docs/link"),
+                PathBuf::from("/home/u/docs/new/a.txt"),
+            ]
+        );
+        assert_eq!(
+            set.deleted,
+            vec![
+                PathBuf::from("/home/u/docs/old/c.txt"),
+                PathBuf::from("/home/u/docs/old"),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_keeps_latest_state_and_within_filters_by_anchor() {
+        let mut first = ChangeSet::default();
+        first.record_changed(PathBuf::from("/w/a/x"));
+        first.record_deleted(PathBuf::from("/w/a/y"));
+        first.record_changed(PathBuf::from("/w/b/z"));
+        let mut later = ChangeSet::default();
+        later.record_deleted(PathBuf::from("/w/a/x"));
+        later.record_changed(PathBuf::from("/w/a/y"));
+        first.merge(&later);
+
+        let mine = first.within([&PathBuf::from("/w/a")]);
+        assert_eq!(
+            mine.changed.into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/w/a/y")]
+        );
+        assert_eq!(mine.deleted, vec![PathBuf::from("/w/a/x")]);
+    }
+}
@@ -1,9 +1,11 @@
-use clap::{Arg, ArgAction, ArgMatches, Command};
-use std::{path::Path, process::ExitCode};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
+use std::{path::Path, process::ExitCode, time::Duration};
 
 use super::egress;
 use crate::client;
 use crate::parameters::ClientParameters;
+use crate::snapshot;
 
 pub(super) fn build_command() -> Command {
     let share_arg = Arg::new("share")
@@ -34,6 +36,15 @@ pub(super) fn build_command() -> Command {
                 .hide(true)
                 .global(true),
         )
+        .arg(
+            Arg::new("instance")
+                .long("instance")
+                .value_name("NAME")
+                .num_args(1)
+                .default_value(crate::parameters::DEFAULT_INSTANCE)
+                .global(true)
+                .help("run or address the client daemon named NAME (several per host)"),
+        )
         .arg(
             Arg::new("client-state-dir")
                 .long("client-state-dir")
@@ -89,12 +100,232 @@ pub(super) fn build_command() -> Command {
                 .about("List watched files for PATH(s)")
                 .arg(&path_arg),
         )
+        .subcommand(sync_command(&path_arg))
+        .subcommands(pause_commands(&path_arg))
+        .subcommand(status_command())
+        .subcommand(conflicts_command())
+        .subcommand(restore_command())
         .subcommand(Command::new("log").about("Show client log output"))
         .subcommand(Command::new("start").about("Start the client daemon"))
         .subcommand(Command::new("restart").about("Restart the client daemon"))
         .subcommand(Command::new("stop").about("Stop the client daemon"))
 }
 
+fn sync_command(path_arg: &Arg) -> Command {
+    Command::new("sync")
+        .about("Push PATH(s), or every anchor, right now")
+        .arg(path_arg)
+        .arg(
+            Arg::new("wait")
+                .long("wait")
+                .action(ArgAction::SetTrue)
+                .help("block until the server applies the push, then print head_generation"),
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .value_name("SECS")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("60")
+                .help("give up waiting after SECS (non-zero exit)"),
+        )
+}
+
+fn pause_commands(path_arg: &Arg) -> [Command; 2] {
+    [
+        Command::new("pause")
+            .about("Hold back pushes for PATH(s) anchors, or all (pulls continue)")
+            .arg(path_arg),
+        Command::new("resume")
+            .about("Resume pushes for PATH(s) anchors, or all")
+            .arg(path_arg),
+    ]
+}
+
+fn status_command() -> Command {
+    Command::new("status")
+        .about("Show what the client daemon is doing")
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("print machine-readable JSON"),
+        )
+}
+
+fn conflicts_command() -> Command {
+    let run_arg = Arg::new("run")
+        .long("run")
+        .value_name("N")
+        .value_parser(clap::value_parser!(u64))
+        .help("only look in backup run N (default: newest run holding the file)");
+    Command::new("conflicts")
+        .about("Review and resolve files saved aside by Behind pulls")
+        .subcommand_required(true)
+        .subcommand(Command::new("ls").about("List backup runs with their unresolved files"))
+        .subcommand(
+            Command::new("show")
+                .about("Diff our saved version of PATH against the one in place")
+                .arg(Arg::new("path").value_name("PATH").required(true))
+                .arg(&run_arg),
+        )
+        .subcommand(
+            Command::new("resolve")
+                .about("Settle PATH(s) by keeping our version or the pulled one")
+                .arg(
+                    Arg::new("path")
+                        .value_name("PATH")
+                        .num_args(1..)
+                        .required(true),
+                )
+                .arg(&run_arg)
+                .arg(
+                    Arg::new("keep-local")
+                        .long("keep-local")
+                        .action(ArgAction::SetTrue)
+                        .help("put our saved version back in place"),
+                )
+                .arg(
+                    Arg::new("keep-remote")
+                        .long("keep-remote")
+                        .action(ArgAction::SetTrue)
+                        .help("keep the version pulled from the server"),
+                )
+                .group(
+                    ArgGroup::new("keep")
+                        .args(["keep-local", "keep-remote"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("prune")
+                .about("Apply the [behind_backups] retention policy now")
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue)
+                        .help("only list the runs that would be removed"),
+                ),
+        )
+}
+
+fn restore_command() -> Command {
+    Command::new("restore")
+        .about("Bring files back from a backup run or from the server's history")
+        .long_about(
+            "Bring files back from a backup run (--run, then push them) or from a server \
+             snapshot (--generation / --at, needs [snapshots] on the server)",
+        )
+        .arg(
+            Arg::new("path")
+                .value_name("PATH")
+                .num_args(1..)
+                .help("only files at or under PATH(s) (default with --run: the whole run)"),
+        )
+        .arg(
+            Arg::new("run")
+                .long("run")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64))
+                .help("backup run to restore from (see `conflicts ls`)"),
+        )
+        .arg(
+            Arg::new("generation")
+                .long("generation")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64))
+                .requires("path")
+                .help("server snapshot of generation N"),
+        )
+        .arg(
+            Arg::new("at")
+                .long("at")
+                .value_name("TIME")
+                .requires("path")
+                .help("newest server snapshot at or before TIME (\"2026-10-01 14:00\")"),
+        )
+        .group(
+            ArgGroup::new("source")
+                .args(["run", "generation", "at"])
+                .required(true),
+        )
+        .arg(
+            Arg::new("to")
+                .long("to")
+                .value_name("DIR")
+                .conflicts_with("run")
+                .help("put server copies under DIR instead of in place"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("only list what would be restored"),
+        )
+}
+
+fn dispatch_restore(s: &ArgMatches, params: &ClientParameters) -> ExitCode {
+    let paths: Vec<&String> = s.get_many::<String>("path").unwrap_or_default().collect();
+    let dry_run = s.get_flag("dry-run");
+    if let Some(run) = s.get_one::<u64>("run").copied() {
+        return egress(client::restore_run(params, run, &paths, dry_run));
+    }
+    let want = if let Some(generation) = s.get_one::<u64>("generation").copied() {
+        snapshot::Pick::Generation(generation)
+    } else {
+        let at = s.get_one::<String>("at").map_or("", String::as_str);
+        let Some(t) = parse_local_time(at) else {
+            fancy_error!("--at '{at}': expected \"YYYY-MM-DD HH:MM[:SS]\"");
+            return ExitCode::FAILURE;
+        };
+        snapshot::Pick::At(t)
+    };
+    let to = s.get_one::<String>("to").map(String::as_str);
+    egress(client::restore_snapshot(params, want, &paths, to, dry_run))
+}
+
+/// `YYYY-MM-DD HH:MM[:SS]` (or a bare date, meaning midnight) in local time, as unix seconds.
+fn parse_local_time(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()?
+                .and_hms_opt(0, 0, 0)
+        })?;
+    Some(naive.and_local_timezone(Local).earliest()?.timestamp())
+}
+
+fn dispatch_conflicts(sub: &ArgMatches, params: &ClientParameters) -> ExitCode {
+    match sub.subcommand() {
+        Some(("ls", _)) => egress(client::conflicts_ls(params)),
+        Some(("prune", s)) => egress(client::conflicts_prune(params, s.get_flag("dry-run"))),
+        Some(("show", s)) => {
+            let path = s.get_one::<String>("path").map_or("", String::as_str);
+            egress(client::conflicts_show(
+                params,
+                s.get_one::<u64>("run").copied(),
+                path,
+            ))
+        }
+        Some(("resolve", s)) => {
+            let paths: Vec<&String> = s.get_many::<String>("path").unwrap_or_default().collect();
+            egress(client::conflicts_resolve(
+                params,
+                s.get_one::<u64>("run").copied(),
+                &paths,
+                s.get_flag("keep-local"),
+            ))
+        }
+        _ => {
+            fancy_error!("unknown subcommand");
+            ExitCode::FAILURE
+        }
+    }
+}
+
 fn check_path_exists(p: &str) -> bool {
     let p = Path::new(p);
     if p.exists() {
@@ -142,6 +373,22 @@ pub fn dispatch(sub: &ArgMatches, params: &ClientParameters) -> ExitCode {
                 .map(|ps| ps.filter(|p| check_path_exists(p)).collect());
             egress(client::ls(params, paths))
         }
+        Some(("sync", s)) => {
+            let paths = s
+                .get_many::<String>("path")
+                .map(|ps| ps.filter(|p| check_path_exists(p)).collect());
+            let timeout = Duration::from_secs(s.get_one::<u64>("timeout").copied().unwrap_or(60));
+            egress(client::sync(params, paths, s.get_flag("wait"), timeout))
+        }
+        Some((cmd @ ("pause" | "resume"), s)) => {
+            let paths = s
+                .get_many::<String>("path")
+                .map(|ps| ps.filter(|p| check_path_exists(p)).collect());
+            egress(client::pause(params, paths, cmd == "resume"))
+        }
+        Some(("status", s)) => egress(client::status(params, s.get_flag("json"))),
+        Some(("conflicts", s)) => dispatch_conflicts(s, params),
+        Some(("restore", s)) => dispatch_restore(s, params),
         Some(("log", _)) => egress(client::log(params)),
         _ => {
             fancy_error!("unknown subcommand");
@@ -17,19 +17,139 @@ pub(super) fn build_command() -> Command {
                 .hide(true)
                 .global(true),
         )
-        .subcommand(Command::new("start").about("Start the server daemon"))
-        .subcommand(Command::new("restart").about("Restart the server daemon"))
+        .arg(
+            Arg::new("instance")
+                .long("instance")
+                .value_name("NAME")
+                .num_args(1)
+                .default_value(crate::parameters::DEFAULT_INSTANCE)
+                .global(true)
+                .help("run or address the server daemon named NAME (several per host)"),
+        )
+        .arg(
+            Arg::new("server-config")
+                .help("server TOML (overrides default path)")
+                .long_help("overrides default server config path")
+                .short('s')
+                .long("srv-cfg")
+                .num_args(1)
+                .global(true),
+        )
+        .subcommand(
+            Command::new("start")
+                .about("Start the server daemon")
+                .arg(force_reset_arg()),
+        )
+        .subcommand(
+            Command::new("restart")
+                .about("Restart the server daemon")
+                .arg(force_reset_arg()),
+        )
         .subcommand(Command::new("stop").about("Stop the server daemon"))
-        .subcommand(Command::new("ls").about("Show server sync root and generation state"))
+        .subcommand(
+            Command::new("reload").about("Have the running server re-read its generation state"),
+        )
+        .subcommand(
+            Command::new("ls")
+                .about("Show server status, history, clients and disk usage")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("print the report as JSON"),
+                ),
+        )
+        .subcommand(clients_command())
+        .subcommand(
+            Command::new("changes")
+                .about("Show what each generation applied, from the change journal")
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .value_name("GEN")
+                        .value_parser(clap::value_parser!(u64))
+                        .default_value("0")
+                        .help("first generation to show"),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .value_name("GEN")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("last generation to show (default: the newest)"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("print the entries as JSON"),
+                ),
+        )
+}
+
+fn clients_command() -> Command {
+    Command::new("clients")
+        .about("List, label and forget the devices that sync with this server")
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .action(ArgAction::SetTrue)
+                .help("print the list as JSON"),
+        )
+        .subcommand(
+            Command::new("label")
+                .about("Name a device")
+                .arg(
+                    Arg::new("client")
+                        .required(true)
+                        .help("client id, id prefix or current label"),
+                )
+                .arg(Arg::new("name").help("new label (omit to clear it)")),
+        )
+        .subcommand(
+            Command::new("forget")
+                .about("Remove a device; it comes back if it syncs again")
+                .arg(
+                    Arg::new("client")
+                        .required(true)
+                        .help("client id, id prefix or label"),
+                ),
+        )
+}
+
+fn force_reset_arg() -> Arg {
+    Arg::new("force-reset")
+        .long("force-reset")
+        .action(ArgAction::SetTrue)
+        .help("replace a corrupt generation state with its backup, or start over at 0")
 }
 
 #[must_use]
 pub fn dispatch(sub: &ArgMatches, server: &ServerParameters) -> ExitCode {
     match sub.subcommand() {
-        Some(("start", _)) => egress(server::start(server)),
-        Some(("restart", _)) => egress(server::restart(server)),
-        Some(("stop", _)) => egress(server::stop()),
-        Some(("ls", _)) => egress(server::ls(server)),
+        Some(("start", m)) => egress(server::start(server, m.get_flag("force-reset"))),
+        Some(("restart", m)) => egress(server::restart(server, m.get_flag("force-reset"))),
+        Some(("stop", _)) => egress(server::stop(server)),
+        Some(("reload", _)) => egress(server::reload(server)),
+        Some(("ls", m)) => egress(server::ls(server, m.get_flag("json"))),
+        Some(("clients", m)) => match m.subcommand() {
+            Some(("label", l)) => egress(server::label_client(
+                server,
+                l.get_one::<String>("client").map_or("", String::as_str),
+                l.get_one::<String>("name").map(String::as_str),
+            )),
+            Some(("forget", f)) => egress(server::forget_client(
+                server,
+                f.get_one::<String>("client").map_or("", String::as_str),
+            )),
+            _ => egress(server::clients(server, m.get_flag("json"))),
+        },
+        Some(("changes", m)) => egress(server::changes(
+            server,
+            m.get_one::<u64>("from").copied().unwrap_or_default(),
+            m.get_one::<u64>("to").copied(),
+            m.get_flag("json"),
+        )),
         _ => {
             fancy_error!("unknown subcommand");
             ExitCode::FAILURE
@@ -3,7 +3,7 @@ use log::{debug, error, info, warn};
 
 use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
     sync::{
@@ -17,41 +17,108 @@ use std::{
 
 use crate::{
     config::{self, SysConfig},
-    conflict, ipc,
+    conflict, ignore,
+    ipc::{
+        self,
+        control::{self, Reply, Request, Response},
+    },
+    outbox::Outbox,
     outcome::Outcome,
-    parameters::{ClientParameters, DaemonParameters},
-    rsync::rsync,
+    parameters::{ClientParameters, DaemonParameters, DEFAULT_INSTANCE},
+    pending::PendingJournal,
+    rsync::{self, rsync},
+    runtime::{self, InstanceLock, RuntimeFiles},
+    server, snapshot,
+    status::{self, StatusBoard},
+    time,
 };
 
+const DEBUG_CLIENT_STATE_DIR: &str = "/tmp/sinkd/client";
+/// Ask again if the server has not answered a changes query by then.
+const CHANGES_QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+
 struct ClientSyncState {
     client_id: String,
     acked_generation: u64,
     ack_path: PathBuf,
+    /// Pushes of ours the server applied since `sync --wait` callers were last answered.
+    applied_pushes: Vec<AppliedPush>,
+    /// Head we last asked the server for changes up to, and when (`Behind` repeats).
+    changes_query: Option<(u64, Instant)>,
+    /// `push_id` for our next push; the server names it when it reports a failure.
+    next_push_id: u64,
 }
 
-fn client_state_dir(params: &ClientParameters) -> PathBuf {
-    let shared = &params.shared;
-    if shared.debug > 0 {
-        if let Some(p) = &params.client_state_dir_override {
-            if !p.as_os_str().is_empty() {
-                return p.clone();
-            }
-        }
-        if let Ok(p) = std::env::var("SINKD_CLIENT_STATE_DIR") {
-            let p = p.trim();
-            if !p.is_empty() {
-                return PathBuf::from(p);
-            }
+/// Control-socket request the Zenoh thread must answer (it owns the outbox).
+struct ControlCommand {
+    request: Request,
+    reply: Reply,
+}
+
+/// A push of ours the server acked, as its ack names it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AppliedPush {
+    push_id: u64,
+    anchors: Vec<PathBuf>,
+    head_generation: u64,
+}
+
+/// `sync --wait` caller waiting for the server to apply its anchors.
+struct SyncWaiter {
+    reply: Reply,
+    anchors: Vec<PathBuf>,
+    /// Pushes before this one were sent before the request and don't count.
+    first_push_id: u64,
+    /// Anchors no push since `first_push_id` has landed yet.
+    remaining: Vec<PathBuf>,
+    head_generation: u64,
+    deadline: Instant,
+}
+
+/// `--client-state-dir` / `SINKD_CLIENT_STATE_DIR`, honoured in debug mode only.
+fn client_state_dir_override(params: &ClientParameters) -> Option<PathBuf> {
+    if params.shared.debug == 0 {
+        return None;
+    }
+    if let Some(p) = &params.client_state_dir_override {
+        if !p.as_os_str().is_empty() {
+            return Some(p.clone());
         }
-        PathBuf::from("/tmp/sinkd/client")
+    }
+    std::env::var("SINKD_CLIENT_STATE_DIR")
+        .ok()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
+
+fn client_state_dir(params: &ClientParameters) -> PathBuf {
+    if let Some(dir) = client_state_dir_override(params) {
+        return dir;
+    }
+    let base = if params.shared.debug > 0 {
+        PathBuf::from(DEBUG_CLIENT_STATE_DIR)
     } else if cfg!(target_os = "windows") {
         PathBuf::from(r"C:\ProgramData\sinkd\client")
     } else {
         let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
         PathBuf::from(home).join(".local/share/sinkd")
+    };
+    // every extra instance gets its own client_id and journals
+    if params.shared.instance == DEFAULT_INSTANCE {
+        base
+    } else {
+        base.join("instances").join(&params.shared.instance)
     }
 }
 
+fn runtime_files(params: &ClientParameters) -> RuntimeFiles {
+    // scenarios run several debug clients on one host, one per state dir
+    let dir = client_state_dir_override(params)
+        .unwrap_or_else(|| runtime::runtime_dir(params.shared.debug));
+    RuntimeFiles::new(&dir, &params.shared.daemon_name())
+}
+
 fn ensure_client_state_dir(params: &ClientParameters) -> Outcome<PathBuf> {
     let dir = client_state_dir(params);
     if !dir.exists() {
@@ -99,6 +166,12 @@ fn load_client_sync_state(params: &ClientParameters) -> Outcome<Arc<Mutex<Client
         client_id,
         acked_generation,
         ack_path,
+        applied_pushes: Vec::new(),
+        changes_query: None,
+        // ids stay distinct across restarts, so a late report can't match a newer push
+        next_push_id: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(1, |d| u64::try_from(d.as_millis()).unwrap_or(1)),
     })))
 }
 
@@ -106,9 +179,11 @@ fn attach_client_outbound_basis(
     payload: &mut ipc::Payload,
     sync: &Mutex<ClientSyncState>,
 ) -> Outcome<()> {
-    let s = sync
+    let mut s = sync
         .lock()
         .map_err(|e| format!("client sync state lock: {e}"))?;
+    payload.push_id = s.next_push_id;
+    s.next_push_id += 1;
     payload.client_id.clear();
     payload.client_id.push_str(&s.client_id);
     payload.basis_generation = s.acked_generation;
@@ -117,33 +192,104 @@ fn attach_client_outbound_basis(
     Ok(())
 }
 
+/// Returns `true` when `server_msg` acks a push of ours.
 fn maybe_record_writer_ack(
     sync: &Mutex<ClientSyncState>,
     server_msg: &ipc::Payload,
     local_dirty: &Mutex<HashSet<PathBuf>>,
-) -> Outcome<()> {
+) -> Outcome<bool> {
     let mut s = sync
         .lock()
         .map_err(|e| format!("client sync state lock: {e}"))?;
     if server_msg.last_writer_client_id.is_empty() {
-        return Ok(());
+        return Ok(false);
     }
     if server_msg.last_writer_client_id == s.client_id
         && server_msg.head_generation > s.acked_generation
     {
-        s.acked_generation = server_msg.head_generation;
-        persist_acked_generation(&s.ack_path, s.acked_generation)?;
-        if let Ok(mut dirty) = local_dirty.lock() {
-            dirty.clear();
+        s.applied_pushes.push(AppliedPush {
+            push_id: server_msg.push_id,
+            anchors: server_msg.src_paths.clone(),
+            head_generation: server_msg.head_generation,
+        });
+        // the server applies disjoint pushes side by side: a gap below our generation holds
+        // someone else's changes, which the next `Behind` round pulls in. Until then our
+        // edits stay dirty, so that pull can still tell them from the server's; the server
+        // leaves our own generations out of staleness checks and change answers.
+        if s.acked_generation + 1 == server_msg.head_generation {
+            s.acked_generation = server_msg.head_generation;
+            persist_acked_generation(&s.ack_path, s.acked_generation)?;
+            if let Ok(mut dirty) = local_dirty.lock() {
+                dirty.clear();
+            }
+        } else {
+            debug!(
+                "client>> push applied at {} but acked is {}; not skipping the gap",
+                server_msg.head_generation, s.acked_generation
+            );
         }
+        return Ok(true);
     }
-    Ok(())
+    Ok(false)
+}
+
+/// Server replies meant for one pusher carry its `client_id`; broadcasts carry none.
+fn addressed_to_us(sync: &Mutex<ClientSyncState>, server_msg: &ipc::Payload) -> bool {
+    server_msg.client_id.is_empty()
+        || sync
+            .lock()
+            .is_ok_and(|s| s.client_id == server_msg.client_id)
 }
 
-fn mark_local_dirty(local_dirty: &Mutex<HashSet<PathBuf>>, path: &Path) {
+/// Returns `true` when `path` was not already dirty (the journal needs rewriting).
+fn mark_local_dirty(local_dirty: &Mutex<HashSet<PathBuf>>, path: &Path) -> bool {
     if let Ok(mut dirty) = local_dirty.lock() {
         let p = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
-        dirty.insert(p);
+        return dirty.insert(p);
+    }
+    false
+}
+
+/// Mirrors the outbox anchors and dirty set into `pending.toml` (no-op when unchanged).
+fn persist_pending(
+    journal: &PendingJournal,
+    outbox: &Outbox,
+    local_dirty: &Mutex<HashSet<PathBuf>>,
+) {
+    if let Err(e) = journal.save_anchors(outbox.anchors()) {
+        error!("client: unable to journal pending anchors: {e}");
+    }
+    persist_dirty(journal, local_dirty);
+}
+
+fn persist_dirty(journal: &PendingJournal, local_dirty: &Mutex<HashSet<PathBuf>>) {
+    let Ok(dirty) = local_dirty.lock() else {
+        return;
+    };
+    if let Err(e) = journal.save_dirty(&dirty) {
+        error!("client: unable to journal dirty paths: {e}");
+    }
+}
+
+/// Copies the daemon's in-memory state into `board` and writes `status.toml` if it changed.
+fn refresh_status(
+    board: &StatusBoard,
+    sync: &Mutex<ClientSyncState>,
+    outbox: &Outbox,
+    local_dirty: &Mutex<HashSet<PathBuf>>,
+) {
+    let acked = sync.lock().map(|s| s.acked_generation).unwrap_or_default();
+    let dirty_files = local_dirty.lock().map(|d| d.len()).unwrap_or_default();
+    board.update(|st| {
+        st.acked_generation = acked;
+        st.behind = st.server_head_generation.is_some_and(|head| head > acked);
+        st.dirty_files = dirty_files;
+        st.pending_anchors = outbox.queued().cloned().collect();
+        st.in_flight_anchors = outbox.in_flight().cloned().collect();
+        st.paused_anchors = outbox.paused().cloned().collect();
+    });
+    if let Err(e) = board.flush() {
+        warn!("client: unable to write status snapshot: {e}");
     }
 }
 
@@ -174,12 +320,16 @@ fn notify_config_for_platform() -> notify::Config {
 }
 
 pub fn start(params: &ClientParameters) -> Outcome<()> {
+    // held across the fork: the daemon keeps the lock until it exits
+    let _lock = InstanceLock::acquire(&runtime_files(params))?;
     println!("logging to: {}", params.shared.log_path.display());
     ipc::daemon(&DaemonParameters::Client(params.clone()))
 }
 
-pub fn stop(_params: &ClientParameters) -> Outcome<()> {
-    ipc::send_terminate_signal()
+pub fn stop(params: &ClientParameters) -> Outcome<()> {
+    let message = ipc::stop_daemon(&params.shared, &runtime_files(params))?;
+    println!("{message}");
+    Ok(())
 }
 
 pub fn restart(params: &ClientParameters) -> Outcome<()> {
@@ -192,9 +342,24 @@ pub fn restart(params: &ClientParameters) -> Outcome<()> {
     }
 }
 
-fn notify_reload() {
-    if let Err(e) = ipc::publish_config_reload_signal() {
-        warn!("config updated but could not publish reload notification over Zenoh: {e}");
+/// Asks a running daemon to re-read the config files; nothing to do when none is running.
+fn notify_reload(params: &ClientParameters) {
+    #[cfg(unix)]
+    {
+        let socket = runtime_files(params).socket;
+        if !socket.exists() {
+            return;
+        }
+        if let Err(e) = control::request(&socket, &Request::Reload) {
+            warn!("config updated but the running daemon did not reload it: {e}");
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = params;
+        if let Err(e) = ipc::publish_config_reload_signal() {
+            warn!("config updated but could not publish reload notification over Zenoh: {e}");
+        }
     }
 }
 
@@ -244,7 +409,7 @@ pub fn add(
         }
     }
 
-    notify_reload();
+    notify_reload(params);
     Ok(())
 }
 
@@ -288,7 +453,7 @@ pub fn rm(
         }
     }
 
-    notify_reload();
+    notify_reload(params);
     Ok(())
 }
 
@@ -305,7 +470,7 @@ pub fn adduser(params: &ClientParameters, users: Option<ValuesRef<String>>) -> O
     }
     config::save_system_config_file(sys_path, &sys)?;
     info!("updated system config {}", sys_path.display());
-    notify_reload();
+    notify_reload(params);
     Ok(())
 }
 
@@ -320,7 +485,7 @@ pub fn rmuser(params: &ClientParameters, users: Option<ValuesRef<String>>) -> Ou
     }
     config::save_system_config_file(sys_path, &sys)?;
     info!("updated system config {}", sys_path.display());
-    notify_reload();
+    notify_reload(params);
     Ok(())
 }
 
@@ -350,6 +515,468 @@ pub fn ls(params: &ClientParameters, paths: Option<Vec<&String>>) -> Outcome<()>
     Ok(())
 }
 
+/// Anchors covered by `paths`: the anchor holding each path, or every anchor below it.
+/// No paths means every anchor.
+fn anchors_for_paths<'a, I>(anchors: I, paths: &[PathBuf]) -> Vec<PathBuf>
+where
+    I: IntoIterator<Item = &'a PathBuf>,
+{
+    let mut out: Vec<PathBuf> = anchors
+        .into_iter()
+        .filter(|a| paths.is_empty() || paths.iter().any(|p| p.starts_with(a) || a.starts_with(p)))
+        .cloned()
+        .collect();
+    out.sort();
+    out
+}
+
+/// The parts of `anchors` under one of the server's read `roots`: a whole anchor inside a
+/// root, or a root inside an anchor.
+fn readable_anchors(anchors: &[PathBuf], roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut out: BTreeSet<PathBuf> = BTreeSet::new();
+    for a in anchors {
+        if roots.iter().any(|r| a.starts_with(r)) {
+            out.insert(a.clone());
+        } else {
+            out.extend(roots.iter().filter(|r| r.starts_with(a)).cloned());
+        }
+    }
+    out.into_iter().collect()
+}
+
+/// Asks the running daemon to push `paths` (or all anchors) now; with `wait`, blocks until the
+/// server applies a push from this client and prints the resulting `head_generation`.
+pub fn sync(
+    params: &ClientParameters,
+    paths: Option<Vec<&String>>,
+    wait: bool,
+    timeout: Duration,
+) -> Outcome<()> {
+    let paths = resolve_cli_paths("sync", paths)?;
+    let resp = control::request(
+        &runtime_files(params).socket,
+        &Request::Sync {
+            paths,
+            wait,
+            timeout_secs: timeout.as_secs(),
+        },
+    )?;
+    let data = resp.data.unwrap_or_default();
+    if let Some(anchors) = data.get("anchors").and_then(|a| a.as_array()) {
+        for a in anchors.iter().filter_map(|a| a.as_str()) {
+            println!("sync requested: {a}");
+        }
+    }
+    if let Some(head) = data
+        .get("head_generation")
+        .and_then(serde_json::Value::as_u64)
+    {
+        println!("head_generation: {head}");
+    }
+    Ok(())
+}
+
+/// Holds back (or with `resume`, releases) pushes for the anchors covering `paths`.
+pub fn pause(params: &ClientParameters, paths: Option<Vec<&String>>, resume: bool) -> Outcome<()> {
+    let paths = resolve_cli_paths(if resume { "resume" } else { "pause" }, paths)?;
+    let resp = control::request(
+        &runtime_files(params).socket,
+        &Request::Pause { paths, resume },
+    )?;
+    println!("{}", resp.message);
+    Ok(())
+}
+
+/// No PATH arguments (`None`) means every anchor. PATHs that were all filtered out for not
+/// existing are an error, not every anchor.
+fn resolve_cli_paths(cmd: &str, paths: Option<Vec<&String>>) -> Outcome<Vec<PathBuf>> {
+    let Some(paths) = paths else {
+        return Ok(Vec::new());
+    };
+    if paths.is_empty() {
+        return bad!("{}: none of the given paths exist", cmd);
+    }
+    paths.iter().map(|p| config::resolve(p)).collect()
+}
+
+/// Prints what the daemon reports over its control socket, or the last snapshot it left behind.
+pub fn status(params: &ClientParameters, json: bool) -> Outcome<()> {
+    let live = control::request(&runtime_files(params).socket, &Request::Status)
+        .ok()
+        .and_then(|resp| resp.data)
+        .and_then(|data| serde_json::from_value::<status::ClientStatus>(data).ok());
+    let report = if let Some(st) = live {
+        status::StatusReport {
+            alive: true,
+            status: st,
+        }
+    } else {
+        let dir = client_state_dir(params);
+        match status::read(&dir) {
+            Some(st) => status::StatusReport {
+                alive: status::pid_alive(st.pid),
+                status: st,
+            },
+            None => status::StatusReport {
+                alive: false,
+                status: status::ClientStatus {
+                    client_id: fs::read_to_string(dir.join("client_id"))
+                        .map(|s| s.trim().to_string())
+                        .unwrap_or_default(),
+                    acked_generation: load_acked_generation(&dir.join("acked_generation")),
+                    ..status::ClientStatus::default()
+                },
+            },
+        }
+    };
+    if json {
+        let out = serde_json::to_string_pretty(&report).map_err(|e| format!("status json: {e}"))?;
+        println!("{out}");
+    } else {
+        print_status(&report);
+    }
+    Ok(())
+}
+
+fn print_status(report: &status::StatusReport) {
+    fn list(paths: &[PathBuf]) -> String {
+        if paths.is_empty() {
+            return "-".to_string();
+        }
+        paths
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    let st = &report.status;
+    if report.alive {
+        println!("daemon: running (pid {})", st.pid);
+    } else if st.pid == 0 {
+        println!("daemon: not running");
+    } else {
+        println!("daemon: not running (last pid {})", st.pid);
+    }
+    println!("client_id: {}", st.client_id);
+    println!("acked_generation: {}", st.acked_generation);
+    match st.server_head_generation {
+        Some(head) => println!(
+            "server head_generation: {head}{}",
+            if st.behind { " (Behind)" } else { "" }
+        ),
+        None => println!("server head_generation: unknown"),
+    }
+    if let Some(s) = &st.server_status {
+        println!("server status: {s}");
+    }
+    if let Some(why) = &st.refused {
+        println!("pushes refused: {why}");
+    }
+    println!("dirty files: {}", st.dirty_files);
+    println!("pending anchors: {}", list(&st.pending_anchors));
+    println!("in-flight anchors: {}", list(&st.in_flight_anchors));
+    println!("rsync: {}", st.rsync.as_deref().unwrap_or("idle"));
+    println!("paused anchors: {}", list(&st.paused_anchors));
+    println!("degraded anchors: {}", list(&st.degraded_anchors));
+    match (&st.last_error, &st.last_error_at) {
+        (Some(e), Some(at)) => println!("last error: [{at}] {e}"),
+        (Some(e), None) => println!("last error: {e}"),
+        _ => println!("last error: -"),
+    }
+    if !st.changed_at.is_empty() {
+        println!("updated: {}", st.changed_at);
+    }
+}
+
+/// `sinkd client conflicts ls`: open backup runs and their unresolved files.
+pub fn conflicts_ls(params: &ClientParameters) -> Outcome<()> {
+    let runs = conflict::runs(&client_state_dir(params))?;
+    if runs.is_empty() {
+        println!("no unresolved conflicts");
+        return Ok(());
+    }
+    for run in runs {
+        let created = if run.manifest.created_at.is_empty() {
+            "unknown time"
+        } else {
+            run.manifest.created_at.as_str()
+        };
+        println!(
+            "run {}  {created}  head_generation {} (acked {})  {} unresolved",
+            run.id,
+            run.manifest.head_generation,
+            run.manifest.acked_generation,
+            run.files.len()
+        );
+        for file in &run.files {
+            println!("    {}", file.original.display());
+        }
+    }
+    Ok(())
+}
+
+/// `sinkd client conflicts show`: diff our saved version against what is in place now.
+pub fn conflicts_show(params: &ClientParameters, run: Option<u64>, path: &str) -> Outcome<()> {
+    let path = conflict_path(path)?;
+    let (run, file) = conflict::find(&client_state_dir(params), run, &path)?;
+    println!(
+        "run {} (head_generation {}): ours '{}', in place '{}'",
+        run.id,
+        run.manifest.head_generation,
+        file.saved.display(),
+        file.original.display()
+    );
+    let status = std::process::Command::new("diff")
+        .arg("-u")
+        .arg(&file.saved)
+        .arg(&file.original)
+        .status()
+        .map_err(|e| format!("unable to run diff: {e}"))?;
+    // diff exits 1 when the files differ
+    match status.code() {
+        Some(0 | 1) => Ok(()),
+        _ => bad!("diff failed with {}", status),
+    }
+}
+
+/// `sinkd client conflicts resolve`: keep our version or the pulled one for each path.
+pub fn conflicts_resolve(
+    params: &ClientParameters,
+    run: Option<u64>,
+    paths: &[&String],
+    keep_local: bool,
+) -> Outcome<()> {
+    let state_dir = client_state_dir(params);
+    for path in paths {
+        let path = conflict_path(path)?;
+        let (found, file) = conflict::find(&state_dir, run, &path)?;
+        let archived = conflict::resolve(&found, &file, keep_local)?;
+        let kept = if keep_local { "local" } else { "remote" };
+        println!("{}: kept {kept} version", path.display());
+        if let Some(dir) = archived {
+            println!(
+                "run {} fully resolved; archived to {}",
+                found.id,
+                dir.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `sinkd client conflicts prune`: apply (or with `dry_run`, preview) backup retention.
+pub fn conflicts_prune(params: &ClientParameters, dry_run: bool) -> Outcome<()> {
+    let retention = config::settings(params)?.retention;
+    let pruned = conflict::prune(
+        &client_state_dir(params),
+        &retention,
+        std::time::SystemTime::now(),
+        dry_run,
+    )?;
+    if pruned.is_empty() {
+        println!("nothing to prune");
+    }
+    let verb = if dry_run { "would prune" } else { "pruned" };
+    for p in pruned {
+        println!(
+            "{verb} {} ({} bytes): {}",
+            p.dir.display(),
+            p.bytes,
+            p.reason
+        );
+    }
+    Ok(())
+}
+
+/// `sinkd client restore --run N`: put a backup run's saved files back where they came from
+/// and have the daemon push them.
+pub fn restore_run(
+    params: &ClientParameters,
+    run: u64,
+    paths: &[&String],
+    dry_run: bool,
+) -> Outcome<()> {
+    let paths = paths
+        .iter()
+        .map(|p| conflict_path(p))
+        .collect::<Outcome<Vec<_>>>()?;
+    let plan = conflict::restore_plan(&client_state_dir(params), run, &paths)?;
+    let verb = if dry_run { "would restore" } else { "restored" };
+    for file in &plan {
+        println!(
+            "{verb} {} from {}",
+            file.original.display(),
+            file.saved.display()
+        );
+    }
+    if dry_run {
+        return Ok(());
+    }
+    conflict::restore(&plan)?;
+
+    let restored = plan.into_iter().map(|f| f.original).collect();
+    let sync = Request::Sync {
+        paths: restored,
+        wait: false,
+        timeout_secs: 0,
+    };
+    match control::request(&runtime_files(params).socket, &sync) {
+        Ok(resp) => println!("{}", resp.message),
+        Err(e) => {
+            println!("not pushed yet ({e}); run `sinkd client sync` once the client daemon is up");
+        }
+    }
+    Ok(())
+}
+
+/// Pulls `paths` as they were in a server snapshot, into place or under `to`. In place, the
+/// files it replaces or deletes are kept as a backup run and the result is pushed like any
+/// local edit; `acked_generation` stays as it is either way.
+pub fn restore_snapshot(
+    params: &ClientParameters,
+    want: snapshot::Pick,
+    paths: &[&String],
+    to: Option<&str>,
+    dry_run: bool,
+) -> Outcome<()> {
+    if paths.is_empty() {
+        return bad!("restore: name the PATH(s) to bring back from the server");
+    }
+    let paths = paths
+        .iter()
+        .map(|p| conflict_path(p))
+        .collect::<Outcome<Vec<_>>>()?;
+    let sys = config::load_system_config_file(params.system_config.as_ref().as_path())?;
+    let server = ServerRoot::new(&sys.server_addr, params.shared.debug);
+    let state_dir = client_state_dir(params);
+    let kept = server.snapshots(&state_dir.join("snapshot_manifests"))?;
+    let (generation, taken_at) = snapshot::pick(&kept, want)?;
+    println!(
+        "generation {generation} (snapshot taken {})",
+        time::local_stamp(taken_at)
+    );
+
+    let dest = match to {
+        Some(dir) => std::path::absolute(dir).map_err(|e| format!("--to '{dir}': {e}"))?,
+        None => PathBuf::from("/"),
+    };
+    let verb = if dry_run {
+        "would restore"
+    } else {
+        "restoring"
+    };
+    for path in &paths {
+        let rel = path.strip_prefix("/").unwrap_or(path);
+        println!("{verb} {} -> {}", path.display(), dest.join(rel).display());
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    let srcs: Vec<PathBuf> = paths.iter().map(|p| server.source(generation, p)).collect();
+    let backup_run = if to.is_none() {
+        let info = conflict::PullInfo {
+            head_generation: generation,
+            acked_generation: load_acked_generation(&state_dir.join("acked_generation")),
+            dirty: Vec::new(),
+        };
+        Some(conflict::begin_backup_run(&state_dir, &info)?)
+    } else {
+        fs::create_dir_all(&dest)?;
+        None
+    };
+    let pulled = rsync(
+        &srcs,
+        &dest,
+        &config::ResolvedRsyncConfig::default(),
+        backup_run.as_deref(),
+    );
+    let Some(run) = backup_run else {
+        return pulled;
+    };
+    // a failed pull may still have replaced some files: keep what it saved
+    if conflict::finish_backup_run(&run)? == 0 {
+        fs::remove_dir_all(&run)?;
+    } else {
+        println!("replaced local files are saved in {}", run.display());
+    }
+    pulled?;
+    let sync = Request::Sync {
+        paths,
+        wait: false,
+        timeout_secs: 0,
+    };
+    match control::request(&runtime_files(params).socket, &sync) {
+        Ok(resp) => println!("{}", resp.message),
+        Err(e) => {
+            println!("not pushed yet ({e}); run `sinkd client sync` once the client daemon is up");
+        }
+    }
+    Ok(())
+}
+
+/// The server's sync root as `server_addr` reaches it: a path on this machine (`localhost`,
+/// or an absolute `server_addr`) or `host:` plus the server's default root.
+enum ServerRoot {
+    Local(PathBuf),
+    Remote { host: String, root: PathBuf },
+}
+
+impl ServerRoot {
+    fn new(server_addr: &str, debug: u8) -> Self {
+        let addr = server_addr.trim();
+        if addr.starts_with('/') {
+            ServerRoot::Local(PathBuf::from(addr))
+        } else if addr.is_empty()
+            || addr == "localhost"
+            || config::get_hostname().is_ok_and(|h| h == addr)
+        {
+            ServerRoot::Local(server::get_srv_dir(debug))
+        } else {
+            ServerRoot::Remote {
+                host: addr.to_string(),
+                root: server::get_srv_dir(debug),
+            }
+        }
+    }
+
+    /// Kept snapshots as [`snapshot::taken`] gives them. A remote root's manifests are copied
+    /// into `scratch` (emptied first) to be read.
+    fn snapshots(&self, scratch: &Path) -> Outcome<Vec<(u64, i64)>> {
+        match self {
+            ServerRoot::Local(root) => snapshot::taken(root),
+            ServerRoot::Remote { host, root } => {
+                if scratch.exists() {
+                    fs::remove_dir_all(scratch)?;
+                }
+                fs::create_dir_all(scratch)?;
+                let src = format!("{host}:{}/", snapshot::root(root).display());
+                rsync::fetch_filtered(&src, scratch, &snapshot::MANIFEST_FILTER)?;
+                let taken = snapshot::taken_in(scratch);
+                let _ = fs::remove_dir_all(scratch);
+                taken
+            }
+        }
+    }
+
+    /// rsync source for `path` in snapshot `generation`; `/./` makes `-R` keep the client path.
+    fn source(&self, generation: u64, path: &Path) -> PathBuf {
+        let rel = path.strip_prefix("/").unwrap_or(path);
+        let (host, root) = match self {
+            ServerRoot::Local(root) => (String::new(), root),
+            ServerRoot::Remote { host, root } => (format!("{host}:"), root),
+        };
+        let snap = snapshot::root(root).join(generation.to_string());
+        PathBuf::from(format!("{host}{}/./{}", snap.display(), rel.display()))
+    }
+}
+
+/// Like [`config::resolve`], but the file may be gone (a pull deleted it).
+fn conflict_path(path: &str) -> Outcome<PathBuf> {
+    config::resolve(path)
+        .or_else(|_| std::path::absolute(path).map_err(|e| format!("'{path}': {e}").into()))
+}
+
 pub fn log(params: &ClientParameters) -> Outcome<()> {
     let data = fs::read_to_string(&params.shared.log_path).map_err(|e| {
         format!(
@@ -363,6 +990,7 @@ pub fn log(params: &ClientParameters) -> Outcome<()> {
 
 // Daemonized call, stdin/stdout/stderr are closed
 pub fn init(params: &ClientParameters) -> Outcome<()> {
+    runtime::write_pid(&runtime_files(params).pid)?;
     let client_sync = load_client_sync_state(params)?;
     let params = Arc::new(params.clone());
     // `_srv_addr`: RSYNC destination / server address from TOML — reserved until the wire protocol
@@ -371,11 +999,24 @@ pub fn init(params: &ClientParameters) -> Outcome<()> {
 
     let (notify_tx, notify_rx): (mpsc::Sender<notify::Event>, mpsc::Receiver<notify::Event>) =
         mpsc::channel();
-    let (event_tx, event_rx): (mpsc::Sender<PathBuf>, mpsc::Receiver<PathBuf>) = mpsc::channel();
+    let (event_tx, event_rx) = mpsc::channel::<FileEvent>();
+
+    let board = {
+        let client_id = client_sync
+            .lock()
+            .map_err(|e| format!("client sync state lock: {e}"))?
+            .client_id
+            .clone();
+        Arc::new(StatusBoard::new(
+            &ensure_client_state_dir(params.as_ref())?,
+            &client_id,
+        ))
+    };
 
     let watchers: Arc<Mutex<Vec<RecommendedWatcher>>> = Arc::new(Mutex::new(Vec::new()));
     {
-        let initial = setup_watchers(&inode_map, notify_tx.clone())?;
+        let (initial, degraded) = setup_watchers(&inode_map, notify_tx.clone())?;
+        board.update(|st| st.degraded_anchors = degraded);
         *watchers
             .lock()
             .map_err(|e| format!("watchers lock poisoned: {e}"))? = initial;
@@ -384,15 +1025,41 @@ pub fn init(params: &ClientParameters) -> Outcome<()> {
     let fatal = Arc::new(AtomicBool::new(false));
     signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&fatal))?;
 
+    let (journal, replay) = PendingJournal::open(&ensure_client_state_dir(params.as_ref())?);
+    let journal = Arc::new(journal);
+    let settings = config::settings(params.as_ref())?;
+    let mut outbox = Outbox::new(settings.backoff);
+    prune_backups(params.as_ref(), &settings.retention);
+    let replay_anchors: Vec<PathBuf> = replay
+        .anchors_to_replay(inode_map.keys())
+        .into_iter()
+        .filter(|a| inode_map.contains_key(a))
+        .collect();
+    if !replay_anchors.is_empty() || !replay.dirty.is_empty() {
+        info!(
+            "client: replaying journal: {} anchor(s), {} dirty path(s)",
+            replay_anchors.len(),
+            replay.dirty.len()
+        );
+    }
+    outbox.enqueue(replay_anchors);
+
     let inodes = Arc::new(RwLock::new(inode_map));
-    let local_dirty = Arc::new(Mutex::new(HashSet::<PathBuf>::new()));
+    let local_dirty = Arc::new(Mutex::new(replay.dirty.into_iter().collect::<HashSet<_>>()));
+
+    let (control_tx, control_rx) = mpsc::channel::<ControlCommand>();
+    #[cfg(unix)]
+    let control_thread = spawn_control_socket(params.as_ref(), &fatal, &board, control_tx)?;
+    #[cfg(not(unix))]
+    drop(control_tx);
 
     let watch_thread = thread::spawn({
         let fatal = Arc::clone(&fatal);
         let inode_map = Arc::clone(&inodes);
         let local_dirty = Arc::clone(&local_dirty);
+        let journal = Arc::clone(&journal);
         // watch_thread needs a mutable map to assign "last event" to inode
-        move || watch_entry(inode_map, notify_rx, event_tx, fatal, local_dirty)
+        move || watch_entry(inode_map, notify_rx, event_tx, fatal, local_dirty, journal)
     });
 
     let zenoh_thread = thread::spawn({
@@ -413,6 +1080,10 @@ pub fn init(params: &ClientParameters) -> Outcome<()> {
                 notify_tx,
                 client_sync,
                 local_dirty,
+                outbox,
+                journal,
+                board,
+                control_rx,
             )
         }
     });
@@ -427,17 +1098,90 @@ pub fn init(params: &ClientParameters) -> Outcome<()> {
         Ok(Err(e)) => error!("{e}"),
         Err(join_err) => return bad!("client:zenoh_thread join error! >> {:?}", join_err),
     }
+    #[cfg(unix)]
+    if control_thread.join().is_err() {
+        return bad!("client:control_thread join error!");
+    }
     Ok(())
 }
 
+#[cfg(unix)]
+fn spawn_control_socket(
+    params: &ClientParameters,
+    fatal: &Arc<AtomicBool>,
+    board: &Arc<StatusBoard>,
+    control_tx: mpsc::Sender<ControlCommand>,
+) -> Outcome<thread::JoinHandle<()>> {
+    let socket = runtime_files(params).socket;
+    if let Some(dir) = socket.parent() {
+        runtime::ensure_dir(dir)?;
+    }
+    let fatal_flag = Arc::clone(fatal);
+    let board = Arc::clone(board);
+    control::serve(socket, Arc::clone(fatal), move |request, reply| {
+        route_control(request, reply, &fatal_flag, &board, &control_tx);
+    })
+}
+
+/// Runs on the control socket's connection thread: answers what it can from shared state and
+/// hands the rest to the Zenoh thread.
+fn route_control(
+    request: Request,
+    reply: Reply,
+    fatal: &AtomicBool,
+    board: &StatusBoard,
+    control_tx: &mpsc::Sender<ControlCommand>,
+) {
+    match request {
+        Request::Stop => {
+            info!("client: stop requested over control socket");
+            fatal.store(true, Ordering::Relaxed);
+            let _ = reply.send(Response::ok("client daemon stopping"));
+        }
+        Request::Status => {
+            let report = status::StatusReport {
+                alive: true,
+                status: board.snapshot(),
+            };
+            let resp = match serde_json::to_value(&report) {
+                Ok(data) => Response::ok("running").data(data),
+                Err(e) => Response::error(format!("status json: {e}")),
+            };
+            let _ = reply.send(resp);
+        }
+        Request::Changes { .. }
+        | Request::Clients
+        | Request::LabelClient { .. }
+        | Request::ForgetClient { .. } => {
+            let _ = reply.send(Response::error("only the server daemon answers that"));
+        }
+        request => {
+            if let Err(mpsc::SendError(cmd)) = control_tx.send(ControlCommand { request, reply }) {
+                let _ = cmd
+                    .reply
+                    .send(Response::error("client daemon is shutting down"));
+            }
+        }
+    }
+}
+
+/// A change the watcher saw, in `dir` under `anchor`. `due` once the anchor's interval has
+/// passed: the anchor is queued for a push, otherwise the change only widens the next one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileEvent {
+    anchor: PathBuf,
+    dir: PathBuf,
+    due: bool,
+}
+
 // This will check the event path against the known paths passed at config time
-// Only top level paths are sent to the synch thread if the watched directory has exceeded
-// interval. In other words events are filtered against intervals (per inode) and added
-// to the synch queue.
+// Every event is sent to the synch thread with its directory, but only marked due if the
+// watched directory has exceeded interval. In other words events are filtered against
+// intervals (per inode) before they queue a push.
 fn check_interval(
     event_path: &Path,
     inode_map: &Arc<RwLock<config::InodeMap>>,
-    event_tx: &mpsc::Sender<PathBuf>,
+    event_tx: &mpsc::Sender<FileEvent>,
 ) -> Outcome<()> {
     // need to dynamically lookup keys and compare path names
     debug!("checking interval, event:{}", event_path.display());
@@ -446,12 +1190,22 @@ fn check_interval(
             if event_path.starts_with(inode_path) {
                 let now = Instant::now();
                 let elapse = now.duration_since(inode.last_event);
-                if elapse >= inode.interval {
+                let due = elapse >= inode.interval;
+                if due {
                     debug!("EVENT>> elapse: {}", elapse.as_secs());
                     inode.last_event = now;
-                    if let Err(e) = event_tx.send(inode_path.clone()) {
-                        return bad!("unable to send event path to sync queue: {}", e);
-                    }
+                }
+                let dir = match event_path.parent() {
+                    Some(parent) if event_path != inode_path => parent.to_path_buf(),
+                    _ => inode_path.clone(),
+                };
+                let event = FileEvent {
+                    anchor: inode_path.clone(),
+                    dir,
+                    due,
+                };
+                if let Err(e) = event_tx.send(event) {
+                    return bad!("unable to send event path to sync queue: {}", e);
                 }
                 break;
             }
@@ -462,13 +1216,26 @@ fn check_interval(
     }
 }
 
+/// True when `event_path` falls under an anchor whose ignore patterns match it.
+fn is_ignored_event(event_path: &Path, inode_map: &Arc<RwLock<config::InodeMap>>) -> bool {
+    let Ok(inode_map) = inode_map.read() else {
+        return false;
+    };
+    inode_map.iter().any(|(inode_path, inode)| {
+        event_path
+            .strip_prefix(inode_path)
+            .is_ok_and(|rel| ignore::is_ignored(rel, &inode.excludes))
+    })
+}
+
 #[allow(clippy::needless_pass_by_value)]
 fn watch_entry(
     inode_map: Arc<RwLock<config::InodeMap>>,
     notify_rx: mpsc::Receiver<notify::Event>,
-    event_tx: mpsc::Sender<PathBuf>,
+    event_tx: mpsc::Sender<FileEvent>,
     fatal: Arc<AtomicBool>,
     local_dirty: Arc<Mutex<HashSet<PathBuf>>>,
+    journal: Arc<PendingJournal>,
 ) -> Outcome<()> {
     loop {
         if fatal.load(Ordering::Relaxed) {
@@ -489,8 +1256,12 @@ fn watch_entry(
                         notify::EventKind::Create(_) | notify::EventKind::Modify(_)
                     );
                     for path in &event.paths {
-                        if track_dirty {
-                            mark_local_dirty(local_dirty.as_ref(), path);
+                        if is_ignored_event(path, &inode_map) {
+                            debug!("client:watch_entry>> ignoring {}", path.display());
+                            continue;
+                        }
+                        if track_dirty && mark_local_dirty(local_dirty.as_ref(), path) {
+                            persist_dirty(&journal, &local_dirty);
                         }
                         check_interval(path, &inode_map, &event_tx)?;
                     }
@@ -511,16 +1282,24 @@ fn watch_entry(
 #[allow(clippy::needless_pass_by_value, clippy::too_many_arguments)]
 fn zenoh_entry(
     inode_map: Arc<RwLock<config::InodeMap>>,
-    event_rx: mpsc::Receiver<PathBuf>,
+    event_rx: mpsc::Receiver<FileEvent>,
     fatal: Arc<AtomicBool>,
     params: Arc<ClientParameters>,
     watchers: Arc<Mutex<Vec<RecommendedWatcher>>>,
     notify_tx: mpsc::Sender<Event>,
     client_sync: Arc<Mutex<ClientSyncState>>,
     local_dirty: Arc<Mutex<HashSet<PathBuf>>>,
+    mut outbox: Outbox,
+    journal: Arc<PendingJournal>,
+    board: Arc<StatusBoard>,
+    control_rx: mpsc::Receiver<ControlCommand>,
 ) -> Outcome<()> {
     let (zenoh_client, zenoh_rx, terminal_topic): (ipc::ZenohClient, ipc::Rx, String) =
-        match ipc::connect_with_terminate_topic(&[ipc::TOPIC_SERVER], ipc::TOPIC_CLIENTS) {
+        match ipc::connect_with_terminate_topic(
+            &[ipc::TOPIC_SERVER],
+            ipc::TOPIC_CLIENTS,
+            &params.shared,
+        ) {
             Ok(conn) => conn,
             Err(e) => {
                 fatal.store(true, Ordering::Relaxed);
@@ -528,15 +1307,23 @@ fn zenoh_entry(
             }
         };
 
-    // The server will send status updates to it's clients every 5 seconds
+    let mut waiters: Vec<SyncWaiter> = Vec::new();
+    // The server will send status updates to it's clients every 5 seconds; the short timeout
+    // keeps control-socket requests responsive in between.
     loop {
         if fatal.load(Ordering::Relaxed) {
+            for w in waiters.drain(..) {
+                let _ = w.reply.send(Response::error(
+                    "client daemon stopped before the server ack",
+                ));
+            }
+            ipc::announce_exit(&zenoh_client, &params.shared);
             zenoh_client.disconnect();
             info!("client:zenoh_entry>> aborting");
             return Ok(());
         }
 
-        match zenoh_rx.recv_timeout(Duration::from_secs(1)) {
+        match zenoh_rx.recv_timeout(Duration::from_millis(250)) {
             Ok(message) => {
                 if let Err(e) = handle_incoming_transport_message(
                     message,
@@ -550,9 +1337,13 @@ fn zenoh_entry(
                     &notify_tx,
                     &client_sync,
                     local_dirty.as_ref(),
+                    &mut outbox,
+                    &board,
                 ) {
                     error!("client:zenoh_entry>> process: {e}");
+                    board.record_error(&e.to_string());
                 }
+                persist_pending(&journal, &outbox, &local_dirty);
             }
             Err(e) => match e {
                 RecvTimeoutError::Disconnected => {
@@ -564,7 +1355,108 @@ fn zenoh_entry(
                 }
             },
         }
+        while let Ok(cmd) = control_rx.try_recv() {
+            if let Some(waiter) = handle_control(
+                cmd,
+                params.as_ref(),
+                &inode_map,
+                &watchers,
+                &notify_tx,
+                &client_sync,
+                &mut outbox,
+                &board,
+            ) {
+                waiters.push(waiter);
+            }
+        }
+        answer_sync_waiters(&mut waiters, &client_sync);
+        refresh_status(&board, &client_sync, &outbox, &local_dirty);
+    }
+}
+
+/// Answers a forwarded control request, or returns the `sync --wait` caller to park.
+#[allow(clippy::too_many_arguments)]
+fn handle_control(
+    cmd: ControlCommand,
+    params: &ClientParameters,
+    inode_map: &Arc<RwLock<config::InodeMap>>,
+    watchers: &Arc<Mutex<Vec<RecommendedWatcher>>>,
+    notify_tx: &mpsc::Sender<Event>,
+    client_sync: &Mutex<ClientSyncState>,
+    outbox: &mut Outbox,
+    board: &StatusBoard,
+) -> Option<SyncWaiter> {
+    let ControlCommand { request, reply } = cmd;
+    let resp = match request {
+        Request::Reload => {
+            match apply_client_config_reload(params, inode_map, watchers, notify_tx, outbox, board)
+            {
+                Ok(()) => Response::ok("configuration reloaded"),
+                Err(e) => Response::error(format!("reload failed: {e}")),
+            }
+        }
+        Request::Sync {
+            paths,
+            wait,
+            timeout_secs,
+        } => match queue_sync(&paths, inode_map, outbox) {
+            Ok(anchors) if wait => {
+                let first_push_id = client_sync.lock().map_or(0, |s| s.next_push_id);
+                return Some(SyncWaiter {
+                    reply,
+                    remaining: anchors.clone(),
+                    anchors,
+                    first_push_id,
+                    head_generation: 0,
+                    deadline: Instant::now() + Duration::from_secs(timeout_secs),
+                });
+            }
+            Ok(anchors) => Response::ok(format!("sync queued for {} anchor(s)", anchors.len()))
+                .data(serde_json::json!({ "anchors": anchors })),
+            Err(e) => Response::error(e.to_string()),
+        },
+        Request::Pause { paths, resume } => pause_anchors(&paths, resume, inode_map, outbox),
+        Request::Stop
+        | Request::Status
+        | Request::Changes { .. }
+        | Request::Clients
+        | Request::LabelClient { .. }
+        | Request::ForgetClient { .. } => Response::error("request handled by the socket thread"),
+    };
+    let _ = reply.send(resp);
+    None
+}
+
+fn answer_sync_waiters(waiters: &mut Vec<SyncWaiter>, client_sync: &Mutex<ClientSyncState>) {
+    let applied = client_sync
+        .lock()
+        .map(|mut s| std::mem::take(&mut s.applied_pushes))
+        .unwrap_or_default();
+    for w in waiters.iter_mut() {
+        for push in applied.iter().filter(|p| p.push_id >= w.first_push_id) {
+            w.remaining
+                .retain(|a| !push.anchors.iter().any(|p| a.starts_with(p)));
+            w.head_generation = w.head_generation.max(push.head_generation);
+        }
     }
+    let now = Instant::now();
+    waiters.retain(|w| {
+        if w.remaining.is_empty() {
+            let head = w.head_generation;
+            let _ = w.reply.send(
+                Response::ok(format!("server applied push at generation {head}"))
+                    .data(serde_json::json!({ "anchors": w.anchors, "head_generation": head })),
+            );
+            return false;
+        }
+        if now >= w.deadline {
+            let _ = w.reply.send(Response::error(
+                "sync: timed out waiting for the server to apply our push",
+            ));
+            return false;
+        }
+        true
+    });
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -572,7 +1464,7 @@ fn handle_incoming_transport_message(
     message: Option<ipc::ZenohMessage>,
     terminal_topic: &str,
     fatal: &Arc<AtomicBool>,
-    event_rx: &mpsc::Receiver<PathBuf>,
+    event_rx: &mpsc::Receiver<FileEvent>,
     zenoh_client: &ipc::ZenohClient,
     inode_map: &Arc<RwLock<config::InodeMap>>,
     params: &ClientParameters,
@@ -580,6 +1472,8 @@ fn handle_incoming_transport_message(
     notify_tx: &mpsc::Sender<Event>,
     client_sync: &Arc<Mutex<ClientSyncState>>,
     local_dirty: &Mutex<HashSet<PathBuf>>,
+    outbox: &mut Outbox,
+    board: &StatusBoard,
 ) -> Outcome<()> {
     let Some(msg) = message else {
         return bad!("client:zenoh_entry>> empty message?");
@@ -592,82 +1486,123 @@ fn handle_incoming_transport_message(
     }
 
     if msg.topic == ipc::TOPIC_CONTROL_RELOAD {
-        return apply_client_config_reload(params, inode_map, watchers, notify_tx);
+        return apply_client_config_reload(params, inode_map, watchers, notify_tx, outbox, board);
     }
 
     // process Zenoh traffic from server
     debug!("client>> 👍 recv: {}", msg.payload);
+    board.update(|st| {
+        st.server_head_generation = Some(msg.payload.head_generation);
+        st.server_status = Some(format!("{:?}", msg.payload.status));
+    });
     process(
         event_rx,
         zenoh_client,
         inode_map,
         client_sync,
         local_dirty,
+        outbox,
         params,
+        board,
         &msg.payload,
     )
 }
 
-#[allow(clippy::too_many_lines)]
+#[allow(clippy::too_many_lines, clippy::too_many_arguments)]
 fn process(
-    event_rx: &mpsc::Receiver<PathBuf>,
+    event_rx: &mpsc::Receiver<FileEvent>,
     zenoh_client: &ipc::ZenohClient,
     inode_map: &Arc<RwLock<config::InodeMap>>,
     client_sync: &Arc<Mutex<ClientSyncState>>,
     local_dirty: &Mutex<HashSet<PathBuf>>,
+    outbox: &mut Outbox,
     params: &ClientParameters,
+    board: &StatusBoard,
     server_msg: &ipc::Payload,
 ) -> Outcome<()> {
-    maybe_record_writer_ack(client_sync.as_ref(), server_msg, local_dirty)?;
+    if server_msg.dest_path == Path::new(ipc::CHANGES_DEST) {
+        return pull_changes(
+            zenoh_client,
+            inode_map,
+            client_sync,
+            local_dirty,
+            outbox,
+            params,
+            board,
+            server_msg,
+        );
+    }
+    if maybe_record_writer_ack(client_sync.as_ref(), server_msg, local_dirty)? {
+        outbox.on_applied(&server_msg.src_paths);
+        board.update(|st| st.refused = None);
+    }
 
     match server_msg.status {
         ipc::Status::NotReady(reason) => match reason {
             ipc::Reason::Busy => {
-                info!("client:process>> server busy... wait 5 secs");
-                std::thread::sleep(Duration::from_secs(5)); // should be config driven
+                // keep listening; the retry fires from a later Ready once the backoff elapses
+                queue_file_events(event_rx, outbox)?;
+                if !addressed_to_us(client_sync.as_ref(), server_msg) {
+                    debug!("client:process>> another client's anchors are busy");
+                    return Ok(());
+                }
+                if let Some(delay) = outbox.on_busy(Instant::now()) {
+                    info!(
+                        "client:process>> server busy; retrying {} anchor(s) in {}ms",
+                        outbox.pending_len(),
+                        delay.as_millis()
+                    );
+                } else {
+                    debug!("client:process>> server busy");
+                }
                 Ok(())
             }
             ipc::Reason::Behind => {
-                debug!("client:process>> Behind synch up");
-                // let's sync up
-                if let Ok(map_read) = inode_map.read() {
-                    let head = server_msg.head_generation;
-                    let src_paths = map_read.keys().cloned().collect();
-                    let mut payload = ipc::Payload::new()?
-                        .status(ipc::Status::NotReady(ipc::Reason::Behind))
-                        .src_paths(src_paths);
-                    let has_dirty = local_dirty.lock().is_ok_and(|d| !d.is_empty());
-                    let state_dir = client_state_dir(params);
-                    let backup_run = if has_dirty {
-                        let dir = conflict::next_behind_backup_dir(&state_dir)?;
-                        warn!(
-                            "client: behind pull with local edits pending; rsync backups will use {}",
-                            dir.display()
-                        );
-                        Some(dir)
-                    } else {
-                        None
-                    };
-                    pull(&payload, backup_run.as_deref())?;
-                    if let Some(ref dir) = backup_run {
-                        info!(
-                            "client: behind pull finished; pre-replace copies (if any) are under {} (head_generation={})",
-                            dir.display(),
-                            head
-                        );
-                    }
-                    attach_client_outbound_basis(&mut payload, client_sync.as_ref())?;
-                    zenoh_client.publish(&mut payload)?;
-                    record_pull_acked(client_sync.as_ref(), head)?;
-                    if let Ok(mut dirty) = local_dirty.lock() {
-                        dirty.clear();
-                    }
-                    Ok(())
+                debug!("client:process>> Behind; asking what changed");
+                request_changes(zenoh_client, client_sync, server_msg.head_generation)
+            }
+
+            ipc::Reason::Unauthorized | ipc::Reason::QuotaExceeded => {
+                if !addressed_to_us(client_sync.as_ref(), server_msg) {
+                    return Ok(());
+                }
+                let why = if reason == ipc::Reason::Unauthorized {
+                    "not authorized; see the server's audit log"
                 } else {
-                    bad!("unable to acquire inode_map read lock")
+                    "over quota on the server"
+                };
+                // keep the anchors: a grant or freed space on the server lets the retry through
+                let retry = outbox
+                    .on_busy(Instant::now())
+                    .map(|delay| format!("; retrying in {}s", delay.as_secs()))
+                    .unwrap_or_default();
+                let msg = format!("push refused: {why}{retry}");
+                error!("client:process>> {msg}");
+                board.record_error(&msg);
+                board.update(|st| st.refused = Some(why.to_string()));
+                Ok(())
+            }
+            ipc::Reason::Failed => {
+                if !addressed_to_us(client_sync.as_ref(), server_msg) {
+                    return Ok(());
                 }
+                let Some(delay) = outbox.on_failed(&server_msg.src_paths, Instant::now()) else {
+                    debug!(
+                        "client:process>> push {} failed but was already requeued",
+                        server_msg.push_id
+                    );
+                    return Ok(());
+                };
+                let msg = format!(
+                    "server failed to apply push {}: {}; retrying in {}s",
+                    server_msg.push_id,
+                    server_msg.error,
+                    delay.as_secs()
+                );
+                error!("client:process>> {msg}");
+                board.record_error(&msg);
+                Ok(())
             }
-
             ipc::Reason::Other => {
                 warn!("client:process>> unhandled NotReady(Other); no action");
                 Ok(())
@@ -675,36 +1610,45 @@ fn process(
         },
         ipc::Status::Ready => {
             debug!("client:process>> ipc::Status::Ready");
-            match filter_file_events(event_rx) {
-                Ok(filtered_paths) => {
-                    if filtered_paths.is_empty() {
-                        debug!("client:process>> nothing to send");
+            match queue_file_events(event_rx, outbox) {
+                Ok(()) => {
+                    let Some(due_paths) = outbox.take_due(Instant::now()) else {
+                        if !outbox.is_empty() {
+                            debug!(
+                                "client:process>> {} anchor(s) pending, next attempt in {}ms",
+                                outbox.pending_len(),
+                                outbox.retry_in(Instant::now()).as_millis()
+                            );
+                        }
+                        return Ok(());
+                    };
+                    let grouped_paths = if let Ok(map_read) = inode_map.read() {
+                        let mut grouped: HashMap<config::ResolvedRsyncConfig, Vec<PathBuf>> =
+                            HashMap::new();
+                        for path in due_paths {
+                            let rsync_cfg = map_read
+                                .get(&path)
+                                .map_or_else(config::ResolvedRsyncConfig::default, |inode| {
+                                    inode.rsync.clone()
+                                });
+                            grouped.entry(rsync_cfg).or_default().push(path);
+                        }
+                        grouped
                     } else {
-                        let grouped_paths = if let Ok(map_read) = inode_map.read() {
-                            let mut grouped: HashMap<config::ResolvedRsyncConfig, Vec<PathBuf>> =
-                                HashMap::new();
-                            for path in filtered_paths {
-                                let rsync_cfg = map_read
-                                    .get(&path)
-                                    .map_or_else(config::ResolvedRsyncConfig::default, |inode| {
-                                        inode.rsync.clone()
-                                    });
-                                grouped.entry(rsync_cfg).or_default().push(path);
-                            }
-                            grouped
+                        return bad!("unable to acquire inode_map read lock");
+                    };
+
+                    for (rsync_cfg, paths) in grouped_paths {
+                        let scope = existing_sources(&paths, outbox.sources(&paths));
+                        let mut payload = ipc::Payload::new()?
+                            .src_paths(paths)
+                            .scope(scope)
+                            .rsync(rsync_cfg);
+                        attach_client_outbound_basis(&mut payload, client_sync.as_ref())?;
+                        if let Err(e) = zenoh_client.publish(&mut payload) {
+                            error!("unable to publish {e}");
                         } else {
-                            return bad!("unable to acquire inode_map read lock");
-                        };
-
-                        for (rsync_cfg, paths) in grouped_paths {
-                            let mut payload =
-                                ipc::Payload::new()?.src_paths(paths).rsync(rsync_cfg);
-                            attach_client_outbound_basis(&mut payload, client_sync.as_ref())?;
-                            if let Err(e) = zenoh_client.publish(&mut payload) {
-                                error!("unable to publish {e}");
-                            } else {
-                                info!("published payload: {payload}");
-                            }
+                            info!("published payload: {payload}");
                         }
                     }
                     Ok(())
@@ -717,14 +1661,298 @@ fn process(
     }
 }
 
+/// Asks the server what changed between our `acked_generation` and `head`; the answer comes
+/// back as a [`ipc::CHANGES_DEST`] message handled by [`pull_changes`].
+fn request_changes(
+    zenoh_client: &ipc::ZenohClient,
+    client_sync: &Mutex<ClientSyncState>,
+    head: u64,
+) -> Outcome<()> {
+    let mut query = {
+        let mut s = client_sync
+            .lock()
+            .map_err(|e| format!("client sync state lock: {e}"))?;
+        if let Some((asked, at)) = s.changes_query {
+            if asked >= head && at.elapsed() < CHANGES_QUERY_TIMEOUT {
+                debug!("client:process>> changes up to {asked} already requested");
+                return Ok(());
+            }
+        }
+        s.changes_query = Some((head, Instant::now()));
+        ipc::Payload::new()?
+            .dest_path(ipc::CHANGES_DEST)
+            .status(ipc::Status::NotReady(ipc::Reason::Behind))
+            .client_id(s.client_id.clone())
+            .basis_generation(s.acked_generation)
+            .head_generation(head)
+    };
+    zenoh_client.publish(&mut query)
+}
+
+/// Catches up from the server's answer to [`request_changes`]: applies deletions, pulls the
+/// changed files under our anchors (every anchor when the server could not tell), then pushes
+/// our side again on top of the new head. A file changed both here and on the server keeps our
+/// version as a conflict copy beside it, which that push then shares.
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
+fn pull_changes(
+    zenoh_client: &ipc::ZenohClient,
+    inode_map: &Arc<RwLock<config::InodeMap>>,
+    client_sync: &Arc<Mutex<ClientSyncState>>,
+    local_dirty: &Mutex<HashSet<PathBuf>>,
+    outbox: &mut Outbox,
+    params: &ClientParameters,
+    board: &StatusBoard,
+    answer: &ipc::Payload,
+) -> Outcome<()> {
+    {
+        let mut s = client_sync
+            .lock()
+            .map_err(|e| format!("client sync state lock: {e}"))?;
+        if answer.client_id != s.client_id {
+            return Ok(());
+        }
+        s.changes_query = None;
+        if answer.basis_generation != s.acked_generation {
+            debug!(
+                "client:process>> stale changes answer (since {}, acked {})",
+                answer.basis_generation, s.acked_generation
+            );
+            return Ok(());
+        }
+    }
+    let head = answer.head_generation;
+    let anchors: Vec<PathBuf> = inode_map
+        .read()
+        .map_err(|e| format!("inode_map read lock poisoned: {e}"))?
+        .keys()
+        .cloned()
+        .collect();
+
+    let dirty = local_dirty.lock().map(|d| d.clone()).unwrap_or_default();
+    let pull_info = conflict::PullInfo {
+        head_generation: head,
+        acked_generation: answer.basis_generation,
+        dirty: {
+            let mut paths: Vec<PathBuf> = dirty.iter().cloned().collect();
+            paths.sort();
+            paths
+        },
+    };
+    // conflict copies when the server says what changed, a backup run when it cannot
+    let (src_paths, copies) = if let Some(changes) = &answer.changes {
+        let mine = changes.within(&anchors);
+        let conflicts = conflict::detect(&mine.changed, &dirty);
+        info!(
+            "client: behind {}..{head}: pulling {} changed file(s), {} deletion(s), {} conflict(s)",
+            answer.basis_generation,
+            mine.changed.len(),
+            mine.deleted.len(),
+            conflicts.len()
+        );
+        apply_remote_deletions(&mine.deleted, local_dirty);
+        let copies = conflict::keep_local_copies(&conflicts, &config::get_hostname()?)?;
+        (mine.changed.into_iter().collect::<Vec<_>>(), copies)
+    } else if answer.src_paths.is_empty() {
+        info!(
+            "client: behind {}..{head}: server has no change record; pulling every anchor",
+            answer.basis_generation
+        );
+        (anchors.clone(), Vec::new())
+    } else {
+        let readable = readable_anchors(&anchors, &answer.src_paths);
+        info!(
+            "client: behind {}..{head}: server has no change record; pulling {} readable path(s)",
+            answer.basis_generation,
+            readable.len()
+        );
+        (readable, Vec::new())
+    };
+
+    if !src_paths.is_empty() {
+        let full_pull = answer.changes.is_none();
+        let pull_payload = ipc::Payload::new()?
+            .status(ipc::Status::NotReady(ipc::Reason::Behind))
+            .src_paths(src_paths);
+        let state_dir = client_state_dir(params);
+        let backup_run = if full_pull && !pull_info.dirty.is_empty() {
+            let dir = conflict::begin_backup_run(&state_dir, &pull_info)?;
+            warn!(
+                "client: behind pull with local edits pending; rsync backups will use {}",
+                dir.display()
+            );
+            Some(dir)
+        } else {
+            None
+        };
+        board.update(|st| {
+            st.rsync = Some(format!(
+                "pulling {} path(s) from {} (head_generation={head})",
+                pull_payload.src_paths.len(),
+                pull_payload.hostname
+            ));
+        });
+        if let Err(e) = board.flush() {
+            warn!("client: unable to write status snapshot: {e}");
+        }
+        let pulled = pull(&pull_payload, backup_run.as_deref());
+        board.update(|st| st.rsync = None);
+        pulled?;
+        if let Some(ref dir) = backup_run {
+            conflict::finish_backup_run(dir)?;
+            info!(
+                "client: behind pull finished; pre-replace copies (if any) are under {} (head_generation={})",
+                dir.display(),
+                head
+            );
+        }
+    }
+    let copies = conflict::settle(copies);
+    for (original, copy) in &copies {
+        warn!(
+            "client: '{}' changed here and on the server; our version is kept as '{}'",
+            original.display(),
+            copy.display()
+        );
+    }
+    if !copies.is_empty() {
+        let run = conflict::record_copies(&client_state_dir(params), &pull_info, &copies)?;
+        info!(
+            "client: {} conflict(s) recorded in {}; see `sinkd client conflicts ls`",
+            copies.len(),
+            run.display()
+        );
+    }
+    match config::settings(params) {
+        Ok(settings) => prune_backups(params, &settings.retention),
+        Err(e) => warn!("client: backup retention skipped: {e}"),
+    }
+
+    let mut payload = ipc::Payload::new()?
+        .status(ipc::Status::NotReady(ipc::Reason::Behind))
+        .src_paths(anchors);
+    attach_client_outbound_basis(&mut payload, client_sync.as_ref())?;
+    // paused anchors are pulled like any other but their pushes are held back
+    payload.src_paths.retain(|p| !outbox.is_paused(p));
+    if !payload.src_paths.is_empty() {
+        zenoh_client.publish(&mut payload)?;
+        outbox.mark_all_in_flight(Instant::now());
+    }
+    record_pull_acked(client_sync.as_ref(), head)?;
+    if let Ok(mut dirty) = local_dirty.lock() {
+        dirty.clear();
+    }
+    Ok(())
+}
+
+/// Applies the `behind_backups` retention policy, logging what it removed.
+fn prune_backups(params: &ClientParameters, retention: &conflict::RetentionConfig) {
+    match conflict::prune(
+        &client_state_dir(params),
+        retention,
+        std::time::SystemTime::now(),
+        false,
+    ) {
+        Ok(pruned) => {
+            for p in pruned {
+                info!(
+                    "client: pruned backup run '{}' ({} bytes): {}",
+                    p.dir.display(),
+                    p.bytes,
+                    p.reason
+                );
+            }
+        }
+        Err(e) => warn!("client: backup retention: {e}"),
+    }
+}
+
+/// Removes what the server deleted, except files we changed locally (our push restores them).
+fn apply_remote_deletions(deleted: &[PathBuf], local_dirty: &Mutex<HashSet<PathBuf>>) {
+    let dirty = local_dirty.lock().map(|d| d.clone()).unwrap_or_default();
+    // contents come before their directory, so emptied directories can go too
+    for path in deleted {
+        if dirty.contains(path) {
+            warn!(
+                "client: keeping locally edited '{}' deleted on the server",
+                path.display()
+            );
+            continue;
+        }
+        let removed = match fs::symlink_metadata(path) {
+            Ok(meta) if meta.is_dir() => fs::remove_dir(path),
+            Ok(_) => fs::remove_file(path),
+            Err(_) => continue,
+        };
+        if let Err(e) = removed {
+            debug!("client: not removing '{}': {e}", path.display());
+        }
+    }
+}
+
+/// `sinkd client sync`: queue the anchors covering `paths` and drop any running backoff.
+fn queue_sync(
+    paths: &[PathBuf],
+    inode_map: &Arc<RwLock<config::InodeMap>>,
+    outbox: &mut Outbox,
+) -> Outcome<Vec<PathBuf>> {
+    let anchors = {
+        let map = inode_map
+            .read()
+            .map_err(|e| format!("inode_map read lock poisoned: {e}"))?;
+        anchors_for_paths(map.keys(), paths)
+    };
+    if anchors.is_empty() {
+        return bad!("sync: no configured anchor covers the given path(s)");
+    }
+    if let Some(paused) = anchors.iter().find(|a| outbox.is_paused(a)) {
+        return bad!("sync: '{}' is paused; resume it first", paused.display());
+    }
+    info!("client: sync requested for {} anchor(s)", anchors.len());
+    outbox.enqueue(anchors.clone());
+    outbox.expedite();
+    Ok(anchors)
+}
+
+fn pause_anchors(
+    paths: &[PathBuf],
+    resume: bool,
+    inode_map: &Arc<RwLock<config::InodeMap>>,
+    outbox: &mut Outbox,
+) -> Response {
+    let anchors = match inode_map.read() {
+        Ok(map) => anchors_for_paths(map.keys(), paths),
+        Err(e) => return Response::error(format!("inode_map read lock poisoned: {e}")),
+    };
+    if anchors.is_empty() {
+        return Response::error("no configured anchor covers the given path(s)");
+    }
+    let verb = if resume { "resumed" } else { "paused" };
+    info!("client: {verb} {} anchor(s)", anchors.len());
+    let listed = anchors
+        .iter()
+        .map(|a| a.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if resume {
+        outbox.resume(&anchors);
+    } else {
+        outbox.pause(anchors);
+    }
+    Response::ok(format!("{verb}: {listed}"))
+}
+
 fn apply_client_config_reload(
     params: &ClientParameters,
     inode_map: &Arc<RwLock<config::InodeMap>>,
     watchers: &Arc<Mutex<Vec<RecommendedWatcher>>>,
     notify_tx: &mpsc::Sender<Event>,
+    outbox: &mut Outbox,
+    board: &StatusBoard,
 ) -> Outcome<()> {
     let (_srv_addr, new_map) = config::get(params)?;
-    let new_watchers = setup_watchers(&new_map, notify_tx.clone())?;
+    outbox.reconfigure(config::settings(params)?.backoff);
+    let (new_watchers, degraded) = setup_watchers(&new_map, notify_tx.clone())?;
+    board.update(|st| st.degraded_anchors = degraded);
     {
         let mut im = inode_map
             .write()
@@ -742,11 +1970,13 @@ fn apply_client_config_reload(
 }
 
 #[allow(clippy::needless_pass_by_value)]
+/// Returns the watchers plus the anchors that could not be watched (reported as degraded).
 fn setup_watchers(
     inode_map: &config::InodeMap,
     tx: mpsc::Sender<Event>,
-) -> Outcome<Vec<RecommendedWatcher>> {
+) -> Outcome<(Vec<RecommendedWatcher>, Vec<PathBuf>)> {
     let mut watchers: Vec<RecommendedWatcher> = Vec::new();
+    let mut degraded: Vec<PathBuf> = Vec::new();
 
     for pathbuf in inode_map.keys() {
         // Clone tx for use in this iteration
@@ -769,6 +1999,7 @@ fn setup_watchers(
 
         if watcher.watch(pathbuf, RecursiveMode::Recursive).is_err() {
             warn!("unable to set watcher for: '{}'", pathbuf.display());
+            degraded.push(pathbuf.clone());
         } else {
             info!("set watcher for: '{}'", pathbuf.display());
             watchers.push(watcher);
@@ -778,7 +2009,7 @@ fn setup_watchers(
     if watchers.is_empty() {
         bad!("nothing to watch! aborting")
     } else {
-        Ok(watchers)
+        Ok((watchers, degraded))
     }
 }
 
@@ -786,14 +2017,15 @@ fn setup_watchers(
 // Using a HashSet to filter out redundancies will return
 // sanitized list of paths ready to send to sinkd server
 // TODO: need to account for serveral users
-fn filter_file_events(event_rx: &mpsc::Receiver<PathBuf>) -> Outcome<Vec<PathBuf>> {
-    let mut path_set = HashSet::<PathBuf>::new();
+fn queue_file_events(event_rx: &mpsc::Receiver<FileEvent>, outbox: &mut Outbox) -> Outcome<()> {
+    let mut due = BTreeSet::new();
     loop {
         match event_rx.try_recv() {
-            // only top level paths are passed
-            Ok(path) => {
-                debug!("Adding file to path_set");
-                path_set.insert(path);
+            Ok(event) => {
+                outbox.touch(&event.anchor, event.dir);
+                if event.due {
+                    due.insert(event.anchor);
+                }
             }
             Err(err) => match err {
                 mpsc::TryRecvError::Disconnected => return bad!("event_rx disconnected"),
@@ -801,8 +2033,27 @@ fn filter_file_events(event_rx: &mpsc::Receiver<PathBuf>) -> Outcome<Vec<PathBuf
             },
         }
     }
+    outbox.enqueue_changed(due);
+    Ok(())
+}
 
-    Ok(path_set.into_iter().collect())
+/// `sources` with each path that is gone (deleted since it changed) replaced by its nearest
+/// remaining parent inside its anchor, and those under another dropped.
+fn existing_sources(anchors: &[PathBuf], sources: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut out = BTreeSet::new();
+    for mut source in sources {
+        while !source.exists() && !anchors.contains(&source) {
+            match source.parent() {
+                Some(parent) => source = parent.to_path_buf(),
+                None => break,
+            }
+        }
+        out.insert(source);
+    }
+    out.iter()
+        .filter(|p| !out.iter().any(|o| o != *p && p.starts_with(o)))
+        .cloned()
+        .collect()
 }
 
 #[allow(dead_code)]
@@ -849,35 +2100,166 @@ fn pull(payload: &ipc::Payload, backup_dir: Option<&Path>) -> Outcome<()> {
 
 #[cfg(test)]
 mod tests {
-    use std::{path::PathBuf, sync::mpsc};
+    use std::{
+        path::PathBuf,
+        sync::{mpsc, Mutex},
+        time::{Duration, Instant},
+    };
 
-    use super::filter_file_events;
+    use super::{
+        anchors_for_paths, answer_sync_waiters, existing_sources, queue_file_events,
+        readable_anchors, AppliedPush, ClientSyncState, FileEvent, SyncWaiter,
+    };
+    use crate::{backoff::BackoffConfig, outbox::Outbox};
+
+    fn outbox() -> Outbox {
+        Outbox::new(BackoffConfig {
+            base_ms: 1000,
+            ceiling_secs: 8,
+        })
+    }
 
     #[test]
-    fn filter_file_events_deduplicates_paths() {
+    fn queue_file_events_queues_due_anchors_with_their_changed_dirs() {
         let (tx, rx) = mpsc::channel();
-        let path = PathBuf::from("/tmp/a");
-        tx.send(path.clone()).expect("send should succeed");
-        tx.send(path.clone()).expect("send should succeed");
-        tx.send(PathBuf::from("/tmp/b"))
-            .expect("send should succeed");
+        let event = |anchor: &str, dir: &str, due: bool| FileEvent {
+            anchor: PathBuf::from(anchor),
+            dir: PathBuf::from(dir),
+            due,
+        };
+        tx.send(event("/tmp/a", "/tmp/a/x", true)).expect("send");
+        tx.send(event("/tmp/a", "/tmp/a/x/y", false)).expect("send");
+        tx.send(event("/tmp/a", "/tmp/a/z", false)).expect("send");
+        // inside the interval: widens b's next push but does not queue one
+        tx.send(event("/tmp/b", "/tmp/b/w", false)).expect("send");
+
+        let mut ob = outbox();
+        queue_file_events(&rx, &mut ob).expect("queue should succeed");
+        let due = ob.take_due(Instant::now()).expect("a is due");
+        assert_eq!(due, vec![PathBuf::from("/tmp/a")]);
+        assert_eq!(
+            ob.sources(&due),
+            vec![PathBuf::from("/tmp/a/x"), PathBuf::from("/tmp/a/z")]
+        );
+    }
+
+    #[test]
+    fn existing_sources_falls_back_to_a_remaining_parent() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let anchor = tmp.path().join("docs");
+        std::fs::create_dir_all(anchor.join("kept")).expect("mkdir");
+        let sources = vec![
+            anchor.join("kept"),
+            anchor.join("gone/deeper"),
+            anchor.join("kept/gone"),
+        ];
+        assert_eq!(
+            existing_sources(std::slice::from_ref(&anchor), sources),
+            vec![anchor.clone()]
+        );
+        assert_eq!(
+            existing_sources(
+                std::slice::from_ref(&anchor),
+                vec![anchor.join("kept/gone")]
+            ),
+            vec![anchor.join("kept")]
+        );
+    }
 
-        let mut filtered = filter_file_events(&rx).expect("filter should succeed");
-        filtered.sort();
+    #[test]
+    fn anchors_for_paths_maps_files_to_anchor_and_parents_to_children() {
+        let anchors = [
+            PathBuf::from("/home/u/docs"),
+            PathBuf::from("/home/u/music"),
+            PathBuf::from("/srv/share"),
+        ];
+        assert_eq!(anchors_for_paths(anchors.iter(), &[]).len(), 3);
+        assert_eq!(
+            anchors_for_paths(anchors.iter(), &[PathBuf::from("/home/u/docs/a.txt")]),
+            vec![PathBuf::from("/home/u/docs")]
+        );
+        assert_eq!(
+            anchors_for_paths(anchors.iter(), &[PathBuf::from("/home/u")]),
+            vec![
+                PathBuf::from("/home/u/docs"),
+                PathBuf::from("/home/u/music")
+            ]
+        );
+        assert!(anchors_for_paths(anchors.iter(), &[PathBuf::from("/etc")]).is_empty());
+    }
 
-        assert_eq!(filtered.len(), 2);
-        assert_eq!(filtered[0], PathBuf::from("/tmp/a"));
-        assert_eq!(filtered[1], PathBuf::from("/tmp/b"));
+    #[test]
+    fn full_pull_is_limited_to_readable_roots() {
+        let anchors = [
+            PathBuf::from("/home/u"),
+            PathBuf::from("/srv/share/team"),
+            PathBuf::from("/opt/private"),
+        ];
+        let roots = [PathBuf::from("/srv/share"), PathBuf::from("/home/u/public")];
+        assert_eq!(
+            readable_anchors(&anchors, &roots),
+            vec![
+                PathBuf::from("/home/u/public"),
+                PathBuf::from("/srv/share/team")
+            ]
+        );
     }
 
     #[test]
-    fn filter_file_events_returns_error_when_channel_disconnected() {
-        let (tx, rx) = mpsc::channel::<PathBuf>();
+    fn queue_file_events_returns_error_when_channel_disconnected() {
+        let (tx, rx) = mpsc::channel::<FileEvent>();
         drop(tx);
 
-        let err = filter_file_events(&rx).expect_err("disconnect should return an error");
+        let err =
+            queue_file_events(&rx, &mut outbox()).expect_err("disconnect should return an error");
         assert_eq!(err.to_string(), "event_rx disconnected");
     }
+
+    #[test]
+    fn sync_waiter_ignores_acks_for_other_pushes() {
+        let (docs, music) = (PathBuf::from("/w/docs"), PathBuf::from("/w/music"));
+        let sync = Mutex::new(ClientSyncState {
+            client_id: "our-id".to_string(),
+            acked_generation: 0,
+            ack_path: PathBuf::new(),
+            applied_pushes: Vec::new(),
+            changes_query: None,
+            next_push_id: 10,
+        });
+        let (tx, rx) = mpsc::channel();
+        let mut waiters = vec![SyncWaiter {
+            reply: tx,
+            anchors: vec![docs.clone(), music.clone()],
+            first_push_id: 10,
+            remaining: vec![docs.clone(), music.clone()],
+            head_generation: 0,
+            deadline: Instant::now() + Duration::from_secs(30),
+        }];
+        let ack = |push_id, anchors: Vec<PathBuf>, head_generation| {
+            sync.lock().expect("lock").applied_pushes.push(AppliedPush {
+                push_id,
+                anchors,
+                head_generation,
+            });
+        };
+
+        // sent before the request, even though it covers both anchors
+        ack(9, vec![docs.clone(), music.clone()], 4);
+        // a later push of some other anchor
+        ack(10, vec![PathBuf::from("/w/other")], 5);
+        answer_sync_waiters(&mut waiters, &sync);
+        assert_eq!(waiters.len(), 1);
+
+        ack(11, vec![docs], 6);
+        answer_sync_waiters(&mut waiters, &sync);
+        assert_eq!(waiters.len(), 1, "music not applied yet");
+        ack(12, vec![music], 7);
+        answer_sync_waiters(&mut waiters, &sync);
+        assert!(waiters.is_empty());
+        let resp = rx.try_recv().expect("answered");
+        assert!(resp.ok, "{}", resp.message);
+        assert_eq!(resp.data.expect("data")["head_generation"], 7);
+    }
 }
 
 #[cfg(test)]
@@ -889,7 +2271,9 @@ mod conflict_resolution_tests {
 
     use crate::ipc;
 
-    use super::{mark_local_dirty, maybe_record_writer_ack, ClientSyncState};
+    use super::{
+        apply_remote_deletions, mark_local_dirty, maybe_record_writer_ack, ClientSyncState,
+    };
 
     fn sample_payload(last_writer: &str, head_generation: u64) -> ipc::Payload {
         ipc::Payload::from(
@@ -904,6 +2288,7 @@ mod conflict_resolution_tests {
             last_writer.to_string(),
             ipc::Status::Ready,
             None,
+            None,
         )
     }
 
@@ -929,12 +2314,48 @@ mod conflict_resolution_tests {
             client_id: "our-id".to_string(),
             acked_generation: 5,
             ack_path,
+            applied_pushes: Vec::new(),
+            changes_query: None,
+            next_push_id: 1,
         });
         let dirty = Mutex::new(HashSet::from([PathBuf::from("/nope/unrelated")]));
-        let msg = sample_payload("our-id", 7);
+        let msg = sample_payload("our-id", 6);
         maybe_record_writer_ack(&sync, &msg, &dirty).expect("ack");
         assert!(dirty.lock().expect("lock").is_empty());
-        assert_eq!(sync.lock().expect("lock").acked_generation, 7);
+        assert_eq!(sync.lock().expect("lock").acked_generation, 6);
+        assert_eq!(
+            sync.lock().expect("lock").applied_pushes[0].head_generation,
+            6
+        );
+    }
+
+    #[test]
+    fn writer_ack_past_a_foreign_generation_keeps_acked_behind() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let ack_path = tmp.path().join("acked_generation");
+        fs::write(&ack_path, "5").expect("seed ack");
+        let sync = Mutex::new(ClientSyncState {
+            client_id: "our-id".to_string(),
+            acked_generation: 5,
+            ack_path,
+            applied_pushes: Vec::new(),
+            changes_query: None,
+            next_push_id: 1,
+        });
+        let dirty = Mutex::new(HashSet::from([PathBuf::from("/nope/unrelated")]));
+        // generation 6 was another client's disjoint push
+        let msg = sample_payload("our-id", 7);
+        assert!(maybe_record_writer_ack(&sync, &msg, &dirty).expect("ack"));
+        assert_eq!(
+            dirty.lock().expect("lock").len(),
+            1,
+            "dirty until acked moves"
+        );
+        assert_eq!(sync.lock().expect("lock").acked_generation, 5);
+        assert_eq!(
+            sync.lock().expect("lock").applied_pushes[0].head_generation,
+            7
+        );
     }
 
     #[test]
@@ -946,6 +2367,9 @@ mod conflict_resolution_tests {
             client_id: "our-id".to_string(),
             acked_generation: 5,
             ack_path,
+            applied_pushes: Vec::new(),
+            changes_query: None,
+            next_push_id: 1,
         });
         let marker = PathBuf::from("/tmp/marker");
         let dirty = Mutex::new(HashSet::from([marker.clone()]));
@@ -964,6 +2388,9 @@ mod conflict_resolution_tests {
             client_id: "our-id".to_string(),
             acked_generation: 5,
             ack_path,
+            applied_pushes: Vec::new(),
+            changes_query: None,
+            next_push_id: 1,
         });
         let marker = PathBuf::from("/tmp/marker2");
         let dirty = Mutex::new(HashSet::from([marker.clone()]));
@@ -972,4 +2399,23 @@ mod conflict_resolution_tests {
         assert_eq!(sync.lock().expect("lock").acked_generation, 5);
         assert!(dirty.lock().expect("lock").contains(&marker));
     }
+
+    #[test]
+    fn remote_deletions_spare_locally_edited_files() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let root = fs::canonicalize(tmp.path()).expect("canonicalize");
+        let gone_dir = root.join("old");
+        fs::create_dir(&gone_dir).expect("mkdir");
+        let gone = gone_dir.join("a.txt");
+        let kept = root.join("b.txt");
+        fs::write(&gone, b"a").expect("write");
+        fs::write(&kept, b"b").expect("write");
+        let dirty = Mutex::new(HashSet::from([kept.clone()]));
+
+        apply_remote_deletions(&[gone.clone(), gone_dir.clone(), kept.clone()], &dirty);
+
+        assert!(!gone.exists());
+        assert!(!gone_dir.exists());
+        assert!(kept.exists());
+    }
 }
@@ -3,8 +3,10 @@
 //!   [`crate::client`] via [`crate::parameters::ClientParameters`]. `server_addr` in the
 //!   system file is the sync target/description for clients (see also client-side `_srv_addr` note in
 //!   [`crate::client::init`]).
-//! - **Server** — runtime sync root under `/srv/sinkd` (or debug path) and `generation_state.toml` there; the
-//!   server does **not** load client TOML anchor lists for queue/dedup logic.
+//! - **Server** — its own TOML (`/etc/sinkd-server.conf` or `--srv-cfg`, see [`ServerConfig`]) for
+//!   apply tuning, snapshots, client authorization and quotas, plus the runtime sync root
+//!   under `/srv/sinkd` (or debug path) and `generation_state.toml` there; the server does
+//!   **not** load client TOML anchor lists for queue/dedup logic.
 
 use serde::{Deserialize, Serialize};
 use std::{
@@ -24,8 +26,12 @@ macro_rules! reject_unsupported_rsync_fields {
     };
 }
 
-use crate::{outcome::Outcome, parameters::ClientParameters};
-use log::{error, warn};
+use crate::{
+    apply::ApplyConfig, auth::AuthConfig, backoff::BackoffConfig, conflict::RetentionConfig,
+    ignore, outcome::Outcome, parameters::ClientParameters, snapshot::SnapshotConfig,
+    usage::QuotaConfig,
+};
+use log::{error, info, warn};
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -40,6 +46,9 @@ pub struct ResolvedRsyncConfig {
     pub ignore_existing: bool,
     pub size_only: bool,
     pub stats: bool,
+    /// Anchor ignore patterns (built-in defaults plus `excludes`), passed as `--exclude`.
+    #[serde(default)]
+    pub excludes: Vec<String>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -115,6 +124,7 @@ impl RsyncConfig {
             ignore_existing: self.ignore_existing.unwrap_or(base.ignore_existing),
             size_only: self.size_only.unwrap_or(base.size_only),
             stats: self.stats.unwrap_or(base.stats),
+            excludes: base.excludes.clone(),
         }
     }
 }
@@ -125,6 +135,8 @@ pub(crate) struct Anchor {
     pub(crate) path: PathBuf,
     interval: Option<u64>,
     excludes: Option<Vec<String>>,
+    /// `false` turns off the built-in ignore list ([`crate::ignore::DEFAULT_IGNORES`]).
+    default_ignores: Option<bool>,
     rsync: Option<RsyncConfig>,
     rsync_checksum: Option<bool>,
     rsync_compress: Option<bool>,
@@ -144,6 +156,7 @@ impl Anchor {
             path,
             interval: None,
             excludes: None,
+            default_ignores: None,
             rsync: None,
             rsync_checksum: None,
             rsync_compress: None,
@@ -158,6 +171,13 @@ impl Anchor {
         }
     }
 
+    fn ignore_patterns(&self) -> Vec<String> {
+        ignore::resolve(
+            self.default_ignores.unwrap_or(true),
+            self.excludes.as_deref().unwrap_or_default(),
+        )
+    }
+
     fn rsync_override(&self) -> RsyncConfig {
         let mut cfg = self.rsync.clone().unwrap_or_default();
         if self.rsync_checksum.is_some() {
@@ -200,6 +220,10 @@ pub(crate) struct SysConfig {
     pub(crate) users: Vec<String>,
     pub(crate) anchors: Option<Vec<Anchor>>,
     pub(crate) rsync: Option<RsyncConfig>,
+    /// Retry schedule when the server answers `Busy`.
+    pub(crate) backoff: Option<BackoffConfig>,
+    /// Retention for the client's `behind_backups` runs.
+    pub(crate) behind_backups: Option<RetentionConfig>,
 }
 
 pub(crate) fn load_system_config_file(path: &Path) -> Outcome<SysConfig> {
@@ -237,6 +261,8 @@ impl SysConfig {
             users: Vec::new(),
             anchors: Some(Vec::new()),
             rsync: None,
+            backoff: None,
+            behind_backups: None,
         }
     }
 }
@@ -398,10 +424,11 @@ pub fn get(client: &ClientParameters) -> Outcome<(String, InodeMap)> {
             |override_cfg| override_cfg.merge_over(&sys_rsync),
         );
         for anchor in &cfg.anchors {
-            // let excludes = anchor.excludes.is_some().or()
-            let resolved_rsync = anchor.rsync_override().merge_over(&user_rsync);
+            let excludes = anchor.ignore_patterns();
+            let mut resolved_rsync = anchor.rsync_override().merge_over(&user_rsync);
+            resolved_rsync.excludes.clone_from(&excludes);
             inode_map.entry(anchor.path.clone()).or_insert(Inode {
-                excludes: anchor.excludes.clone().unwrap_or(vec![]),
+                excludes,
                 interval: Duration::from_secs(anchor.interval.unwrap_or(5)),
                 last_event: Instant::now(),
                 event: false,
@@ -412,9 +439,11 @@ pub fn get(client: &ClientParameters) -> Outcome<(String, InodeMap)> {
 
     if let Some(anchors) = &parser.sys.anchors {
         for anchor in anchors {
-            let resolved_rsync = anchor.rsync_override().merge_over(&sys_rsync);
+            let excludes = anchor.ignore_patterns();
+            let mut resolved_rsync = anchor.rsync_override().merge_over(&sys_rsync);
+            resolved_rsync.excludes.clone_from(&excludes);
             inode_map.entry(anchor.path.clone()).or_insert(Inode {
-                excludes: anchor.excludes.clone().unwrap_or(vec![]),
+                excludes,
                 interval: Duration::from_secs(anchor.interval.unwrap_or(5)),
                 last_event: Instant::now(),
                 event: false,
@@ -425,6 +454,43 @@ pub fn get(client: &ClientParameters) -> Outcome<(String, InodeMap)> {
     Ok((parser.sys.server_addr, inode_map))
 }
 
+/// Client daemon tuning that lives in the system config next to the anchors.
+#[derive(Debug, Clone, Default)]
+pub struct ClientSettings {
+    pub backoff: BackoffConfig,
+    pub retention: RetentionConfig,
+}
+
+pub fn settings(client: &ClientParameters) -> Outcome<ClientSettings> {
+    let sys = load_system_config_file(client.system_config.as_ref().as_path())?;
+    Ok(ClientSettings {
+        backoff: sys.backoff.unwrap_or_default(),
+        retention: sys.behind_backups.unwrap_or_default(),
+    })
+}
+
+/// The server's own TOML (`/etc/sinkd-server.conf` or `--srv-cfg`); every table is optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub apply: ApplyConfig,
+    pub snapshots: SnapshotConfig,
+    pub auth: AuthConfig,
+    pub quotas: QuotaConfig,
+}
+
+/// Reads the server config; a missing file means built-in defaults.
+pub fn load_server_config(path: &Path) -> Outcome<ServerConfig> {
+    if !path.exists() {
+        info!("no server config at {}; using defaults", path.display());
+        return Ok(ServerConfig::default());
+    }
+    let raw = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read server config {}: {e}", path.display()))?;
+    Ok(toml::from_str(&raw)
+        .map_err(|e| format!("cannot parse server config {}: {e}", path.display()))?)
+}
+
 #[must_use]
 pub fn have_permissions() -> bool {
     #[cfg(unix)]
@@ -618,4 +684,31 @@ mod tests {
         assert_eq!(cfg.compress, Some(true));
         assert_eq!(cfg.max_size.as_deref(), Some("10m"));
     }
+
+    #[test]
+    fn anchor_ignore_patterns_extend_defaults_with_excludes() {
+        let anchor: Anchor = toml::from_str(
+            r#"
+            path = "/tmp/a"
+            excludes = ["*.pyc"]
+            "#,
+        )
+        .expect("anchor should parse");
+        let patterns = anchor.ignore_patterns();
+        assert!(patterns.iter().any(|p| p == ".DS_Store"));
+        assert_eq!(patterns.last().map(String::as_str), Some("*.pyc"));
+    }
+
+    #[test]
+    fn anchor_can_disable_default_ignores() {
+        let anchor: Anchor = toml::from_str(
+            r#"
+            path = "/tmp/a"
+            default_ignores = false
+            excludes = ["*.pyc"]
+            "#,
+        )
+        .expect("anchor should parse");
+        assert_eq!(anchor.ignore_patterns(), vec!["*.pyc".to_string()]);
+    }
 }
@@ -1,15 +1,124 @@
-//! Client-side backup directories for behind-pull (`behind_backups/N`).
+//! Client-side safety nets for behind-pull: conflict copies next to files edited on both
+//! sides, and backup directories (`behind_backups/N`) for pulls that cannot tell what changed.
+//!
+//! Every pull that saved something gets a numbered run with a `run.toml` manifest. A run's
+//! conflicts are the files rsync backed up into it (stored under their absolute path, rsync
+//! `-R` style) plus the conflict copies it lists. Once the last one is resolved, the run moves
+//! to `behind_backups/archive/N`. Run numbers are never reused, so `N` names one run for good.
+//!
+//! Runs are pruned by a [`RetentionConfig`] (count, age, total size), oldest first; a run that
+//! still holds unresolved conflicts is never pruned.
 
 use std::{
+    collections::HashSet,
     ffi::OsStr,
+    fmt::Write as _,
     fs,
+    hash::BuildHasher,
     io::ErrorKind,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
-use crate::outcome::Outcome;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{durable, outcome::Outcome, time};
 
 const BEHIND_BACKUPS: &str = "behind_backups";
+const RUN_MANIFEST: &str = "run.toml";
+const ARCHIVE: &str = "archive";
+/// Next run id to hand out.
+const NEXT_RUN: &str = "next_run";
+
+/// `[behind_backups]` table in the system config. `0` turns a limit off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Keep at most this many runs (open and archived together).
+    pub keep: usize,
+    /// Prune runs older than this.
+    pub max_age_days: u64,
+    /// Prune the oldest runs while all of them together take more than this.
+    pub max_total_mb: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            keep: 20,
+            max_age_days: 30,
+            max_total_mb: 1024,
+        }
+    }
+}
+
+/// A run [`prune`] removes (or would remove, on a dry run), and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pruned {
+    pub dir: PathBuf,
+    pub bytes: u64,
+    pub reason: &'static str,
+}
+
+/// What a Behind pull was doing when it had to save files aside.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PullInfo {
+    /// Server head the pull brought us to.
+    pub head_generation: u64,
+    /// Our `acked_generation` before the pull.
+    pub acked_generation: u64,
+    /// Local edits the server had not acked yet.
+    pub dirty: Vec<PathBuf>,
+}
+
+/// `run.toml`: why a run exists and what it holds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunManifest {
+    pub created_at: String,
+    pub head_generation: u64,
+    pub acked_generation: u64,
+    /// Dirty paths that made the pull save files aside.
+    pub dirty: Vec<PathBuf>,
+    /// Our versions kept beside the originals as `(conflict from …)` copies.
+    pub copies: Vec<ConflictCopy>,
+    /// Everything the run saved (backed-up files or conflict copies), by original path.
+    pub files: Vec<SavedFile>,
+    /// Originals the user already resolved.
+    pub resolved: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictCopy {
+    pub path: PathBuf,
+    pub copy: PathBuf,
+}
+
+/// One unresolved file: the pulled version at `original`, ours at `saved`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictFile {
+    pub original: PathBuf,
+    pub saved: PathBuf,
+    /// `saved` is a conflict copy beside `original` rather than a file inside the run.
+    pub beside: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub id: u64,
+    pub dir: PathBuf,
+    pub manifest: RunManifest,
+    pub files: Vec<ConflictFile>,
+}
 
 fn is_decimal_dir(name: &OsStr) -> Option<u64> {
     let s = name.to_str()?;
@@ -19,45 +128,54 @@ fn is_decimal_dir(name: &OsStr) -> Option<u64> {
     s.parse().ok()
 }
 
-/// Returns absolute `…/behind_backups/N` with smallest unused `N` (from 0).
-pub fn next_behind_backup_dir(client_state_dir: &Path) -> Outcome<PathBuf> {
-    let behind = client_state_dir.join(BEHIND_BACKUPS);
-    fs::create_dir_all(&behind)
-        .map_err(|e| format!("behind_backups '{}': {e}", behind.display()))?;
-
-    let mut used = std::collections::BTreeSet::<u64>::new();
-    for entry in
-        fs::read_dir(&behind).map_err(|e| format!("read_dir '{}': {e}", behind.display()))?
-    {
-        let entry = entry.map_err(|e| format!("read_dir entry '{}': {e}", behind.display()))?;
+/// Ids of the run directories under `dir`.
+fn run_ids(dir: &Path) -> Outcome<Vec<u64>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return bad!("read_dir '{}': {e}", dir.display()),
+    };
+    let mut ids = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("read_dir entry '{}': {e}", dir.display()))?;
         let file_type = entry
             .file_type()
             .map_err(|e| format!("file_type '{}': {e}", entry.path().display()))?;
-        if !file_type.is_dir() {
-            continue;
-        }
-        if let Some(n) = is_decimal_dir(&entry.file_name()) {
-            used.insert(n);
+        if let Some(n) = is_decimal_dir(&entry.file_name()).filter(|_| file_type.is_dir()) {
+            ids.push(n);
         }
     }
+    Ok(ids)
+}
 
-    let mut n: u64 = 0;
-    while used.contains(&n) {
-        n = n.saturating_add(1);
-    }
+/// Returns absolute `…/behind_backups/N` for a new run. Ids only ever grow: `N` is past every
+/// open and archived run and past the last id handed out (kept in `next_run`), so archiving or
+/// pruning a run never frees its id for reuse.
+pub fn next_behind_backup_dir(client_state_dir: &Path) -> Outcome<PathBuf> {
+    let behind = client_state_dir.join(BEHIND_BACKUPS);
+    fs::create_dir_all(&behind)
+        .map_err(|e| format!("behind_backups '{}': {e}", behind.display()))?;
+
+    let counter = behind.join(NEXT_RUN);
+    let recorded = fs::read_to_string(&counter)
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(0);
+    let mut n = run_ids(&behind)?
+        .into_iter()
+        .chain(run_ids(&behind.join(ARCHIVE))?)
+        .map(|id| id.saturating_add(1))
+        .fold(recorded, u64::max);
 
     loop {
         let run = behind.join(n.to_string());
         match fs::create_dir(&run) {
-            Ok(()) => return Ok(run),
-            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
-                // Another Behind handler may have created this slot between scan and mkdir.
-                used.insert(n);
-                n = n.saturating_add(1);
-                while used.contains(&n) {
-                    n = n.saturating_add(1);
-                }
+            Ok(()) => {
+                durable::write_atomic(&counter, n.saturating_add(1).to_string().as_bytes())?;
+                return Ok(run);
             }
+            // another Behind handler may have created this slot between scan and mkdir
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => n = n.saturating_add(1),
             Err(e) => {
                 return bad!("create backup run '{}': {e}", run.display());
             }
@@ -65,6 +183,477 @@ pub fn next_behind_backup_dir(client_state_dir: &Path) -> Outcome<PathBuf> {
     }
 }
 
+/// Files we edited since our acked generation (`dirty`) that the server changed as well.
+#[must_use]
+pub fn detect<'a, I, S>(remote_changed: I, dirty: &HashSet<PathBuf, S>) -> Vec<PathBuf>
+where
+    I: IntoIterator<Item = &'a PathBuf>,
+    S: BuildHasher,
+{
+    remote_changed
+        .into_iter()
+        .filter(|p| dirty.contains(*p))
+        .cloned()
+        .collect()
+}
+
+/// `dir/name (conflict from <host> <date>).ext` beside `path`.
+#[must_use]
+pub fn conflict_copy_path(path: &Path, host: &str, date: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!(
+            "{stem} (conflict from {host} {date}).{}",
+            ext.to_string_lossy()
+        ),
+        None => format!("{stem} (conflict from {host} {date})"),
+    };
+    path.with_file_name(name)
+}
+
+/// Copies our version of each conflicting file aside before the pull replaces it.
+/// Returns `(original, copy)` pairs; files already gone locally are skipped.
+pub fn keep_local_copies(conflicts: &[PathBuf], host: &str) -> Outcome<Vec<(PathBuf, PathBuf)>> {
+    let date = time::stamp(Some("%Y-%m-%d %H%M%S"));
+    let mut kept = Vec::new();
+    for original in conflicts {
+        if !original.is_file() {
+            continue;
+        }
+        let mut copy = conflict_copy_path(original, host, &date);
+        let mut n = 2;
+        while copy.exists() {
+            copy = conflict_copy_path(original, host, &format!("{date} {n}"));
+            n += 1;
+        }
+        fs::copy(original, &copy).map_err(|e| {
+            format!(
+                "keep conflicting '{}' as '{}': {e}",
+                original.display(),
+                copy.display()
+            )
+        })?;
+        kept.push((original.clone(), copy));
+    }
+    Ok(kept)
+}
+
+/// After the pull: drops copies identical to what the server sent (both sides made the same
+/// edit) and returns the real conflicts.
+#[must_use]
+pub fn settle(copies: Vec<(PathBuf, PathBuf)>) -> Vec<(PathBuf, PathBuf)> {
+    copies
+        .into_iter()
+        .filter(|(original, copy)| {
+            let same = matches!((fs::read(original), fs::read(copy)), (Ok(a), Ok(b)) if a == b);
+            if !same {
+                return true;
+            }
+            debug!("conflict: '{}' converged, no copy kept", original.display());
+            if let Err(e) = fs::remove_file(copy) {
+                warn!("conflict: unable to remove '{}': {e}", copy.display());
+            }
+            false
+        })
+        .collect()
+}
+
+/// Starts a run for a pull that may back files up into it (rsync `--backup-dir`); call
+/// [`finish_backup_run`] once the pull is done.
+pub fn begin_backup_run(client_state_dir: &Path, pull: &PullInfo) -> Outcome<PathBuf> {
+    let dir = next_behind_backup_dir(client_state_dir)?;
+    write_manifest(&dir, &new_manifest(pull))?;
+    Ok(dir)
+}
+
+/// Lists what rsync backed up into `run` in its manifest; returns how many files that is.
+pub fn finish_backup_run(run: &Path) -> Outcome<usize> {
+    let mut manifest = read_manifest(run);
+    let mut backed_up = Vec::new();
+    collect_files(run, run, &mut backed_up)?;
+    manifest.files = backed_up
+        .iter()
+        .map(|rel| saved_file(Path::new("/").join(rel), &run.join(rel)))
+        .collect::<Outcome<_>>()?;
+    write_manifest(run, &manifest)?;
+    Ok(manifest.files.len())
+}
+
+/// Records conflict copies left by a pull as a run of their own.
+pub fn record_copies(
+    client_state_dir: &Path,
+    pull: &PullInfo,
+    copies: &[(PathBuf, PathBuf)],
+) -> Outcome<PathBuf> {
+    let dir = next_behind_backup_dir(client_state_dir)?;
+    let manifest = RunManifest {
+        copies: copies
+            .iter()
+            .map(|(path, copy)| ConflictCopy {
+                path: path.clone(),
+                copy: copy.clone(),
+            })
+            .collect(),
+        files: copies
+            .iter()
+            .map(|(path, copy)| saved_file(path.clone(), copy))
+            .collect::<Outcome<_>>()?,
+        ..new_manifest(pull)
+    };
+    write_manifest(&dir, &manifest)?;
+    Ok(dir)
+}
+
+fn new_manifest(pull: &PullInfo) -> RunManifest {
+    RunManifest {
+        created_at: time::stamp(Some("%F %T")),
+        head_generation: pull.head_generation,
+        acked_generation: pull.acked_generation,
+        dirty: pull.dirty.clone(),
+        ..RunManifest::default()
+    }
+}
+
+fn saved_file(path: PathBuf, saved: &Path) -> Outcome<SavedFile> {
+    let mut file = fs::File::open(saved).map_err(|e| format!("hash '{}': {e}", saved.display()))?;
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut file, &mut hasher)
+        .map_err(|e| format!("hash '{}': {e}", saved.display()))?;
+    let sha256 = hasher
+        .finalize()
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        });
+    Ok(SavedFile { path, size, sha256 })
+}
+
+fn write_manifest(run: &Path, manifest: &RunManifest) -> Outcome<()> {
+    let serialized =
+        toml::to_string(manifest).map_err(|e| format!("serialize run manifest: {e}"))?;
+    durable::write_atomic(&run.join(RUN_MANIFEST), serialized.as_bytes())
+}
+
+fn read_manifest(run: &Path) -> RunManifest {
+    let path = run.join(RUN_MANIFEST);
+    let Ok(raw) = fs::read_to_string(&path) else {
+        return RunManifest::default();
+    };
+    toml::from_str(&raw).unwrap_or_else(|e| {
+        warn!("conflict: unreadable manifest '{}': {e}", path.display());
+        RunManifest::default()
+    })
+}
+
+/// Open runs (not archived), oldest first, with their unresolved files.
+pub fn runs(client_state_dir: &Path) -> Outcome<Vec<Run>> {
+    let behind = client_state_dir.join(BEHIND_BACKUPS);
+    let Ok(entries) = fs::read_dir(&behind) else {
+        return Ok(Vec::new());
+    };
+    let mut runs = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("read_dir entry '{}': {e}", behind.display()))?;
+        let Some(id) = is_decimal_dir(&entry.file_name()) else {
+            continue;
+        };
+        if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        runs.push(load_run(id, entry.path())?);
+    }
+    runs.sort_by_key(|r| r.id);
+    Ok(runs)
+}
+
+fn load_run(id: u64, dir: PathBuf) -> Outcome<Run> {
+    let manifest = read_manifest(&dir);
+    let mut files = saved_files(&dir, &manifest)?;
+    files.retain(|f| !manifest.resolved.contains(&f.original));
+    Ok(Run {
+        id,
+        dir,
+        manifest,
+        files,
+    })
+}
+
+/// Everything `dir` still holds a saved version of, resolved or not, sorted by original path.
+fn saved_files(dir: &Path, manifest: &RunManifest) -> Outcome<Vec<ConflictFile>> {
+    let mut files = Vec::new();
+    let mut backed_up = Vec::new();
+    collect_files(dir, dir, &mut backed_up)?;
+    for rel in backed_up {
+        files.push(ConflictFile {
+            original: Path::new("/").join(&rel),
+            saved: dir.join(&rel),
+            beside: false,
+        });
+    }
+    for c in &manifest.copies {
+        if c.copy.exists() {
+            files.push(ConflictFile {
+                original: c.path.clone(),
+                saved: c.copy.clone(),
+                beside: true,
+            });
+        }
+    }
+    files.sort_by(|a, b| a.original.cmp(&b.original));
+    Ok(files)
+}
+
+/// Files run `id` (open or archived) can put back, limited to those at or under `paths`
+/// (every file when empty).
+pub fn restore_plan(
+    client_state_dir: &Path,
+    id: u64,
+    paths: &[PathBuf],
+) -> Outcome<Vec<ConflictFile>> {
+    let behind = client_state_dir.join(BEHIND_BACKUPS);
+    // ids are unique, so a run is open or archived, never both (unless the tree predates that)
+    let found: Vec<PathBuf> = [
+        behind.join(id.to_string()),
+        behind.join(ARCHIVE).join(id.to_string()),
+    ]
+    .into_iter()
+    .filter(|d| d.is_dir())
+    .collect();
+    let dir = match found.as_slice() {
+        [dir] => dir.clone(),
+        [] => return bad!("no backup run {} under '{}'", id, behind.display()),
+        _ => {
+            return bad!(
+                "backup run {} is both open and archived ('{}', '{}'); restore from one by hand",
+                id,
+                found[0].display(),
+                found[1].display()
+            )
+        }
+    };
+    let mut files = saved_files(&dir, &read_manifest(&dir))?;
+    if !paths.is_empty() {
+        files.retain(|f| paths.iter().any(|p| f.original.starts_with(p)));
+    }
+    if files.is_empty() {
+        return bad!(
+            "backup run {} holds nothing to restore for the given path(s)",
+            id
+        );
+    }
+    Ok(files)
+}
+
+/// Copies each saved version back to its original location (the run keeps its copy).
+pub fn restore(files: &[ConflictFile]) -> Outcome<()> {
+    for file in files {
+        if let Some(parent) = file.original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&file.saved, &file.original).map_err(|e| {
+            format!(
+                "restore '{}' from '{}': {e}",
+                file.original.display(),
+                file.saved.display()
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Paths of the files under `dir`, relative to `root` (the manifest itself excluded).
+fn collect_files(root: &Path, dir: &Path, out: &mut Vec<PathBuf>) -> Outcome<()> {
+    for entry in fs::read_dir(dir).map_err(|e| format!("read_dir '{}': {e}", dir.display()))? {
+        let entry = entry.map_err(|e| format!("read_dir entry '{}': {e}", dir.display()))?;
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            collect_files(root, &path, out)?;
+        } else if let Ok(rel) = path.strip_prefix(root) {
+            if rel != Path::new(RUN_MANIFEST) {
+                out.push(rel.to_path_buf());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The newest open run (or run `id`) holding an unresolved conflict for `path`.
+pub fn find(client_state_dir: &Path, id: Option<u64>, path: &Path) -> Outcome<(Run, ConflictFile)> {
+    let found = runs(client_state_dir)?
+        .into_iter()
+        .rev()
+        .filter(|r| id.is_none_or(|id| r.id == id))
+        .find_map(|r| {
+            let file = r.files.iter().find(|f| f.original == path).cloned()?;
+            Some((r, file))
+        });
+    match (found, id) {
+        (Some(hit), _) => Ok(hit),
+        (None, Some(id)) => bad!(
+            "run {id} has no unresolved conflict for '{}'",
+            path.display()
+        ),
+        (None, None) => bad!("no unresolved conflict for '{}'", path.display()),
+    }
+}
+
+/// Settles `file` from `run`: `keep_local` puts our saved version back in place, otherwise the
+/// pulled version stays. Returns the archive path when that was the run's last conflict.
+pub fn resolve(run: &Run, file: &ConflictFile, keep_local: bool) -> Outcome<Option<PathBuf>> {
+    if keep_local {
+        if let Some(parent) = file.original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let restored = if file.beside {
+            fs::rename(&file.saved, &file.original)
+        } else {
+            fs::copy(&file.saved, &file.original).map(|_| ())
+        };
+        restored.map_err(|e| {
+            format!(
+                "restore '{}' from '{}': {e}",
+                file.original.display(),
+                file.saved.display()
+            )
+        })?;
+    } else if file.beside {
+        fs::remove_file(&file.saved)
+            .map_err(|e| format!("remove '{}': {e}", file.saved.display()))?;
+    }
+
+    let mut manifest = run.manifest.clone();
+    if !manifest.resolved.contains(&file.original) {
+        manifest.resolved.push(file.original.clone());
+    }
+    write_manifest(&run.dir, &manifest)?;
+    if run
+        .files
+        .iter()
+        .any(|f| !manifest.resolved.contains(&f.original))
+    {
+        return Ok(None);
+    }
+    let archive = run
+        .dir
+        .parent()
+        .map(|behind| behind.join(ARCHIVE))
+        .ok_or_else(|| format!("run '{}' has no parent", run.dir.display()))?;
+    fs::create_dir_all(&archive)?;
+    let dest = archive.join(run.id.to_string());
+    if dest.exists() {
+        return bad!(
+            "archive run {}: '{}' already exists",
+            run.id,
+            dest.display()
+        );
+    }
+    fs::rename(&run.dir, &dest).map_err(|e| format!("archive run '{}': {e}", run.dir.display()))?;
+    Ok(Some(dest))
+}
+
+struct Candidate {
+    created: SystemTime,
+    dir: PathBuf,
+    bytes: u64,
+    /// Holds unresolved conflicts.
+    protected: bool,
+}
+
+/// Applies `cfg` to the open and archived runs; with `dry_run`, only reports.
+pub fn prune(
+    client_state_dir: &Path,
+    cfg: &RetentionConfig,
+    now: SystemTime,
+    dry_run: bool,
+) -> Outcome<Vec<Pruned>> {
+    let mut all: Vec<Candidate> = Vec::new();
+    for run in runs(client_state_dir)? {
+        all.push(Candidate {
+            created: run_created(&run.dir, &run.manifest),
+            bytes: dir_size(&run.dir),
+            protected: !run.files.is_empty(),
+            dir: run.dir,
+        });
+    }
+    let archive = client_state_dir.join(BEHIND_BACKUPS).join(ARCHIVE);
+    if let Ok(entries) = fs::read_dir(archive) {
+        for dir in entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()) {
+            all.push(Candidate {
+                created: run_created(&dir, &read_manifest(&dir)),
+                bytes: dir_size(&dir),
+                protected: false,
+                dir,
+            });
+        }
+    }
+    all.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.dir.cmp(&b.dir)));
+
+    let mut pruned = Vec::new();
+    let mut drop_oldest = |all: &mut Vec<Candidate>, reason: &'static str| -> bool {
+        let Some(i) = all.iter().position(|c| !c.protected) else {
+            return false;
+        };
+        let c = all.remove(i);
+        pruned.push(Pruned {
+            dir: c.dir,
+            bytes: c.bytes,
+            reason,
+        });
+        true
+    };
+
+    if cfg.max_age_days > 0 {
+        let max_age = Duration::from_secs(cfg.max_age_days.saturating_mul(24 * 3600));
+        let too_old = |c: &Candidate| now.duration_since(c.created).is_ok_and(|age| age > max_age);
+        // sorted oldest first: the next unprotected run is the oldest one left
+        while all.iter().find(|c| !c.protected).is_some_and(too_old) {
+            drop_oldest(&mut all, "older than max_age_days");
+        }
+    }
+    if cfg.keep > 0 {
+        while all.len() > cfg.keep && drop_oldest(&mut all, "more than keep runs") {}
+    }
+    if cfg.max_total_mb > 0 {
+        let limit = cfg.max_total_mb.saturating_mul(1024 * 1024);
+        while all.iter().map(|c| c.bytes).sum::<u64>() > limit
+            && drop_oldest(&mut all, "over max_total_mb")
+        {}
+    }
+
+    if !dry_run {
+        for p in &pruned {
+            fs::remove_dir_all(&p.dir).map_err(|e| format!("prune '{}': {e}", p.dir.display()))?;
+        }
+    }
+    Ok(pruned)
+}
+
+/// `created_at` from the manifest, else the directory's mtime.
+fn run_created(dir: &Path, manifest: &RunManifest) -> SystemTime {
+    chrono::NaiveDateTime::parse_from_str(&manifest.created_at, "%F %T")
+        .ok()
+        .and_then(|t| t.and_local_timezone(chrono::Local).single())
+        .map(SystemTime::from)
+        .or_else(|| fs::metadata(dir).and_then(|m| m.modified()).ok())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let mut files = Vec::new();
+    if collect_files(dir, dir, &mut files).is_err() {
+        return 0;
+    }
+    files
+        .iter()
+        .filter_map(|rel| fs::symlink_metadata(dir.join(rel)).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,12 +667,22 @@ mod tests {
     }
 
     #[test]
-    fn next_behind_backup_dir_skips_used() {
+    fn next_behind_backup_dir_never_reuses_an_id() {
         let tmp = tempfile::tempdir().expect("tempdir");
         fs::create_dir_all(tmp.path().join("behind_backups/0")).expect("mkdir");
         fs::create_dir_all(tmp.path().join("behind_backups/2")).expect("mkdir");
         let p = next_behind_backup_dir(tmp.path()).expect("next");
-        assert!(p.ends_with("behind_backups/1") || p.ends_with("behind_backups\\1"));
+        assert!(p.ends_with("behind_backups/3") || p.ends_with("behind_backups\\3"));
+
+        // archived and pruned runs keep their ids
+        fs::create_dir_all(tmp.path().join("behind_backups/archive")).expect("mkdir");
+        fs::rename(&p, tmp.path().join("behind_backups/archive/3")).expect("archive");
+        let p = next_behind_backup_dir(tmp.path()).expect("next");
+        assert!(p.ends_with("behind_backups/4") || p.ends_with("behind_backups\\4"));
+        fs::remove_dir_all(tmp.path().join("behind_backups/archive")).expect("prune");
+        fs::remove_dir(&p).expect("prune");
+        let p = next_behind_backup_dir(tmp.path()).expect("next");
+        assert!(p.ends_with("behind_backups/5") || p.ends_with("behind_backups\\5"));
     }
 
     #[test]
@@ -94,12 +693,222 @@ mod tests {
         assert!(p.ends_with("behind_backups/0") || p.ends_with("behind_backups\\0"));
     }
 
-    /// Directory name `01` is treated as integer `1` (same as Rust `str::parse`), so `0` stays free.
+    /// Directory name `01` is treated as integer `1` (same as Rust `str::parse`).
     #[test]
     fn next_behind_backup_dir_leading_zero_name_occupies_numeric_slot_one() {
         let tmp = tempfile::tempdir().expect("tempdir");
         fs::create_dir_all(tmp.path().join("behind_backups/01")).expect("mkdir");
         let p = next_behind_backup_dir(tmp.path()).expect("next");
-        assert!(p.ends_with("behind_backups/0") || p.ends_with("behind_backups\\0"));
+        assert!(p.ends_with("behind_backups/2") || p.ends_with("behind_backups\\2"));
+    }
+
+    #[test]
+    fn conflict_copy_path_keeps_extension_last() {
+        let host = "laptop";
+        let date = "2026-10-19 101500";
+        assert_eq!(
+            conflict_copy_path(Path::new("/w/notes.txt"), host, date),
+            PathBuf::from("/w/notes (conflict from laptop 2026-10-19 101500).txt")
+        );
+        assert_eq!(
+            conflict_copy_path(Path::new("/w/Makefile"), host, date),
+            PathBuf::from("/w/Makefile (conflict from laptop 2026-10-19 101500)")
+        );
+    }
+
+    #[test]
+    fn only_files_changed_on_both_sides_conflict_and_identical_copies_are_dropped() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let both = tmp.path().join("both.txt");
+        let same = tmp.path().join("same.txt");
+        let remote_only = tmp.path().join("remote.txt");
+        for p in [&both, &same, &remote_only] {
+            fs::write(p, b"local").expect("write");
+        }
+        let dirty = HashSet::from([both.clone(), same.clone(), tmp.path().join("local.txt")]);
+        let remote = [both.clone(), same.clone(), remote_only];
+
+        let conflicts = detect(&remote, &dirty);
+        assert_eq!(conflicts, vec![both.clone(), same.clone()]);
+
+        let copies = keep_local_copies(&conflicts, "laptop").expect("copies");
+        assert_eq!(copies.len(), 2);
+        // the pull: the server has a different version of `both`, the same one of `same`
+        fs::write(&both, b"remote").expect("write");
+
+        let left = settle(copies);
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].0, both);
+        assert_eq!(fs::read(&left[0].1).expect("copy"), b"local");
+        let files = fs::read_dir(tmp.path()).expect("ls").count();
+        assert_eq!(files, 4, "both, same, remote and one conflict copy");
+    }
+
+    #[test]
+    fn resolving_last_conflict_archives_the_run() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let state = tmp.path().join("state");
+        let work = tmp.path().join("work");
+        fs::create_dir_all(&work).expect("mkdir");
+
+        // a backup run: rsync -R left our old version of work/a.txt inside it
+        let pull = |head| PullInfo {
+            head_generation: head,
+            acked_generation: 5,
+            dirty: Vec::new(),
+        };
+        let backup = begin_backup_run(&state, &pull(7)).expect("run");
+        let a = work.join("a.txt");
+        fs::write(&a, b"remote").expect("write");
+        let saved = backup.join(a.strip_prefix("/").expect("absolute"));
+        fs::create_dir_all(saved.parent().expect("parent")).expect("mkdir");
+        fs::write(&saved, b"local").expect("write");
+
+        // a copy run: work/b.txt conflicted and our version sits beside it
+        let b = work.join("b.txt");
+        let b_copy = conflict_copy_path(&b, "laptop", "2026-10-19 101500");
+        fs::write(&b, b"remote").expect("write");
+        fs::write(&b_copy, b"local").expect("write");
+        record_copies(&state, &pull(8), &[(b.clone(), b_copy.clone())]).expect("record");
+
+        let listed = runs(&state).expect("runs");
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].manifest.head_generation, 7);
+        assert_eq!(listed[0].files[0].original, a);
+        assert!(listed[1].files[0].beside);
+
+        let (run, file) = find(&state, None, &a).expect("find a");
+        assert_eq!(run.id, 0);
+        let archived = resolve(&run, &file, true).expect("keep local");
+        assert_eq!(fs::read(&a).expect("a"), b"local");
+        assert!(archived.expect("archived").ends_with("archive/0"));
+
+        let (run, file) = find(&state, Some(1), &b).expect("find b");
+        resolve(&run, &file, false).expect("keep remote");
+        assert_eq!(fs::read(&b).expect("b"), b"remote");
+        assert!(!b_copy.exists());
+        assert!(runs(&state).expect("runs").is_empty());
+        assert!(find(&state, None, &b).is_err());
+    }
+
+    #[test]
+    fn prune_spares_runs_with_unresolved_conflicts() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let state = tmp.path();
+        let mut manifest = new_manifest(&PullInfo::default());
+        manifest.created_at = "2020-01-01 00:00:00".to_string();
+        // run 0: old but unresolved; run 1: old and empty; runs 2, 3: recent and empty
+        for id in 0..4 {
+            let dir = state.join(BEHIND_BACKUPS).join(id.to_string());
+            fs::create_dir_all(&dir).expect("mkdir");
+            if id < 2 {
+                write_manifest(&dir, &manifest).expect("manifest");
+            } else {
+                write_manifest(&dir, &new_manifest(&PullInfo::default())).expect("manifest");
+            }
+        }
+        let kept = state.join(BEHIND_BACKUPS).join("0/w/a.txt");
+        fs::create_dir_all(kept.parent().expect("parent")).expect("mkdir");
+        fs::write(&kept, b"local").expect("write");
+
+        let cfg = RetentionConfig {
+            keep: 2,
+            max_age_days: 30,
+            max_total_mb: 0,
+        };
+        let now = SystemTime::now();
+        let preview = prune(state, &cfg, now, true).expect("dry run");
+        let reasons: Vec<_> = preview
+            .iter()
+            .map(|p| (p.dir.file_name().expect("name").to_owned(), p.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("1".into(), "older than max_age_days"),
+                ("2".into(), "more than keep runs"),
+            ]
+        );
+        assert_eq!(
+            runs(state).expect("runs").len(),
+            4,
+            "dry run removes nothing"
+        );
+
+        prune(state, &cfg, now, false).expect("prune");
+        let left: Vec<u64> = runs(state).expect("runs").iter().map(|r| r.id).collect();
+        assert_eq!(left, vec![0, 3]);
+    }
+
+    #[test]
+    fn manifest_records_pull_context_and_hashes_backed_up_files() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let pull = PullInfo {
+            head_generation: 9,
+            acked_generation: 6,
+            dirty: vec![PathBuf::from("/w/a.txt")],
+        };
+        let run = begin_backup_run(tmp.path(), &pull).expect("run");
+        fs::create_dir_all(run.join("w")).expect("mkdir");
+        fs::write(run.join("w/a.txt"), b"abc").expect("write");
+        finish_backup_run(&run).expect("finish");
+
+        let manifest = read_manifest(&run);
+        assert_eq!(manifest.head_generation, 9);
+        assert_eq!(manifest.acked_generation, 6);
+        assert_eq!(manifest.dirty, pull.dirty);
+        assert!(!manifest.created_at.is_empty());
+        assert_eq!(
+            manifest.files,
+            vec![SavedFile {
+                path: PathBuf::from("/w/a.txt"),
+                size: 3,
+                sha256: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+                    .to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn restore_maps_rsync_relative_backups_to_original_paths() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let state = tmp.path().join("state");
+        let work = tmp.path().join("work");
+        let run = begin_backup_run(&state, &PullInfo::default()).expect("run");
+        for name in ["docs/a.txt", "docs/sub/b.txt", "other/c.txt"] {
+            let saved = run
+                .join(work.strip_prefix("/").expect("absolute"))
+                .join(name);
+            fs::create_dir_all(saved.parent().expect("parent")).expect("mkdir");
+            fs::write(&saved, name).expect("write");
+        }
+
+        let plan = restore_plan(&state, 0, &[work.join("docs")]).expect("plan");
+        let originals: Vec<_> = plan.iter().map(|f| f.original.clone()).collect();
+        assert_eq!(
+            originals,
+            vec![work.join("docs/a.txt"), work.join("docs/sub/b.txt")]
+        );
+        restore(&plan).expect("restore");
+        assert_eq!(
+            fs::read_to_string(work.join("docs/sub/b.txt")).expect("b"),
+            "docs/sub/b.txt"
+        );
+        assert!(!work.join("other/c.txt").exists());
+
+        assert!(restore_plan(&state, 0, &[work.join("nope")]).is_err());
+        assert!(restore_plan(&state, 4, &[]).is_err());
+
+        // archived, then a new run: `--run 0` still means the archived one
+        let archive = state.join("behind_backups/archive/0");
+        fs::create_dir_all(archive.parent().expect("parent")).expect("mkdir");
+        fs::rename(&run, &archive).expect("archive");
+        let next = begin_backup_run(&state, &PullInfo::default()).expect("run");
+        assert!(next.ends_with("behind_backups/1"));
+        assert_eq!(restore_plan(&state, 0, &[]).expect("archived").len(), 3);
+        // a tree from before ids were unique
+        fs::create_dir_all(state.join("behind_backups/0")).expect("mkdir");
+        let err = restore_plan(&state, 0, &[]).expect_err("ambiguous");
+        assert!(err.to_string().contains("both open and archived"), "{err}");
     }
 }
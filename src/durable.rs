@@ -0,0 +1,69 @@
+//! Crash-safe file replacement: write a sibling temp file, fsync it, then rename over the target.
+
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use crate::outcome::Outcome;
+
+/// Replaces `path` with `contents` so readers see either the old or the new file, never a torn one.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Outcome<()> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("write_atomic: '{}' has no file name", path.display()))?;
+    let tmp = parent.join(format!(
+        ".{}.tmp-{}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let written = (|| -> std::io::Result<()> {
+        let mut f = File::create(&tmp)?;
+        f.write_all(contents)?;
+        f.sync_all()?;
+        fs::rename(&tmp, path)?;
+        sync_dir(parent)
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return bad!("write '{}': {e}", path.display());
+    }
+    Ok(())
+}
+
+/// Persists the rename itself (directory entry) on platforms that allow opening directories.
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        File::open(dir)?.sync_all()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::write_atomic;
+
+    #[test]
+    fn write_atomic_replaces_contents_and_leaves_no_temp_file() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("state.toml");
+        fs::write(&path, "old").expect("seed");
+        write_atomic(&path, b"new").expect("write");
+        assert_eq!(fs::read_to_string(&path).expect("read"), "new");
+        let entries: Vec<_> = fs::read_dir(tmp.path()).expect("read_dir").collect();
+        assert_eq!(entries.len(), 1);
+    }
+}
@@ -0,0 +1,144 @@
+//! Built-in ignore list for editor swap files, OS metadata and partial downloads.
+//!
+//! Patterns use rsync's no-slash semantics: each one is matched against every path
+//! component, so an ignored directory hides everything below it. The same list is
+//! handed to rsync as `--exclude` (see [`crate::rsync::build_args`]) so the watcher and
+//! the transfer agree on what is junk.
+
+use std::path::Path;
+
+/// Applied to every anchor unless the anchor sets `default_ignores = false`.
+pub const DEFAULT_IGNORES: &[&str] = &[
+    // vim / emacs
+    "*.swp",
+    "*.swo",
+    "*.swx",
+    "*~",
+    ".#*",
+    "#*#",
+    // LibreOffice lock files
+    ".~lock.*#",
+    // macOS / Windows metadata
+    ".DS_Store",
+    "._*",
+    "Thumbs.db",
+    "desktop.ini",
+    // partial downloads
+    "*.part",
+    "*.partial",
+    "*.crdownload",
+    "*.download",
+];
+
+/// Built-in patterns (when `use_defaults`) followed by the anchor's own `excludes`.
+#[must_use]
+pub fn resolve(use_defaults: bool, excludes: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = if use_defaults {
+        DEFAULT_IGNORES.iter().map(|p| (*p).to_string()).collect()
+    } else {
+        Vec::new()
+    };
+    for pattern in excludes {
+        if !out.contains(pattern) {
+            out.push(pattern.clone());
+        }
+    }
+    out
+}
+
+/// True when any component of `path` matches any of `patterns`.
+///
+/// A trailing `/` (rsync's "directories only") is accepted and treated like the bare name.
+#[must_use]
+pub fn is_ignored(path: &Path, patterns: &[String]) -> bool {
+    path.components().any(|c| {
+        let name = c.as_os_str().to_string_lossy();
+        patterns
+            .iter()
+            .any(|p| wildcard_match(p.trim_end_matches('/'), &name))
+    })
+}
+
+/// Shell-style match supporting `*` and `?` (no character classes).
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    while pi < p.len() && p[pi] == '*' {
+        pi += 1;
+    }
+    pi == p.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{is_ignored, resolve, wildcard_match};
+
+    #[test]
+    fn wildcard_match_handles_star_and_question_mark() {
+        assert!(wildcard_match("*.swp", ".notes.txt.swp"));
+        assert!(wildcard_match("*~", "draft.md~"));
+        assert!(wildcard_match(".#*", ".#lock"));
+        assert!(wildcard_match("file?.txt", "file1.txt"));
+        assert!(!wildcard_match("*.swp", "swap.txt"));
+        assert!(!wildcard_match("Thumbs.db", "thumbs.db"));
+    }
+
+    #[test]
+    fn defaults_ignore_editor_and_os_junk() {
+        let patterns = resolve(true, &[]);
+        for junk in [
+            "/w/.notes.md.swp",
+            "/w/notes.md~",
+            "/w/.#notes.md",
+            "/w/sub/.DS_Store",
+            "/w/Thumbs.db",
+            "/w/big.iso.part",
+            "/w/setup.exe.crdownload",
+        ] {
+            assert!(is_ignored(Path::new(junk), &patterns), "{junk}");
+        }
+        assert!(!is_ignored(Path::new("/w/notes.md"), &patterns));
+    }
+
+    #[test]
+    fn ignored_directory_hides_its_contents() {
+        let patterns = resolve(false, &["node_modules/".to_string()]);
+        assert!(is_ignored(
+            Path::new("/w/app/node_modules/left-pad/index.js"),
+            &patterns
+        ));
+    }
+
+    #[test]
+    fn resolve_without_defaults_keeps_only_anchor_excludes() {
+        let patterns = resolve(false, &["*.pyc".to_string()]);
+        assert_eq!(patterns, vec!["*.pyc".to_string()]);
+        assert!(!is_ignored(Path::new("/w/.DS_Store"), &patterns));
+    }
+
+    #[test]
+    fn resolve_appends_anchor_excludes_without_duplicates() {
+        let patterns = resolve(true, &["*.swp".to_string(), "build".to_string()]);
+        assert_eq!(patterns.iter().filter(|p| *p == "*.swp").count(), 1);
+        assert_eq!(patterns.last().map(String::as_str), Some("build"));
+    }
+}
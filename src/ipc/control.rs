@@ -0,0 +1,372 @@
+//! Local control channel between the CLI and a running daemon.
+//!
+//! Each daemon listens on a Unix domain socket in [`crate::runtime::runtime_dir`]. A request is
+//! one JSON line and is answered by one JSON [`Response`] line. The socket is `0600` inside a
+//! `0700` directory, and every connection is also checked against the peer uid: only the
+//! daemon's own user (or root) gets an answer.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::outcome::Outcome;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    /// Re-read the configuration files.
+    Reload,
+    Stop,
+    Status,
+    /// Push `paths` (empty: every anchor) now; with `wait`, answer once the server acked it.
+    Sync {
+        paths: Vec<PathBuf>,
+        wait: bool,
+        timeout_secs: u64,
+    },
+    /// Hold back (or with `resume`, release) pushes for the anchors covering `paths`.
+    Pause {
+        paths: Vec<PathBuf>,
+        resume: bool,
+    },
+    /// Server only: change journal entries for generations `from..=to` (`None`: up to the head).
+    Changes {
+        from: u64,
+        to: Option<u64>,
+    },
+    /// Server only: the client registry.
+    Clients,
+    /// Server only: name the client `client` resolves to (`None` clears the name).
+    LabelClient {
+        client: String,
+        label: Option<String>,
+    },
+    /// Server only: drop the client `client` resolves to from the registry.
+    ForgetClient {
+        client: String,
+    },
+}
+
+impl Request {
+    /// How long the CLI waits for the answer.
+    fn answer_timeout(&self) -> Duration {
+        match self {
+            Request::Sync {
+                wait: true,
+                timeout_secs,
+                ..
+            } => Duration::from_secs(timeout_secs.saturating_add(5)),
+            _ => IO_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl Response {
+    #[must_use]
+    pub fn ok<S: Into<String>>(message: S) -> Self {
+        Response {
+            ok: true,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    #[must_use]
+    pub fn error<S: Into<String>>(message: S) -> Self {
+        Response {
+            ok: false,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    #[must_use]
+    pub fn data(mut self, data: serde_json::Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+/// Where a handler sends its answer; it may do so later from another thread (`sync --wait`).
+pub type Reply = mpsc::Sender<Response>;
+
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends `req` to the daemon behind `socket`. A daemon-side error comes back as `Err`.
+pub fn request(socket: &Path, req: &Request) -> Outcome<Response> {
+    #[cfg(unix)]
+    {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            os::unix::net::UnixStream,
+        };
+
+        let mut stream = UnixStream::connect(socket)
+            .map_err(|e| format!("no daemon listening on '{}': {e}", socket.display()))?;
+        stream.set_read_timeout(Some(req.answer_timeout()))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut line =
+            serde_json::to_string(req).map_err(|e| format!("encode control request: {e}"))?;
+        line.push('\n');
+        stream.write_all(line.as_bytes())?;
+
+        let mut answer = String::new();
+        BufReader::new(stream)
+            .read_line(&mut answer)
+            .map_err(|e| format!("no answer from daemon: {e}"))?;
+        if answer.trim().is_empty() {
+            return bad!("daemon closed the control socket without answering");
+        }
+        let resp: Response = serde_json::from_str(answer.trim())
+            .map_err(|e| format!("malformed control response: {e}"))?;
+        if resp.ok {
+            Ok(resp)
+        } else {
+            bad!("{}", resp.message)
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (socket, req);
+        bad!("the control socket is only available on Unix")
+    }
+}
+
+/// Waits for a stopping daemon to remove its socket.
+pub fn wait_closed(socket: &Path, timeout: Duration) -> Outcome<()> {
+    let deadline = std::time::Instant::now() + timeout;
+    while socket.exists() {
+        if std::time::Instant::now() >= deadline {
+            return bad!(
+                "daemon still running after {}s (socket '{}')",
+                timeout.as_secs(),
+                socket.display()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub use listener::serve;
+
+#[cfg(unix)]
+mod listener {
+    use std::{
+        fs,
+        io::{BufRead, BufReader, ErrorKind, Write},
+        os::{
+            fd::AsRawFd,
+            unix::{
+                fs::PermissionsExt,
+                net::{UnixListener, UnixStream},
+            },
+        },
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use log::{debug, error, info, warn};
+
+    use super::{Reply, Request, Response, IO_TIMEOUT};
+    use crate::outcome::Outcome;
+
+    /// Binds `path` and answers requests with `handler` until `fatal` is set; the socket file
+    /// is removed on the way out. Refuses to start if another daemon already answers there.
+    pub fn serve<H>(
+        path: PathBuf,
+        fatal: Arc<AtomicBool>,
+        handler: H,
+    ) -> Outcome<thread::JoinHandle<()>>
+    where
+        H: Fn(Request, Reply) + Send + Sync + 'static,
+    {
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return bad!(
+                    "another daemon is already listening on '{}'",
+                    path.display()
+                );
+            }
+            fs::remove_file(&path)
+                .map_err(|e| format!("remove stale socket '{}': {e}", path.display()))?;
+        }
+        let listener = UnixListener::bind(&path)
+            .map_err(|e| format!("bind control socket '{}': {e}", path.display()))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+        info!("control socket listening on '{}'", path.display());
+
+        let handler = Arc::new(handler);
+        Ok(thread::spawn(move || {
+            while !fatal.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let handler = Arc::clone(&handler);
+                        thread::spawn(move || {
+                            if let Err(e) = answer(stream, handler.as_ref()) {
+                                warn!("control socket: {e}");
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => {
+                        error!("control socket accept: {e}");
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                }
+            }
+            let _ = fs::remove_file(&path);
+            debug!("control socket closed");
+        }))
+    }
+
+    fn answer<H>(stream: UnixStream, handler: &H) -> Outcome<()>
+    where
+        H: Fn(Request, Reply),
+    {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+
+        let resp = if peer_allowed(&stream) {
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line)?;
+            match serde_json::from_str::<Request>(line.trim()) {
+                Ok(req) => {
+                    debug!("control request: {req:?}");
+                    let wait = req.answer_timeout();
+                    let (tx, rx) = mpsc::channel();
+                    handler(req, tx);
+                    rx.recv_timeout(wait)
+                        .unwrap_or_else(|_| Response::error("daemon did not answer in time"))
+                }
+                Err(e) => Response::error(format!("malformed request: {e}")),
+            }
+        } else {
+            warn!("control socket: rejected connection from another user");
+            Response::error("permission denied")
+        };
+
+        let mut out =
+            serde_json::to_string(&resp).map_err(|e| format!("encode control response: {e}"))?;
+        out.push('\n');
+        writer.write_all(out.as_bytes())?;
+        Ok(())
+    }
+
+    /// Only the daemon's own user and root may send commands.
+    fn peer_allowed(stream: &UnixStream) -> bool {
+        let me = unsafe { libc::geteuid() };
+        peer_uid(stream).is_some_and(|uid| uid == me || uid == 0)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn peer_uid(stream: &UnixStream) -> Option<u32> {
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = libc::socklen_t::try_from(std::mem::size_of::<libc::ucred>()).ok()?;
+        let rc = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                (&raw mut cred).cast(),
+                &raw mut len,
+            )
+        };
+        (rc == 0).then_some(cred.uid)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn peer_uid(stream: &UnixStream) -> Option<u32> {
+        let mut uid: libc::uid_t = 0;
+        let mut gid: libc::gid_t = 0;
+        let rc = unsafe { libc::getpeereid(stream.as_raw_fd(), &raw mut uid, &raw mut gid) };
+        (rc == 0).then_some(uid)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use super::{request, serve, Request, Response};
+
+    #[test]
+    fn request_round_trips_through_socket() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("test.sock");
+        let fatal = Arc::new(AtomicBool::new(false));
+        let handle = serve(path.clone(), Arc::clone(&fatal), |req, reply| {
+            let resp = match req {
+                Request::Pause { paths, .. } if paths.is_empty() => Response::error("no paths"),
+                other => Response::ok(format!("{other:?}")),
+            };
+            let _ = reply.send(resp);
+        })
+        .expect("serve");
+
+        let ok = request(&path, &Request::Status).expect("status");
+        assert_eq!(ok.message, "Status");
+        let err = request(
+            &path,
+            &Request::Pause {
+                paths: Vec::<PathBuf>::new(),
+                resume: false,
+            },
+        )
+        .expect_err("daemon-side error");
+        assert_eq!(err.to_string(), "no paths");
+
+        assert!(serve(path.clone(), Arc::clone(&fatal), |_, _| {}).is_err());
+
+        fatal.store(true, Ordering::Relaxed);
+        handle.join().expect("join");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn request_wire_format_is_tagged_json() {
+        let line = serde_json::to_string(&Request::Sync {
+            paths: vec![PathBuf::from("/w/a")],
+            wait: true,
+            timeout_secs: 30,
+        })
+        .expect("encode");
+        assert_eq!(
+            line,
+            r#"{"op":"sync","paths":["/w/a"],"wait":true,"timeout_secs":30}"#
+        );
+    }
+}
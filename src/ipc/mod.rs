@@ -1,14 +1,24 @@
-use log::error;
+use log::{error, warn};
 use serde::{Deserialize, Serialize};
 use std::{
     fmt,
     path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
 };
 
 #[cfg(windows)]
 use crate::parameters::DaemonType;
-use crate::{config, outcome::Outcome, parameters::DaemonParameters, shiplog};
+use crate::{
+    changes::ChangeSet,
+    config,
+    outcome::Outcome,
+    parameters::{DaemonParameters, SharedDaemonParams},
+    runtime::{self, RuntimeFiles},
+    shiplog,
+};
 
+pub mod control;
 #[cfg(unix)]
 mod unix;
 #[cfg(windows)]
@@ -17,29 +27,112 @@ mod zenoh;
 
 pub use zenoh::{Rx, ZenohClient, ZenohMessage, TOPIC_CLIENTS, TOPIC_CONTROL_RELOAD, TOPIC_SERVER};
 
-pub fn terminal_topic() -> Outcome<String> {
-    Ok(format!("sinkd/{}/terminate", config::get_hostname()?))
+/// `dest_path` of a client's "what changed since my `basis_generation`?" query and of the
+/// server's answer (addressed to the asking `client_id`).
+pub const CHANGES_DEST: &str = "sinkd_changes";
+
+/// `sinkd/<host>/<role>/<instance>`: the per-daemon control prefix.
+fn daemon_topic_prefix(shared: &SharedDaemonParams) -> Outcome<String> {
+    Ok(format!(
+        "sinkd/{}/{}/{}",
+        config::get_hostname()?,
+        shared.daemon_type.role(),
+        shared.instance
+    ))
+}
+
+/// Only the daemon with this role and instance on this host listens here.
+pub fn terminal_topic(shared: &SharedDaemonParams) -> Outcome<String> {
+    Ok(format!("{}/terminate", daemon_topic_prefix(shared)?))
 }
 
-pub fn send_terminate_signal() -> Outcome<()> {
-    let topic = terminal_topic()?;
-    let (client, _rx) = ZenohClient::new(&[], &topic)
+/// A daemon publishes here as it shuts down, confirming a terminate request.
+pub fn exited_topic(shared: &SharedDaemonParams) -> Outcome<String> {
+    Ok(format!("{}/exited", daemon_topic_prefix(shared)?))
+}
+
+/// Asks the daemon described by `shared` to exit and waits up to `timeout` for it to confirm.
+pub fn send_terminate_signal(shared: &SharedDaemonParams, timeout: Duration) -> Outcome<()> {
+    let topic = terminal_topic(shared)?;
+    let exited = exited_topic(shared)?;
+    let (client, rx) = ZenohClient::new(&[&exited], &topic)
         .map_err(|e| format!("failed to create Zenoh client for termination: {e}"))?;
     let mut payload = Payload::new()?.status(Status::NotReady(Reason::Other));
-    let publish_outcome = client.publish(&mut payload);
+    let outcome = client
+        .put(&topic, &mut payload)
+        .and_then(|()| wait_for_exit(&rx, &exited, timeout));
     client.disconnect();
-    publish_outcome.map_err(|e| {
-        error!("failed to send terminate message: {e}");
+    outcome.map_err(|e| {
+        error!("terminate {}: {e}", shared.daemon_name());
         e
-    })?;
-    Ok(())
+    })
+}
+
+fn wait_for_exit(rx: &Rx, exited: &str, timeout: Duration) -> Outcome<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return bad!(
+                "no exit confirmation on {exited} within {}s; is it running?",
+                timeout.as_secs()
+            );
+        }
+        match rx.recv_timeout(left) {
+            Ok(Some(msg)) if msg.topic == exited => return Ok(()),
+            Ok(_) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return bad!("Zenoh receiver disconnected while waiting for {}", exited);
+            }
+        }
+    }
+}
+
+/// Confirms on [`exited_topic`] that this daemon is going down; call right before disconnecting.
+pub fn announce_exit(client: &ZenohClient, shared: &SharedDaemonParams) {
+    let announced = exited_topic(shared).and_then(|topic| {
+        let mut payload = Payload::new()?.status(Status::NotReady(Reason::Other));
+        client.put(&topic, &mut payload)
+    });
+    if let Err(e) = announced {
+        error!("unable to announce exit: {e}");
+    }
+}
+
+/// Stops the daemon described by `shared`: over its control socket when it has one, otherwise
+/// with a scoped Zenoh terminate. Returns what to tell the user once the process is gone.
+///
+/// Nobody holding the instance lock means nothing is running; the socket and PID file a
+/// crashed daemon left behind are removed rather than talked to.
+pub fn stop_daemon(shared: &SharedDaemonParams, files: &RuntimeFiles) -> Outcome<String> {
+    const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+    if cfg!(unix) && !runtime::is_locked(files)? {
+        for stale in [&files.socket, &files.pid] {
+            if stale.exists() {
+                warn!("removing stale '{}'", stale.display());
+                let _ = std::fs::remove_file(stale);
+            }
+        }
+        return Ok(format!("{} is not running", shared.daemon_name()));
+    }
+    let message = if cfg!(unix) && files.socket.exists() {
+        let resp = control::request(&files.socket, &control::Request::Stop)?;
+        control::wait_closed(&files.socket, STOP_TIMEOUT)?;
+        resp.message
+    } else {
+        send_terminate_signal(shared, STOP_TIMEOUT)?;
+        format!("{} stopped", shared.daemon_name())
+    };
+    runtime::wait_unlocked(files, STOP_TIMEOUT)?;
+    Ok(message)
 }
 
 pub fn connect_with_terminate_topic(
     subscriptions: &[&str],
     publish_topic: &str,
+    shared: &SharedDaemonParams,
 ) -> Outcome<(ZenohClient, Rx, String)> {
-    let terminal = terminal_topic()?;
+    let terminal = terminal_topic(shared)?;
     let mut all_subscriptions = subscriptions.to_vec();
     all_subscriptions.push(TOPIC_CONTROL_RELOAD);
     all_subscriptions.push(&terminal);
@@ -113,6 +206,12 @@ pub fn daemon(params: &DaemonParameters) -> Outcome<()> {
 pub enum Reason {
     Busy,   // server will enter this state
     Behind, // response to client, never enters state
+    /// Refused by `[auth]`: unknown user or device, or no grant for the paths.
+    Unauthorized,
+    /// The push lands in a user tree or share that is at its `[quotas]` limit.
+    QuotaExceeded,
+    /// The server could not apply the push (see `error`); nothing of it landed.
+    Failed,
     #[default]
     Other,
 }
@@ -132,6 +231,9 @@ impl fmt::Display for Status {
                 match reason {
                     Reason::Busy => write!(f, "Sinking")?,
                     Reason::Behind => write!(f, "Behind")?,
+                    Reason::Unauthorized => write!(f, "Unauthorized")?,
+                    Reason::QuotaExceeded => write!(f, "QuotaExceeded")?,
+                    Reason::Failed => write!(f, "Failed")?,
                     Reason::Other => write!(f, "Other")?,
                 }
                 write!(f, ")")
@@ -146,6 +248,9 @@ pub struct Payload {
     pub hostname: String,
     pub username: String,
     pub src_paths: Vec<PathBuf>,
+    /// On a push: the paths under `src_paths` the client saw change, which are all the server
+    /// copies; empty means all of `src_paths`.
+    pub scope: Vec<PathBuf>,
     pub dest_path: PathBuf,
     pub date: String,
     /// Stable id for this sinkd client install (persisted locally).
@@ -158,6 +263,13 @@ pub struct Payload {
     pub last_writer_client_id: String,
     pub status: Status,
     pub rsync: Option<config::ResolvedRsyncConfig>,
+    /// Answer to a [`CHANGES_DEST`] query: what changed after `basis_generation` up to
+    /// `head_generation`, or `None` when the server no longer knows (pull everything).
+    pub changes: Option<ChangeSet>,
+    /// Set by the client on each push and echoed back when the server reports on it.
+    pub push_id: u64,
+    /// Why a push `Failed`.
+    pub error: String,
 }
 
 #[allow(dead_code)]
@@ -167,6 +279,7 @@ impl Payload {
             hostname: config::get_hostname()?,
             username: config::get_username()?,
             src_paths: vec![],
+            scope: vec![],
             date: String::from("2022Jan4"),
             client_id: String::new(),
             basis_generation: 0,
@@ -175,6 +288,9 @@ impl Payload {
             status: Status::Ready,
             dest_path: PathBuf::from("server"),
             rsync: None,
+            changes: None,
+            push_id: 0,
+            error: String::new(),
         })
     }
 
@@ -192,11 +308,13 @@ impl Payload {
         last_writer_client_id: String,
         status: Status,
         rsync: Option<config::ResolvedRsyncConfig>,
+        changes: Option<ChangeSet>,
     ) -> Payload {
         Payload {
             hostname,
             username,
             src_paths,
+            scope: Vec::new(),
             dest_path,
             date,
             client_id,
@@ -205,6 +323,9 @@ impl Payload {
             last_writer_client_id,
             status,
             rsync,
+            changes,
+            push_id: 0,
+            error: String::new(),
         }
     }
 
@@ -226,6 +347,22 @@ impl Payload {
         self
     }
 
+    #[must_use]
+    pub fn scope(mut self, paths: Vec<PathBuf>) -> Self {
+        self.scope = paths;
+        self
+    }
+
+    /// What a push copies: its scope, or all of `src_paths` when it has none.
+    #[must_use]
+    pub fn sources(&self) -> &[PathBuf] {
+        if self.scope.is_empty() {
+            &self.src_paths
+        } else {
+            &self.scope
+        }
+    }
+
     #[must_use]
     pub fn date<S: Into<String>>(mut self, date: S) -> Self {
         self.date = date.into();
@@ -238,6 +375,18 @@ impl Payload {
         self
     }
 
+    #[must_use]
+    pub fn push_id(mut self, id: u64) -> Self {
+        self.push_id = id;
+        self
+    }
+
+    #[must_use]
+    pub fn error<S: Into<String>>(mut self, error: S) -> Self {
+        self.error = error.into();
+        self
+    }
+
     #[must_use]
     pub fn basis_generation(mut self, g: u64) -> Self {
         self.basis_generation = g;
@@ -273,6 +422,12 @@ impl Payload {
         self.rsync = Some(rsync);
         self
     }
+
+    #[must_use]
+    pub fn changes(mut self, changes: Option<ChangeSet>) -> Self {
+        self.changes = changes;
+        self
+    }
 }
 
 impl fmt::Display for Payload {
@@ -295,6 +450,72 @@ impl fmt::Display for Payload {
             self.head_generation,
             self.last_writer_client_id,
             self.status
-        )
+        )?;
+        if let Some(changes) = &self.changes {
+            write!(
+                f,
+                ", changes: {} changed / {} deleted",
+                changes.changed.len(),
+                changes.deleted.len()
+            )?;
+        }
+        if self.push_id != 0 {
+            write!(f, ", push: {}", self.push_id)?;
+        }
+        if !self.error.is_empty() {
+            write!(f, ", error: {}", self.error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{exited_topic, stop_daemon, terminal_topic};
+    use crate::{
+        parameters::{DaemonType, SharedDaemonParams},
+        runtime::RuntimeFiles,
+    };
+
+    fn shared(daemon_type: DaemonType, instance: &str) -> SharedDaemonParams {
+        SharedDaemonParams {
+            daemon_type,
+            verbosity: 2,
+            debug: 1,
+            log_path: PathBuf::from("/tmp/sinkd/test.log"),
+            instance: instance.to_string(),
+        }
+    }
+
+    #[test]
+    fn terminate_topics_are_scoped_by_role_and_instance() {
+        let client = terminal_topic(&shared(DaemonType::UnixClient, "default")).expect("topic");
+        let server = terminal_topic(&shared(DaemonType::UnixServer, "default")).expect("topic");
+        let second = terminal_topic(&shared(DaemonType::UnixClient, "work")).expect("topic");
+        assert!(client.ends_with("/client/default/terminate"), "{client}");
+        assert!(server.ends_with("/server/default/terminate"), "{server}");
+        assert!(second.ends_with("/client/work/terminate"), "{second}");
+        assert_ne!(client, server);
+
+        let exited = exited_topic(&shared(DaemonType::WindowsClient, "default")).expect("topic");
+        assert_eq!(exited.replace("/exited", "/terminate"), client);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stopping_a_crashed_daemon_clears_its_leftovers() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let params = shared(DaemonType::UnixServer, "default");
+        let files = RuntimeFiles::new(tmp.path(), &params.daemon_name());
+        std::fs::write(&files.lock, "").expect("lock file");
+        std::fs::write(&files.pid, format!("{}\n", std::process::id())).expect("pid");
+        std::os::unix::net::UnixListener::bind(&files.socket).expect("socket");
+
+        let message = stop_daemon(&params, &files).expect("nothing to stop");
+        assert_eq!(message, "server-default is not running");
+        assert!(!files.socket.exists());
+        assert!(!files.pid.exists());
     }
 }
@@ -22,38 +22,49 @@ pub struct ZenohPayload {
     pub username: String,
     /// Paths serialized as newline-separated strings
     pub src_paths: String,
+    /// Newline-separated, like `src_paths`
+    pub scope: String,
     pub dest_path: String,
     pub date: String,
     pub client_id: String,
     pub basis_generation: u64,
     pub head_generation: u64,
     pub last_writer_client_id: String,
-    /// Status encoded as: 0=Ready, 1=Busy, 2=Behind, 3=Other
+    /// Status encoded as: 0=Ready, 1=Busy, 2=Behind, 3=Other, 4=Unauthorized,
+    /// 5=QuotaExceeded, 6=Failed
     pub status_code: u8,
     pub rsync: Option<crate::config::ResolvedRsyncConfig>,
+    pub changes: Option<crate::changes::ChangeSet>,
+    pub push_id: u64,
+    pub error: String,
 }
 
 impl ZenohPayload {
     /// Convert from internal Payload to Zenoh-compatible payload
     pub fn from_payload(p: &Payload) -> Self {
-        let src_paths = p
-            .src_paths
-            .iter()
-            .map(|path| path.to_string_lossy().to_string())
-            .collect::<Vec<_>>()
-            .join("\n");
+        let join = |paths: &[std::path::PathBuf]| {
+            paths
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
 
         let status_code = match p.status {
             super::Status::Ready => 0,
             super::Status::NotReady(super::Reason::Busy) => 1,
             super::Status::NotReady(super::Reason::Behind) => 2,
             super::Status::NotReady(super::Reason::Other) => 3,
+            super::Status::NotReady(super::Reason::Unauthorized) => 4,
+            super::Status::NotReady(super::Reason::QuotaExceeded) => 5,
+            super::Status::NotReady(super::Reason::Failed) => 6,
         };
 
         ZenohPayload {
             hostname: p.hostname.clone(),
             username: p.username.clone(),
-            src_paths,
+            src_paths: join(&p.src_paths),
+            scope: join(&p.scope),
             dest_path: p.dest_path.to_string_lossy().to_string(),
             date: p.date.clone(),
             client_id: p.client_id.clone(),
@@ -62,6 +73,9 @@ impl ZenohPayload {
             last_writer_client_id: p.last_writer_client_id.clone(),
             status_code,
             rsync: p.rsync.clone(),
+            changes: p.changes.clone(),
+            push_id: p.push_id,
+            error: p.error.clone(),
         }
     }
 
@@ -69,24 +83,29 @@ impl ZenohPayload {
     pub fn to_payload(&self) -> Payload {
         use std::path::PathBuf;
 
-        let src_paths: Vec<PathBuf> = self
-            .src_paths
-            .split('\n')
-            .filter(|s| !s.is_empty())
-            .map(PathBuf::from)
-            .collect();
+        let split = |joined: &str| -> Vec<PathBuf> {
+            joined
+                .split('\n')
+                .filter(|s| !s.is_empty())
+                .map(PathBuf::from)
+                .collect()
+        };
 
         let status = match self.status_code {
             0 => super::Status::Ready,
             1 => super::Status::NotReady(super::Reason::Busy),
             2 => super::Status::NotReady(super::Reason::Behind),
+            4 => super::Status::NotReady(super::Reason::Unauthorized),
+            5 => super::Status::NotReady(super::Reason::QuotaExceeded),
+            6 => super::Status::NotReady(super::Reason::Failed),
             _ => super::Status::NotReady(super::Reason::Other),
         };
 
         Payload {
             hostname: self.hostname.clone(),
             username: self.username.clone(),
-            src_paths,
+            src_paths: split(&self.src_paths),
+            scope: split(&self.scope),
             dest_path: PathBuf::from(&self.dest_path),
             date: self.date.clone(),
             client_id: self.client_id.clone(),
@@ -95,6 +114,9 @@ impl ZenohPayload {
             last_writer_client_id: self.last_writer_client_id.clone(),
             status,
             rsync: self.rsync.clone(),
+            changes: self.changes.clone(),
+            push_id: self.push_id,
+            error: self.error.clone(),
         }
     }
 }
@@ -266,6 +288,20 @@ impl ZenohClient {
         }
     }
 
+    /// Publish `payload` to `topic` synchronously, bypassing the publisher thread and test
+    /// hooks. For control messages that must be on the wire before the session closes.
+    pub fn put(&self, topic: &str, payload: &mut Payload) -> Outcome<()> {
+        payload.date = crate::time::stamp(Some("%Y%m%d"));
+        let bytes = bincode::serialize(&ZenohPayload::from_payload(payload))
+            .map_err(|e| format!("Zenoh serialize error: {e:?}"))?;
+        self.session
+            .put(topic, bytes)
+            .wait()
+            .map_err(|e| format!("Zenoh put to {topic} failed: {e:?}"))?;
+        debug!("put payload to {topic}: {payload}");
+        Ok(())
+    }
+
     /// Disconnect from Zenoh
     pub fn disconnect(&self) {
         debug!("disconnecting from Zenoh...");
@@ -322,10 +358,14 @@ mod tests {
     use std::path::PathBuf;
 
     use super::ZenohPayload;
+    use crate::changes::ChangeSet;
     use crate::ipc::{Payload, Reason, Status};
 
     #[test]
     fn payload_roundtrip_preserves_fields() {
+        let mut changes = ChangeSet::default();
+        changes.record_changed(PathBuf::from("/tmp/a/x"));
+        changes.record_deleted(PathBuf::from("/tmp/b/y"));
         let payload = Payload::from(
             "host-a".to_string(),
             "alice".to_string(),
@@ -338,7 +378,9 @@ mod tests {
             String::new(),
             Status::NotReady(Reason::Behind),
             None,
-        );
+            Some(changes.clone()),
+        )
+        .scope(vec![PathBuf::from("/tmp/a/sub")]);
 
         let wire = ZenohPayload::from_payload(&payload);
         let decoded = wire.to_payload();
@@ -346,6 +388,7 @@ mod tests {
         assert_eq!(decoded.hostname, payload.hostname);
         assert_eq!(decoded.username, payload.username);
         assert_eq!(decoded.src_paths, payload.src_paths);
+        assert_eq!(decoded.scope, payload.scope);
         assert_eq!(decoded.dest_path, payload.dest_path);
         assert_eq!(decoded.date, payload.date);
         assert_eq!(decoded.client_id, payload.client_id);
@@ -353,6 +396,7 @@ mod tests {
         assert_eq!(decoded.head_generation, payload.head_generation);
         assert_eq!(decoded.last_writer_client_id, payload.last_writer_client_id);
         assert_eq!(decoded.status, payload.status);
+        assert_eq!(decoded.changes, Some(changes));
     }
 
     #[test]
@@ -369,6 +413,7 @@ mod tests {
             String::new(),
             Status::Ready,
             None,
+            None,
         );
         let busy = Payload::from(
             "h".to_string(),
@@ -382,6 +427,7 @@ mod tests {
             String::new(),
             Status::NotReady(Reason::Busy),
             None,
+            None,
         );
         let behind = Payload::from(
             "h".to_string(),
@@ -395,6 +441,7 @@ mod tests {
             String::new(),
             Status::NotReady(Reason::Behind),
             None,
+            None,
         );
         let other = Payload::from(
             "h".to_string(),
@@ -408,11 +455,48 @@ mod tests {
             String::new(),
             Status::NotReady(Reason::Other),
             None,
+            None,
+        );
+        let unauthorized = Payload::from(
+            "h".to_string(),
+            "u".to_string(),
+            vec![],
+            PathBuf::from("x"),
+            "d".to_string(),
+            String::new(),
+            0,
+            0,
+            String::new(),
+            Status::NotReady(Reason::Unauthorized),
+            None,
+            None,
         );
 
         assert_eq!(ZenohPayload::from_payload(&ready).status_code, 0);
         assert_eq!(ZenohPayload::from_payload(&busy).status_code, 1);
         assert_eq!(ZenohPayload::from_payload(&behind).status_code, 2);
         assert_eq!(ZenohPayload::from_payload(&other).status_code, 3);
+        let coded = ZenohPayload::from_payload(&unauthorized);
+        assert_eq!(coded.status_code, 4);
+        assert_eq!(coded.to_payload().status, unauthorized.status);
+        let over = Payload {
+            status: Status::NotReady(Reason::QuotaExceeded),
+            ..unauthorized
+        };
+        let coded = ZenohPayload::from_payload(&over);
+        assert_eq!(coded.status_code, 5);
+        assert_eq!(coded.to_payload().status, over.status);
+        let failed = Payload {
+            status: Status::NotReady(Reason::Failed),
+            push_id: 42,
+            error: "rsync failed with status 23".to_string(),
+            ..over
+        };
+        let decoded = ZenohPayload::from_payload(&failed).to_payload();
+        assert_eq!(decoded.status, failed.status);
+        assert_eq!(
+            (decoded.push_id, decoded.error.as_str()),
+            (42, failed.error.as_str())
+        );
     }
 }
@@ -0,0 +1,149 @@
+//! Append-only record of what every generation applied on the server.
+//!
+//! One JSON line per generation in `journal.jsonl` under the sync root: who wrote it, which
+//! files changed or went away, and how many bytes that took. `generation_state.toml` only
+//! keeps when and by whom, so this is where the server looks up what a `Behind` client has to
+//! pull and whether a push is stale. Nothing here is pruned, so `sinkd server changes` can
+//! answer for any range. A line torn by a crash is skipped when reading.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    changes::{ChangeSet, Itemized},
+    outcome::Outcome,
+};
+
+pub const JOURNAL_FILE: &str = "journal.jsonl";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournalEntry {
+    pub generation: u64,
+    pub applied_at_unix: i64,
+    pub client_id: String,
+    pub hostname: String,
+    pub username: String,
+    /// Files (and symlinks) created or updated, as the client sees them.
+    pub changed: Vec<PathBuf>,
+    /// Files and directories removed.
+    pub deleted: Vec<PathBuf>,
+    /// Total size of the changed files.
+    pub size_bytes: u64,
+    /// Bytes rsync sent for them.
+    pub sent_bytes: u64,
+}
+
+impl JournalEntry {
+    #[must_use]
+    pub fn new(generation: u64, applied_at_unix: i64, itemized: &Itemized) -> Self {
+        JournalEntry {
+            generation,
+            applied_at_unix,
+            changed: itemized.changes.changed.iter().cloned().collect(),
+            deleted: itemized.changes.deleted.clone(),
+            size_bytes: itemized.size_bytes,
+            sent_bytes: itemized.sent_bytes,
+            ..JournalEntry::default()
+        }
+    }
+
+    /// The change record this line was written from.
+    #[must_use]
+    pub fn changes(&self) -> ChangeSet {
+        ChangeSet {
+            changed: self.changed.iter().cloned().collect(),
+            deleted: self.deleted.clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn writer(mut self, client_id: &str, hostname: &str, username: &str) -> Self {
+        self.client_id = client_id.to_string();
+        self.hostname = hostname.to_string();
+        self.username = username.to_string();
+        self
+    }
+}
+
+/// Appends `entry` as one line and flushes it to disk.
+pub fn append(path: &Path, entry: &JournalEntry) -> Outcome<()> {
+    let mut line =
+        serde_json::to_string(entry).map_err(|e| format!("encode journal entry: {e}"))?;
+    line.push('\n');
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("open journal '{}': {e}", path.display()))?;
+    f.write_all(line.as_bytes())?;
+    f.sync_data()?;
+    Ok(())
+}
+
+/// Entries for generations `from..=to` (`to: None` means up to the newest), oldest first.
+pub fn read_range(path: &Path, from: u64, to: Option<u64>) -> Outcome<Vec<JournalEntry>> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return bad!("read journal '{}': {e}", path.display()),
+    };
+    let mut entries = Vec::new();
+    for (n, line) in raw.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<JournalEntry>(line) {
+            Ok(e) if e.generation >= from && to.is_none_or(|to| e.generation <= to) => {
+                entries.push(e);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("journal '{}' line {}: {e}; skipped", path.display(), n + 1),
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+    use super::{append, read_range, JournalEntry};
+    use crate::changes::Itemized;
+
+    #[test]
+    fn appended_entries_read_back_by_range_past_a_torn_line() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("journal.jsonl");
+        let mut itemized = Itemized::default();
+        itemized
+            .changes
+            .record_changed(PathBuf::from("/home/u/a.txt"));
+        itemized.size_bytes = 10;
+        for generation in 1..=3 {
+            let entry =
+                JournalEntry::new(generation, 0, &itemized).writer("id-1", "laptop", "alice");
+            append(&path, &entry).expect("append");
+        }
+        let mut f = OpenOptions::new().append(true).open(&path).expect("open");
+        f.write_all(b"{\"generation\": 4, \"chan").expect("torn");
+
+        let mid = read_range(&path, 2, Some(3)).expect("read");
+        assert_eq!(
+            mid.iter().map(|e| e.generation).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(mid[0].username, "alice");
+        assert_eq!(mid[0].changed, vec![PathBuf::from("/home/u/a.txt")]);
+        assert_eq!(read_range(&path, 0, None).expect("read").len(), 3);
+        assert!(read_range(&tmp.path().join("none"), 0, None)
+            .expect("missing")
+            .is_empty());
+    }
+}
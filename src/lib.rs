@@ -9,16 +9,31 @@
 pub mod fancy;
 #[macro_use]
 pub mod outcome;
+pub mod apply;
+pub mod auth;
+pub mod backoff;
+pub mod changes;
 pub mod cli;
 pub mod client;
 pub mod config;
 pub mod conflict;
+pub mod durable;
+pub mod ignore;
 pub mod ipc;
+pub mod journal;
+pub mod outbox;
 pub mod parameters;
+pub mod pending;
+pub mod registry;
 pub mod rsync;
+pub mod runtime;
 pub mod server;
 pub mod shiplog;
+pub mod snapshot;
+pub mod staging;
+pub mod status;
 pub mod test_hooks;
 pub mod time;
+pub mod usage;
 
 pub use outcome::Outcome;
@@ -0,0 +1,469 @@
+//! Client-side queue of anchors waiting to be pushed.
+//!
+//! Anchors move `pending` → `in_flight` when published and leave `in_flight` when the server
+//! acks the push from our `client_id` that carried them. A `Busy` reply, or a push the server failed to apply, puts them back
+//! into `pending` behind a [`Backoff`], so nothing observed is dropped.
+//! Paused anchors stay queued but are never handed out until resumed.
+//!
+//! Each anchor also carries its scope: the directories under it the watcher saw change, which
+//! is all the server has to copy. An anchor queued any other way (a replay, `sinkd client
+//! sync`) goes whole, and a push that comes back unapplied returns its scope to the queue.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::backoff::{Backoff, BackoffConfig};
+
+/// Republish in-flight anchors if the server neither acked nor rejected them by then.
+const IN_FLIGHT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What of an anchor a push has to copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Scope {
+    Whole,
+    /// These directories, each with everything below it.
+    Dirs(BTreeSet<PathBuf>),
+}
+
+impl Scope {
+    fn merge(&mut self, other: Scope) {
+        match (&mut *self, other) {
+            (Scope::Dirs(mine), Scope::Dirs(theirs)) => mine.extend(theirs),
+            (Scope::Whole, _) => {}
+            (_, Scope::Whole) => *self = Scope::Whole,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Outbox {
+    pending: BTreeSet<PathBuf>,
+    in_flight: BTreeSet<PathBuf>,
+    in_flight_since: Option<Instant>,
+    paused: BTreeSet<PathBuf>,
+    /// Not yet pushed; an anchor without one goes whole.
+    scopes: BTreeMap<PathBuf, Scope>,
+    /// What each in-flight anchor's push covers.
+    sent: BTreeMap<PathBuf, Scope>,
+    backoff: Backoff,
+}
+
+impl Outbox {
+    #[must_use]
+    pub fn new(cfg: BackoffConfig) -> Self {
+        Outbox {
+            pending: BTreeSet::new(),
+            in_flight: BTreeSet::new(),
+            in_flight_since: None,
+            paused: BTreeSet::new(),
+            scopes: BTreeMap::new(),
+            sent: BTreeMap::new(),
+            backoff: Backoff::new(cfg),
+        }
+    }
+
+    pub fn reconfigure(&mut self, cfg: BackoffConfig) {
+        self.backoff.reconfigure(cfg);
+    }
+
+    /// Queues whole anchors.
+    pub fn enqueue<I: IntoIterator<Item = PathBuf>>(&mut self, paths: I) {
+        for path in paths {
+            self.scopes.insert(path.clone(), Scope::Whole);
+            self.pending.insert(path);
+        }
+    }
+
+    /// Queues anchors with the scope [`Outbox::touch`] gathered for them (whole if none).
+    pub fn enqueue_changed<I: IntoIterator<Item = PathBuf>>(&mut self, anchors: I) {
+        self.pending.extend(anchors);
+    }
+
+    /// Something changed in `dir` under `anchor`; its next push copies at least that.
+    pub fn touch(&mut self, anchor: &Path, dir: PathBuf) {
+        let scope = if dir == anchor {
+            Scope::Whole
+        } else {
+            Scope::Dirs(BTreeSet::from([dir]))
+        };
+        match self.scopes.get_mut(anchor) {
+            Some(mine) => mine.merge(scope),
+            None => {
+                self.scopes.insert(anchor.to_path_buf(), scope);
+            }
+        }
+    }
+
+    /// The paths the push of in-flight `anchors` copies: each anchor, or the top-most of the
+    /// directories in its scope.
+    #[must_use]
+    pub fn sources(&self, anchors: &[PathBuf]) -> Vec<PathBuf> {
+        let mut out = Vec::new();
+        for anchor in anchors {
+            match self.sent.get(anchor) {
+                Some(Scope::Dirs(dirs)) if !dirs.is_empty() => out.extend(
+                    dirs.iter()
+                        .filter(|d| !dirs.iter().any(|o| o != *d && d.starts_with(o)))
+                        .cloned(),
+                ),
+                _ => out.push(anchor.clone()),
+            }
+        }
+        out
+    }
+
+    /// Moves `anchors` into flight along with their scopes.
+    fn send(&mut self, anchors: BTreeSet<PathBuf>) {
+        for anchor in &anchors {
+            let scope = self.scopes.remove(anchor).unwrap_or(Scope::Whole);
+            self.sent.insert(anchor.clone(), scope);
+        }
+        self.in_flight.extend(anchors);
+    }
+
+    /// Puts in-flight `anchor` back in the queue with what its push was to cover.
+    fn requeue(&mut self, anchor: PathBuf) {
+        if let Some(scope) = self.sent.remove(&anchor) {
+            match self.scopes.get_mut(&anchor) {
+                Some(mine) => mine.merge(scope),
+                None => {
+                    self.scopes.insert(anchor.clone(), scope);
+                }
+            }
+        }
+        self.pending.insert(anchor);
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty() && self.in_flight.is_empty()
+    }
+
+    /// Anchors to publish now, or `None` while backing off or waiting on an earlier push.
+    pub fn take_due(&mut self, now: Instant) -> Option<Vec<PathBuf>> {
+        if !self.backoff.ready(now) {
+            return None;
+        }
+        if let Some(since) = self.in_flight_since {
+            if now.duration_since(since) < IN_FLIGHT_TIMEOUT {
+                return None;
+            }
+            // no answer: treat the earlier push as lost and send it again with anything new
+            for anchor in std::mem::take(&mut self.in_flight) {
+                self.requeue(anchor);
+            }
+            self.in_flight_since = None;
+        }
+        let due = self.take_unpaused();
+        if due.is_empty() {
+            return None;
+        }
+        self.send(due);
+        self.in_flight_since = Some(now);
+        Some(self.in_flight.iter().cloned().collect())
+    }
+
+    /// A push covering every unpaused anchor whole (e.g. the post-`Behind` push) is on the wire.
+    pub fn mark_all_in_flight(&mut self, now: Instant) {
+        let due = self.take_unpaused();
+        for anchor in &due {
+            self.scopes.insert(anchor.clone(), Scope::Whole);
+        }
+        self.send(due);
+        self.in_flight_since = Some(now);
+    }
+
+    fn take_unpaused(&mut self) -> BTreeSet<PathBuf> {
+        let (paused, due) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|p| self.paused.contains(p));
+        self.pending = paused;
+        due
+    }
+
+    pub fn pause<I: IntoIterator<Item = PathBuf>>(&mut self, anchors: I) {
+        self.paused.extend(anchors);
+    }
+
+    pub fn resume(&mut self, anchors: &[PathBuf]) {
+        self.paused.retain(|p| !anchors.contains(p));
+    }
+
+    #[must_use]
+    pub fn is_paused(&self, anchor: &PathBuf) -> bool {
+        self.paused.contains(anchor)
+    }
+
+    pub fn paused(&self) -> impl Iterator<Item = &PathBuf> {
+        self.paused.iter()
+    }
+
+    /// Server is busy. If that turned away a push of ours, keep its anchors and schedule a
+    /// retry (returns the delay); otherwise nothing was lost and there is nothing to back off.
+    pub fn on_busy(&mut self, now: Instant) -> Option<Duration> {
+        if self.in_flight.is_empty() {
+            return None;
+        }
+        for anchor in std::mem::take(&mut self.in_flight) {
+            self.requeue(anchor);
+        }
+        self.in_flight_since = None;
+        Some(self.backoff.fail(now))
+    }
+
+    /// The server could not apply a push of ours covering `paths`: queue those again and
+    /// schedule a retry (returns the delay). `None` if none of them is in flight any more.
+    pub fn on_failed(&mut self, paths: &[PathBuf], now: Instant) -> Option<Duration> {
+        let failed: Vec<PathBuf> = paths
+            .iter()
+            .filter(|p| self.in_flight.remove(*p))
+            .cloned()
+            .collect();
+        if failed.is_empty() {
+            return None;
+        }
+        for anchor in failed {
+            self.requeue(anchor);
+        }
+        if self.in_flight.is_empty() {
+            self.in_flight_since = None;
+        }
+        Some(self.backoff.fail(now))
+    }
+
+    /// Explicit sync request: skip whatever backoff delay is still running.
+    pub fn expedite(&mut self) {
+        self.backoff.reset();
+    }
+
+    /// Server applied a push from us covering `paths`. Anchors published in another push
+    /// (each rsync-config group goes separately) stay in flight until their own answer.
+    pub fn on_applied(&mut self, paths: &[PathBuf]) {
+        for path in paths {
+            if self.in_flight.remove(path) {
+                self.sent.remove(path);
+            }
+        }
+        if self.in_flight.is_empty() {
+            self.in_flight_since = None;
+        }
+        self.backoff.reset();
+    }
+
+    /// Every anchor not yet acked (queued or in flight), for the on-disk journal.
+    #[must_use]
+    pub fn anchors(&self) -> BTreeSet<PathBuf> {
+        self.pending.union(&self.in_flight).cloned().collect()
+    }
+
+    /// Anchors waiting for the next push.
+    pub fn queued(&self) -> impl Iterator<Item = &PathBuf> {
+        self.pending.iter()
+    }
+
+    /// Anchors published and not yet acked.
+    pub fn in_flight(&self) -> impl Iterator<Item = &PathBuf> {
+        self.in_flight.iter()
+    }
+
+    #[must_use]
+    pub fn pending_len(&self) -> usize {
+        self.pending.len() + self.in_flight.len()
+    }
+
+    #[must_use]
+    pub fn retry_in(&self, now: Instant) -> Duration {
+        self.backoff.remaining(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        time::{Duration, Instant},
+    };
+
+    use super::{Outbox, IN_FLIGHT_TIMEOUT};
+    use crate::backoff::BackoffConfig;
+
+    fn outbox() -> Outbox {
+        Outbox::new(BackoffConfig {
+            base_ms: 1000,
+            ceiling_secs: 8,
+        })
+    }
+
+    #[test]
+    fn busy_keeps_paths_and_delays_retry() {
+        let mut ob = outbox();
+        let now = Instant::now();
+        ob.enqueue([PathBuf::from("/w/a")]);
+        assert_eq!(ob.take_due(now), Some(vec![PathBuf::from("/w/a")]));
+
+        let delay = ob.on_busy(now).expect("push was in flight");
+        assert_eq!(ob.pending_len(), 1);
+        assert_eq!(ob.take_due(now), None);
+        assert_eq!(ob.take_due(now + delay), Some(vec![PathBuf::from("/w/a")]));
+    }
+
+    #[test]
+    fn failed_push_requeues_only_its_paths() {
+        let mut ob = outbox();
+        let now = Instant::now();
+        ob.enqueue([
+            PathBuf::from("/w/a"),
+            PathBuf::from("/w/b"),
+            PathBuf::from("/w/c"),
+        ]);
+        assert!(ob.take_due(now).is_some());
+
+        // c's push is applied first; the failure for a's must still find a in flight
+        ob.on_applied(&[PathBuf::from("/w/c")]);
+        let delay = ob
+            .on_failed(&[PathBuf::from("/w/a")], now)
+            .expect("a was in flight");
+        assert_eq!(ob.pending_len(), 2);
+        assert_eq!(
+            ob.in_flight().cloned().collect::<Vec<_>>(),
+            vec![PathBuf::from("/w/b")]
+        );
+        assert_eq!(
+            ob.on_failed(&[PathBuf::from("/w/a")], now),
+            None,
+            "already requeued"
+        );
+        // a late ack naming a does not drop it from the queue
+        ob.on_applied(&[PathBuf::from("/w/a"), PathBuf::from("/w/b")]);
+        assert_eq!(
+            ob.anchors().into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/w/a")]
+        );
+        assert_eq!(ob.take_due(now + delay), Some(vec![PathBuf::from("/w/a")]));
+    }
+
+    #[test]
+    fn ack_for_one_group_leaves_the_other_in_flight_to_fail() {
+        let mut ob = outbox();
+        let now = Instant::now();
+        // two rsync-config groups published from one take
+        ob.enqueue([PathBuf::from("/w/a"), PathBuf::from("/w/b")]);
+        assert!(ob.take_due(now).is_some());
+
+        ob.on_applied(&[PathBuf::from("/w/a")]);
+        assert_eq!(
+            ob.in_flight().cloned().collect::<Vec<_>>(),
+            vec![PathBuf::from("/w/b")]
+        );
+        assert_eq!(ob.take_due(now), None, "b still awaits its answer");
+        let delay = ob
+            .on_failed(&[PathBuf::from("/w/b")], now)
+            .expect("b was still in flight");
+        assert_eq!(
+            ob.anchors().into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/w/b")]
+        );
+        assert_eq!(ob.take_due(now + delay), Some(vec![PathBuf::from("/w/b")]));
+    }
+
+    #[test]
+    fn scope_narrows_a_push_and_comes_back_with_a_failure() {
+        let mut ob = outbox();
+        let now = Instant::now();
+        let a = PathBuf::from("/w/a");
+        ob.touch(&a, PathBuf::from("/w/a/x"));
+        ob.touch(&a, PathBuf::from("/w/a/x/deep"));
+        ob.enqueue_changed([a.clone()]);
+        let due = ob.take_due(now).expect("due");
+        assert_eq!(ob.sources(&due), vec![PathBuf::from("/w/a/x")]);
+
+        // a change during the push widens the retry, which still covers the failed scope
+        ob.touch(&a, PathBuf::from("/w/a/y"));
+        let delay = ob.on_failed(&due, now).expect("in flight");
+        let due = ob.take_due(now + delay).expect("due");
+        assert_eq!(
+            ob.sources(&due),
+            vec![PathBuf::from("/w/a/x"), PathBuf::from("/w/a/y")]
+        );
+        ob.on_applied(&due);
+
+        // a sync request, or a change to the anchor itself, sends it whole
+        ob.touch(&a, PathBuf::from("/w/a/x"));
+        ob.enqueue([a.clone()]);
+        let due = ob.take_due(now + delay).expect("due");
+        assert_eq!(ob.sources(&due), vec![a.clone()]);
+        ob.on_applied(&due);
+        ob.touch(&a, a.clone());
+        ob.enqueue_changed([a.clone()]);
+        let due = ob.take_due(now + delay).expect("due");
+        assert_eq!(ob.sources(&due), vec![a]);
+    }
+
+    #[test]
+    fn busy_broadcast_without_push_does_not_back_off() {
+        let mut ob = outbox();
+        let now = Instant::now();
+        ob.enqueue([PathBuf::from("/w/a")]);
+        assert_eq!(ob.on_busy(now), None);
+        assert!(ob.take_due(now).is_some());
+    }
+
+    #[test]
+    fn in_flight_blocks_resend_until_ack() {
+        let mut ob = outbox();
+        let now = Instant::now();
+        ob.enqueue([PathBuf::from("/w/a")]);
+        assert!(ob.take_due(now).is_some());
+        ob.enqueue([PathBuf::from("/w/b")]);
+        assert_eq!(ob.take_due(now + Duration::from_secs(1)), None);
+
+        ob.on_applied(&[PathBuf::from("/w/a")]);
+        assert_eq!(
+            ob.take_due(now + Duration::from_secs(1)),
+            Some(vec![PathBuf::from("/w/b")])
+        );
+    }
+
+    #[test]
+    fn unanswered_push_is_resent_after_timeout() {
+        let mut ob = outbox();
+        let now = Instant::now();
+        ob.enqueue([PathBuf::from("/w/a")]);
+        assert!(ob.take_due(now).is_some());
+        assert_eq!(
+            ob.take_due(now + IN_FLIGHT_TIMEOUT),
+            Some(vec![PathBuf::from("/w/a")])
+        );
+    }
+
+    #[test]
+    fn paused_anchor_stays_queued_until_resumed() {
+        let mut ob = outbox();
+        let now = Instant::now();
+        ob.pause([PathBuf::from("/w/a")]);
+        ob.enqueue([PathBuf::from("/w/a"), PathBuf::from("/w/b")]);
+        assert_eq!(ob.take_due(now), Some(vec![PathBuf::from("/w/b")]));
+        ob.on_applied(&[PathBuf::from("/w/b")]);
+        assert_eq!(ob.take_due(now), None);
+        assert_eq!(ob.pending_len(), 1);
+
+        ob.resume(&[PathBuf::from("/w/a")]);
+        assert_eq!(ob.take_due(now), Some(vec![PathBuf::from("/w/a")]));
+    }
+
+    #[test]
+    fn ack_resets_backoff() {
+        let mut ob = outbox();
+        let now = Instant::now();
+        ob.enqueue([PathBuf::from("/w/a")]);
+        let _ = ob.take_due(now);
+        let _ = ob.on_busy(now);
+        let _ = ob.take_due(now + Duration::from_secs(2));
+        ob.on_applied(&[PathBuf::from("/w/a")]);
+        assert!(ob.is_empty());
+        assert_eq!(ob.retry_in(now), Duration::ZERO);
+    }
+}
@@ -1,6 +1,6 @@
 //! Composed runtime parameters (shared logging + role-specific fields). Client commands use TOML paths
-//! (`system_config`, `user_configs`); the server daemon uses only [`crate::server`]’s sync root and
-//! persisted generation / client id state — see [`crate::config`] for the split between client and server configuration.
+//! (`system_config`, `user_configs`); the server daemon uses its own server TOML (`config`) plus
+//! [`crate::server`]’s sync root and persisted generation / client id state — see [`crate::config`] for the split between client and server configuration.
 
 use clap::{parser::ValuesRef, ArgMatches};
 use std::{
@@ -12,6 +12,9 @@ use std::{
 use crate::{config, fancy, outcome::Outcome};
 use log::{debug, error};
 
+/// Instance name used when `--instance` is not given.
+pub const DEFAULT_INSTANCE: &str = "default";
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum DaemonType {
     UnixClient,
@@ -20,12 +23,33 @@ pub enum DaemonType {
     WindowsServer,
 }
 
+impl DaemonType {
+    /// `client` or `server`, as used in Zenoh topics and runtime file names.
+    #[must_use]
+    pub fn role(self) -> &'static str {
+        match self {
+            DaemonType::UnixClient | DaemonType::WindowsClient => "client",
+            DaemonType::UnixServer | DaemonType::WindowsServer => "server",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SharedDaemonParams {
     pub daemon_type: DaemonType,
     pub verbosity: u8,
     pub debug: u8,
     pub log_path: PathBuf,
+    /// Distinguishes several daemons of the same role on one host (`--instance`).
+    pub instance: String,
+}
+
+impl SharedDaemonParams {
+    /// `<role>-<instance>`, the stem for this daemon's socket (and other runtime files).
+    #[must_use]
+    pub fn daemon_name(&self) -> String {
+        format!("{}-{}", self.daemon_type.role(), self.instance)
+    }
 }
 
 impl fmt::Display for SharedDaemonParams {
@@ -37,11 +61,13 @@ daemon_type:{:?}
 verbosity:{}
 debug:{}
 log_path:{}
+instance:{}
 ",
                 self.daemon_type,
                 self.verbosity,
                 self.debug,
                 self.log_path.display(),
+                self.instance,
             ),
             fancy::Attrs::Bold,
             fancy::Colors::Yellow,
@@ -81,11 +107,14 @@ impl fmt::Display for ClientParameters {
 #[derive(Clone, Debug)]
 pub struct ServerParameters {
     pub shared: SharedDaemonParams,
+    /// Server TOML; a missing default file means built-in settings.
+    pub config: Arc<PathBuf>,
 }
 
 impl fmt::Display for ServerParameters {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.shared.fmt(f)
+        writeln!(f, "{}", self.shared)?;
+        writeln!(f, "server_config:{}", self.config.display())
     }
 }
 
@@ -117,7 +146,7 @@ impl DaemonParameters {
         let debug = matches.get_count("debug");
         create_log_dir(debug)?;
 
-        let (daemon_type, system_config, user_configs, client_state_dir_override) =
+        let (daemon_type, system_config, user_configs, client_state_dir_override, server_config) =
             match matches.subcommand() {
                 Some(("client", client_m)) => {
                     let windows = client_m.get_flag("windows-daemon");
@@ -135,6 +164,7 @@ impl DaemonParameters {
                         client_m.get_one("system-config"),
                         client_m.get_many("user-configs"),
                         client_state_dir_override,
+                        None,
                     )
                 }
                 Some(("server", server_m)) => {
@@ -144,10 +174,21 @@ impl DaemonParameters {
                     } else {
                         DaemonType::UnixServer
                     };
-                    (daemon_type, None, None, None)
+                    (
+                        daemon_type,
+                        None,
+                        None,
+                        None,
+                        server_m.get_one::<String>("server-config"),
+                    )
                 }
                 _ => return bad!("expected `client` or `server` subcommand"),
             };
+        let instance = matches
+            .subcommand()
+            .and_then(|(_, m)| m.get_one::<String>("instance"))
+            .map_or(DEFAULT_INSTANCE, |s| s.trim());
+        validate_instance(instance)?;
 
         let debug_level = match debug {
             1 | 2 => debug,
@@ -168,7 +209,8 @@ impl DaemonParameters {
             daemon_type,
             verbosity,
             debug: debug_level,
-            log_path: get_log_path(debug, daemon_type),
+            log_path: get_log_path(debug, daemon_type, instance),
+            instance: instance.to_string(),
         };
 
         let params = match daemon_type {
@@ -178,9 +220,10 @@ impl DaemonParameters {
                 user_configs: resolve_user_configs(user_configs)?,
                 client_state_dir_override,
             }),
-            DaemonType::UnixServer | DaemonType::WindowsServer => {
-                Self::Server(ServerParameters { shared })
-            }
+            DaemonType::UnixServer | DaemonType::WindowsServer => Self::Server(ServerParameters {
+                shared,
+                config: resolve_server_config(server_config)?,
+            }),
         };
 
         if params.shared().debug > 0 {
@@ -213,10 +256,26 @@ fn create_log_dir(debug: u8) -> Outcome<()> {
     }
 }
 
-fn get_log_path(debug: u8, daemon_type: DaemonType) -> PathBuf {
-    let file = match daemon_type {
-        DaemonType::UnixClient | DaemonType::WindowsClient => "client.log",
-        DaemonType::UnixServer | DaemonType::WindowsServer => "server.log",
+/// Instance names end up in Zenoh key expressions and file names: keep them plain.
+fn validate_instance(instance: &str) -> Outcome<()> {
+    let plain = instance
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if instance.is_empty() || !plain {
+        return bad!(
+            "invalid --instance '{}': use letters, digits, '-' or '_'",
+            instance
+        );
+    }
+    Ok(())
+}
+
+fn get_log_path(debug: u8, daemon_type: DaemonType, instance: &str) -> PathBuf {
+    let role = daemon_type.role();
+    let file = if instance == DEFAULT_INSTANCE {
+        format!("{role}.log")
+    } else {
+        format!("{role}-{instance}.log")
     };
     log_base_dir(debug).join(file)
 }
@@ -253,6 +312,32 @@ fn resolve_system_config(system_config: Option<&String>) -> Outcome<Arc<PathBuf>
     Ok(Arc::new(cfg_path))
 }
 
+fn resolve_server_config(server_config: Option<&String>) -> Outcome<Arc<PathBuf>> {
+    let cfg_path = if let Some(srv_cfg) = server_config {
+        let normalized =
+            config::resolve(srv_cfg).map_err(|e| format!("server config path error: {e}"))?;
+        if normalized.is_dir() {
+            return bad!(
+                "{} is a directory not a file, aborting",
+                normalized.display()
+            );
+        } else if !normalized.exists() {
+            return bad!("{} does not exist", normalized.display());
+        }
+        normalized
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from("/opt/sinkd/sinkd-server.conf")
+    } else if cfg!(target_os = "windows") {
+        PathBuf::from("/somepath/sinkd-server.conf")
+    } else {
+        PathBuf::from("/etc/sinkd-server.conf")
+    };
+
+    debug!("server config: {}", cfg_path.display());
+
+    Ok(Arc::new(cfg_path))
+}
+
 pub fn resolve_user_configs(user_configs: Option<ValuesRef<String>>) -> Outcome<Arc<Vec<PathBuf>>> {
     let mut resolved_configs = Vec::<PathBuf>::new();
 
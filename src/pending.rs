@@ -0,0 +1,159 @@
+//! On-disk journal of changes the client has observed but the server has not acked yet.
+//!
+//! `pending.toml` in the client state dir mirrors the [`crate::outbox::Outbox`] anchors and the
+//! `local_dirty` file set. [`crate::client::init`] replays it, so a crash, OOM kill or reboot
+//! before the server says `Ready` does not forget what still needs pushing.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{durable, outcome::Outcome};
+
+const PENDING_FILE: &str = "pending.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingState {
+    /// Anchors queued or in flight.
+    #[serde(default)]
+    pub anchors: BTreeSet<PathBuf>,
+    /// Files changed locally since the last ack.
+    #[serde(default)]
+    pub dirty: BTreeSet<PathBuf>,
+}
+
+impl PendingState {
+    /// Anchors to push on replay: queued ones plus every anchor holding a dirty file.
+    #[must_use]
+    pub fn anchors_to_replay<'a, I>(&self, known_anchors: I) -> BTreeSet<PathBuf>
+    where
+        I: IntoIterator<Item = &'a PathBuf>,
+    {
+        let mut out = self.anchors.clone();
+        for anchor in known_anchors {
+            if self.dirty.iter().any(|d| d.starts_with(anchor)) {
+                out.insert(anchor.clone());
+            }
+        }
+        out
+    }
+}
+
+/// Shared by the watch thread (dirty files) and the Zenoh thread (queued anchors).
+#[derive(Debug)]
+pub struct PendingJournal {
+    path: PathBuf,
+    state: Mutex<PendingState>,
+}
+
+impl PendingJournal {
+    /// Opens the journal in `client_state_dir`, returning what a previous run left behind.
+    #[must_use]
+    pub fn open(client_state_dir: &Path) -> (Self, PendingState) {
+        let path = client_state_dir.join(PENDING_FILE);
+        let state = load(&path);
+        (
+            PendingJournal {
+                path,
+                state: Mutex::new(state.clone()),
+            },
+            state,
+        )
+    }
+
+    pub fn save_dirty(&self, dirty: &HashSet<PathBuf>) -> Outcome<()> {
+        self.update(|st| st.dirty = dirty.iter().cloned().collect())
+    }
+
+    pub fn save_anchors(&self, anchors: BTreeSet<PathBuf>) -> Outcome<()> {
+        self.update(|st| st.anchors = anchors)
+    }
+
+    fn update<F: FnOnce(&mut PendingState)>(&self, f: F) -> Outcome<()> {
+        let mut st = self
+            .state
+            .lock()
+            .map_err(|e| format!("pending journal lock: {e}"))?;
+        let before = st.clone();
+        f(&mut st);
+        if *st == before {
+            return Ok(());
+        }
+        let serialized =
+            toml::to_string(&*st).map_err(|e| format!("serialize pending journal: {e}"))?;
+        durable::write_atomic(&self.path, serialized.as_bytes())
+    }
+}
+
+fn load(path: &Path) -> PendingState {
+    let Ok(raw) = fs::read_to_string(path) else {
+        return PendingState::default();
+    };
+    toml::from_str(&raw).unwrap_or_else(|e| {
+        warn!(
+            "client: ignoring unreadable pending journal '{}': {e}",
+            path.display()
+        );
+        PendingState::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeSet, HashSet},
+        path::PathBuf,
+    };
+
+    use super::{PendingJournal, PendingState};
+
+    #[test]
+    fn journal_survives_reopen() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        {
+            let (journal, replay) = PendingJournal::open(tmp.path());
+            assert_eq!(replay, PendingState::default());
+            journal
+                .save_anchors(BTreeSet::from([PathBuf::from("/w/a")]))
+                .expect("anchors");
+            journal
+                .save_dirty(&HashSet::from([PathBuf::from("/w/b/notes.md")]))
+                .expect("dirty");
+        }
+        let (_journal, replay) = PendingJournal::open(tmp.path());
+        assert!(replay.anchors.contains(&PathBuf::from("/w/a")));
+        assert!(replay.dirty.contains(&PathBuf::from("/w/b/notes.md")));
+    }
+
+    #[test]
+    fn replay_maps_dirty_files_to_their_anchors() {
+        let state = PendingState {
+            anchors: BTreeSet::from([PathBuf::from("/w/a")]),
+            dirty: BTreeSet::from([PathBuf::from("/w/b/notes.md")]),
+        };
+        let known = [
+            PathBuf::from("/w/a"),
+            PathBuf::from("/w/b"),
+            PathBuf::from("/w/c"),
+        ];
+        let replay = state.anchors_to_replay(known.iter());
+        assert_eq!(
+            replay,
+            BTreeSet::from([PathBuf::from("/w/a"), PathBuf::from("/w/b")])
+        );
+    }
+
+    #[test]
+    fn corrupt_journal_starts_empty() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        std::fs::write(tmp.path().join("pending.toml"), "anchors = [").expect("seed");
+        let (_journal, replay) = PendingJournal::open(tmp.path());
+        assert_eq!(replay, PendingState::default());
+    }
+}
@@ -0,0 +1,261 @@
+//! Clients the server has heard from: who they are and how far they have synced.
+//!
+//! Every client payload carries its `client_id`, hostname and user, and its
+//! `basis_generation` is the generation that client last reconciled with. Only payloads the
+//! authorizer accepted are recorded, so refused senders never show up. The registry lives
+//! in `clients.toml` under the sync root so devices outlive a server restart; `sinkd server
+//! clients` lists them, labels them and forgets them.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{ipc, outcome::Outcome};
+
+pub const REGISTRY_FILE: &str = "clients.toml";
+
+/// A message that only moves `last_seen_unix` is written out at most this often.
+const SEEN_SAVE_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientRecord {
+    pub client_id: String,
+    /// Name given with `sinkd server clients label`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Device name the client reports.
+    pub hostname: String,
+    pub username: String,
+    pub first_seen_unix: i64,
+    pub last_seen_unix: i64,
+    /// Newest generation applied from this client's pushes (0: none yet).
+    pub last_pushed_generation: u64,
+    /// The client's `acked_generation`, as of its last message.
+    pub acked_generation: u64,
+}
+
+impl ClientRecord {
+    /// The label if there is one, else the device name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.hostname)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Persisted {
+    clients: Vec<ClientRecord>,
+}
+
+#[derive(Debug, Default)]
+pub struct Registry {
+    path: PathBuf,
+    clients: BTreeMap<String, ClientRecord>,
+    saved_unix: i64,
+}
+
+impl Registry {
+    /// Reads `path`; a missing file is an empty registry.
+    pub fn load(path: &Path) -> Outcome<Registry> {
+        let mut reg = Registry {
+            path: path.to_path_buf(),
+            ..Registry::default()
+        };
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(reg),
+            Err(e) => return bad!("read client registry '{}': {e}", path.display()),
+        };
+        let p: Persisted = toml::from_str(&raw)
+            .map_err(|e| format!("parse client registry '{}': {e}", path.display()))?;
+        reg.clients = p
+            .clients
+            .into_iter()
+            .map(|c| (c.client_id.clone(), c))
+            .collect();
+        Ok(reg)
+    }
+
+    fn save(&mut self, now_unix: i64) -> Outcome<()> {
+        let p = Persisted {
+            clients: self.clients(),
+        };
+        let serialized =
+            toml::to_string(&p).map_err(|e| format!("serialize client registry: {e}"))?;
+        fs::write(&self.path, serialized)
+            .map_err(|e| format!("write client registry '{}': {e}", self.path.display()))?;
+        self.saved_unix = now_unix;
+        Ok(())
+    }
+
+    // losing a last-seen update is not worth failing a push over
+    fn save_or_warn(&mut self, now_unix: i64) {
+        if let Err(e) = self.save(now_unix) {
+            warn!("{e}");
+        }
+    }
+
+    /// Notes a message from a client; anonymous payloads are ignored.
+    pub fn observe(&mut self, payload: &ipc::Payload, now_unix: i64) {
+        if payload.client_id.is_empty() {
+            return;
+        }
+        let record = self
+            .clients
+            .entry(payload.client_id.clone())
+            .or_insert_with(|| ClientRecord {
+                client_id: payload.client_id.clone(),
+                first_seen_unix: now_unix,
+                ..ClientRecord::default()
+            });
+        let changed = record.hostname != payload.hostname
+            || record.username != payload.username
+            || record.acked_generation != payload.basis_generation;
+        record.hostname.clone_from(&payload.hostname);
+        record.username.clone_from(&payload.username);
+        record.last_seen_unix = now_unix;
+        record.acked_generation = payload.basis_generation;
+        if changed || now_unix - self.saved_unix >= SEEN_SAVE_SECS {
+            self.save_or_warn(now_unix);
+        }
+    }
+
+    /// Records that `generation` was applied from `client_id`'s push.
+    pub fn pushed(&mut self, client_id: &str, generation: u64, now_unix: i64) {
+        let Some(record) = self.clients.get_mut(client_id) else {
+            return;
+        };
+        record.last_pushed_generation = generation;
+        self.save_or_warn(now_unix);
+    }
+
+    /// The `client_id` that `key` names: an id, a label, or a prefix of exactly one id.
+    pub fn resolve(&self, key: &str) -> Outcome<String> {
+        if self.clients.contains_key(key) {
+            return Ok(key.to_string());
+        }
+        let by_label: Vec<_> = self
+            .clients
+            .values()
+            .filter(|c| c.label.as_deref() == Some(key))
+            .collect();
+        let matches = if by_label.is_empty() {
+            self.clients
+                .keys()
+                .filter(|id| id.starts_with(key))
+                .collect::<Vec<_>>()
+        } else {
+            by_label.iter().map(|c| &c.client_id).collect()
+        };
+        match matches.as_slice() {
+            [id] => Ok((*id).clone()),
+            [] => bad!("no client matches '{}'", key),
+            _ => bad!(
+                "'{key}' matches {} clients; use more of the id",
+                matches.len()
+            ),
+        }
+    }
+
+    /// Names the client `key` resolves to; `None` clears the label.
+    pub fn label(&mut self, key: &str, label: Option<String>, now_unix: i64) -> Outcome<String> {
+        let id = self.resolve(key)?;
+        if let Some(record) = self.clients.get_mut(&id) {
+            record.label = label.filter(|l| !l.is_empty());
+        }
+        self.save(now_unix)?;
+        Ok(id)
+    }
+
+    /// Drops the client `key` resolves to. It comes back if it talks to the server again.
+    pub fn forget(&mut self, key: &str, now_unix: i64) -> Outcome<ClientRecord> {
+        let id = self.resolve(key)?;
+        let record = self
+            .clients
+            .remove(&id)
+            .ok_or_else(|| format!("no client '{id}'"))?;
+        self.save(now_unix)?;
+        Ok(record)
+    }
+
+    /// Every known client, by `client_id`.
+    #[must_use]
+    pub fn clients(&self) -> Vec<ClientRecord> {
+        self.clients.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::Registry;
+    use crate::ipc;
+
+    fn payload(client_id: &str, basis_generation: u64) -> ipc::Payload {
+        ipc::Payload::from(
+            "laptop".to_string(),
+            "alice".to_string(),
+            vec![],
+            PathBuf::new(),
+            String::new(),
+            client_id.to_string(),
+            basis_generation,
+            0,
+            String::new(),
+            ipc::Status::Ready,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn observe_tracks_latest_message_per_client() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let mut reg = Registry::load(&tmp.path().join("clients.toml")).expect("load");
+        reg.observe(&payload("", 0), 5);
+        assert!(reg.clients().is_empty(), "no client_id");
+
+        reg.observe(&payload("id-1", 3), 10);
+        reg.observe(&payload("id-1", 4), 20);
+        reg.pushed("id-1", 5, 20);
+        let clients = reg.clients();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(
+            (
+                clients[0].first_seen_unix,
+                clients[0].last_seen_unix,
+                clients[0].acked_generation,
+                clients[0].last_pushed_generation
+            ),
+            (10, 20, 4, 5)
+        );
+    }
+
+    #[test]
+    fn registry_survives_reload_and_resolves_labels_and_prefixes() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("clients.toml");
+        let mut reg = Registry::load(&path).expect("load");
+        reg.observe(&payload("abc-111", 1), 10);
+        reg.observe(&payload("abd-222", 1), 10);
+        reg.label("abc", Some("work laptop".to_string()), 11)
+            .expect("label by prefix");
+        assert!(reg.resolve("ab").is_err(), "ambiguous prefix");
+
+        let mut reg = Registry::load(&path).expect("reload");
+        assert_eq!(reg.resolve("work laptop").expect("label"), "abc-111");
+        assert_eq!(reg.clients()[0].name(), "work laptop");
+        assert_eq!(reg.clients()[1].name(), "laptop");
+        reg.forget("abd", 12).expect("forget");
+        assert!(reg.resolve("abd").is_err());
+        assert_eq!(Registry::load(&path).expect("reload").clients().len(), 1);
+    }
+}
@@ -1,7 +1,12 @@
 use log::{debug, error};
 
-use std::{ffi::OsStr, path::Path, process::Command};
+use std::{
+    ffi::OsStr,
+    path::Path,
+    process::{Command, Stdio},
+};
 
+use crate::changes::{self, Itemized};
 use crate::config::ResolvedRsyncConfig;
 use crate::outcome::Outcome;
 
@@ -14,6 +19,54 @@ pub fn rsync<P>(
 where
     P: AsRef<OsStr> + AsRef<Path> + std::fmt::Debug,
 {
+    let mut cmd = Command::new("rsync");
+    cmd.args(build_pull_args(rsync_cfg, backup_dir))
+        .args(srcs)
+        .arg(dest);
+    run(cmd, false)?;
+    debug!("\u{1f6b0} rsync {srcs:#?} {dest:#?} backup:{backup_dir:?} \u{1f919}");
+    Ok(())
+}
+
+/// Like [`rsync`] without backups, but returns what the transfer created, updated or deleted.
+pub fn rsync_itemized<P>(srcs: &[P], dest: &P, rsync_cfg: &ResolvedRsyncConfig) -> Outcome<Itemized>
+where
+    P: AsRef<OsStr> + AsRef<Path> + std::fmt::Debug,
+{
+    let mut cmd = Command::new("rsync");
+    cmd.args(build_args(rsync_cfg))
+        .arg(changes::ITEMIZE_ARG)
+        .arg(changes::EIGHT_BIT_ARG)
+        .args(srcs)
+        .arg(dest);
+    let out = run(cmd, true)?;
+    let itemized = changes::parse_itemized(&out);
+    debug!(
+        "\u{1f6b0} rsync {srcs:#?} {dest:#?}: {} changed, {} deleted, {} bytes sent \u{1f919}",
+        itemized.changes.changed.len(),
+        itemized.changes.deleted.len(),
+        itemized.sent_bytes
+    );
+    Ok(itemized)
+}
+
+/// `rsync -rt` of `src` into `dest`, copying only what the `filters` (rsync filter rules,
+/// e.g. `"+ /*/"`) let through.
+pub fn fetch_filtered<P>(src: &P, dest: &Path, filters: &[&str]) -> Outcome<()>
+where
+    P: AsRef<OsStr> + ?Sized,
+{
+    let mut cmd = Command::new("rsync");
+    cmd.arg("-rt");
+    for rule in filters {
+        cmd.arg(format!("--filter={rule}"));
+    }
+    cmd.arg(src).arg(dest);
+    run(cmd, true).map(|_| ())
+}
+
+/// Runs `cmd` to completion; with `capture`, returns its stdout instead of inheriting it.
+fn run(mut cmd: Command, capture: bool) -> Outcome<String> {
     if crate::test_hooks::env_flag_true("SINKD_TEST_RSYNC_FAIL") {
         error!("rsync test hook: forced failure");
         return bad!("rsync test hook: forced failure");
@@ -22,34 +75,30 @@ where
         std::thread::sleep(std::time::Duration::from_millis(delay_ms));
     }
 
-    let mut cmd = Command::new("rsync");
-    cmd.args(build_pull_args(rsync_cfg, backup_dir))
-        .args(srcs)
-        .arg(dest);
-
-    let mut child = match cmd.spawn() {
+    if capture {
+        cmd.stdout(Stdio::piped());
+    }
+    let child = match cmd.spawn() {
         Err(e) => {
             error!("rsync spawn error: {e:#?}");
-            return bad!("rsync spawn failed: {e}");
+            return bad!("rsync spawn failed: {}", e);
         }
         Ok(c) => c,
     };
 
-    let status = match child.wait() {
+    let output = match child.wait_with_output() {
         Err(e) => {
             error!("rsync wait error: {e:#?}");
-            return bad!("rsync wait failed: {e}");
+            return bad!("rsync wait failed: {}", e);
         }
-        Ok(s) => s,
+        Ok(o) => o,
     };
 
-    if !status.success() {
-        error!("rsync exited with status {status}");
-        return bad!("rsync failed with status {status}");
+    if !output.status.success() {
+        error!("rsync exited with status {}", output.status);
+        return bad!("rsync failed with status {}", output.status);
     }
-
-    debug!("\u{1f6b0} rsync {srcs:#?} {dest:#?} backup:{backup_dir:?} \u{1f919}");
-    Ok(())
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
 #[must_use]
@@ -85,6 +134,9 @@ pub fn build_args(rsync_cfg: &ResolvedRsyncConfig) -> Vec<String> {
     if rsync_cfg.stats {
         args.push("--stats".to_string());
     }
+    for pattern in &rsync_cfg.excludes {
+        args.push(format!("--exclude={pattern}"));
+    }
     args
 }
 
@@ -129,6 +181,7 @@ mod tests {
             ignore_existing: true,
             size_only: true,
             stats: true,
+            excludes: vec!["*.swp".to_string(), ".DS_Store".to_string()],
         };
         let args = build_args(&cfg);
         assert_eq!(
@@ -146,6 +199,8 @@ mod tests {
                 "--ignore-existing",
                 "--size-only",
                 "--stats",
+                "--exclude=*.swp",
+                "--exclude=.DS_Store",
             ]
         );
     }
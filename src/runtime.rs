@@ -0,0 +1,234 @@
+//! Per-user runtime directory for daemon sockets, lock and PID files (things that must not
+//! outlive a boot).
+//!
+//! Debug builds share `/tmp/sinkd/run` so scenario scripts can find everything in one place;
+//! otherwise `$XDG_RUNTIME_DIR/sinkd`, `/run/sinkd` for root, or `/tmp/sinkd-<uid>`.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::{outcome::Outcome, status};
+
+/// Files one daemon (role + instance) keeps in its runtime dir.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeFiles {
+    pub dir: PathBuf,
+    pub socket: PathBuf,
+    pub lock: PathBuf,
+    pub pid: PathBuf,
+}
+
+impl RuntimeFiles {
+    /// `<dir>/<name>.sock`, `.lock` and `.pid`, with `name` from
+    /// [`crate::parameters::SharedDaemonParams::daemon_name`].
+    #[must_use]
+    pub fn new(dir: &Path, name: &str) -> Self {
+        RuntimeFiles {
+            dir: dir.to_path_buf(),
+            socket: dir.join(format!("{name}.sock")),
+            lock: dir.join(format!("{name}.lock")),
+            pid: dir.join(format!("{name}.pid")),
+        }
+    }
+}
+
+#[must_use]
+pub fn runtime_dir(debug: u8) -> PathBuf {
+    if debug > 0 {
+        return PathBuf::from("/tmp/sinkd/run");
+    }
+    if cfg!(target_os = "windows") {
+        return PathBuf::from(r"C:\ProgramData\sinkd\run");
+    }
+    if let Ok(xdg) = std::env::var("XDG_RUNTIME_DIR") {
+        let xdg = xdg.trim();
+        if !xdg.is_empty() {
+            return PathBuf::from(xdg).join("sinkd");
+        }
+    }
+    #[cfg(unix)]
+    {
+        let uid = unsafe { libc::geteuid() };
+        if uid == 0 {
+            PathBuf::from("/run/sinkd")
+        } else {
+            PathBuf::from(format!("/tmp/sinkd-{uid}"))
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        std::env::temp_dir().join("sinkd")
+    }
+}
+
+/// Creates `dir` if needed; on Unix it is restricted to the owner (`0700`).
+pub fn ensure_dir(dir: &Path) -> Outcome<()> {
+    fs::create_dir_all(dir).map_err(|e| format!("runtime dir '{}': {e}", dir.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .map_err(|e| format!("runtime dir '{}' permissions: {e}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Exclusive `flock` on the daemon's lock file. Taken by `start` before forking; the daemon
+/// inherits the descriptor, so the lock lasts exactly as long as the daemon process.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+    pid_path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Refuses with the running pid when another process holds the lock; removes a PID file
+    /// left behind by a crashed daemon.
+    pub fn acquire(files: &RuntimeFiles) -> Outcome<Self> {
+        ensure_dir(&files.dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&files.lock)
+            .map_err(|e| format!("open lock file '{}': {e}", files.lock.display()))?;
+        if !try_lock(&file)? {
+            let holder = read_pid(&files.pid)
+                .map(|pid| format!(" (pid {pid})"))
+                .unwrap_or_default();
+            return bad!(
+                "already running{holder}: '{}' is locked; stop it first or use another --instance",
+                files.lock.display()
+            );
+        }
+        if let Some(pid) = read_pid(&files.pid) {
+            warn!(
+                "removing stale pid file '{}' (pid {pid})",
+                files.pid.display()
+            );
+            let _ = fs::remove_file(&files.pid);
+        }
+        Ok(InstanceLock {
+            _file: file,
+            pid_path: files.pid.clone(),
+        })
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // the parent that forked the daemon drops its copy too: only the daemon removes the file
+        if read_pid(&self.pid_path) == Some(std::process::id()) {
+            let _ = fs::remove_file(&self.pid_path);
+        }
+    }
+}
+
+/// Records the daemon's own pid; call from the daemonized process.
+pub fn write_pid(path: &Path) -> Outcome<()> {
+    fs::write(path, format!("{}\n", std::process::id()))
+        .map_err(|e| format!("write pid file '{}': {e}", path.display()).into())
+}
+
+/// Waits until nobody holds `files.lock` (the daemon process has fully exited).
+pub fn wait_unlocked(files: &RuntimeFiles, timeout: Duration) -> Outcome<()> {
+    let Ok(file) = File::open(&files.lock) else {
+        return Ok(());
+    };
+    let deadline = Instant::now() + timeout;
+    while !try_lock(&file)? {
+        if Instant::now() >= deadline {
+            return bad!(
+                "daemon still holds '{}' after {}s",
+                files.lock.display(),
+                timeout.as_secs()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+/// Whether a daemon holds `files.lock`, i.e. is running; its PID file and socket can outlive it.
+pub fn is_locked(files: &RuntimeFiles) -> Outcome<bool> {
+    let Ok(file) = File::open(&files.lock) else {
+        return Ok(false);
+    };
+    // closing `file` lets go of the lock again if we got it
+    Ok(!try_lock(&file)?)
+}
+
+/// Pid recorded in `path`, if that process is still around.
+#[must_use]
+pub fn read_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .filter(|pid| status::pid_alive(*pid))
+}
+
+/// Non-blocking exclusive lock; `false` when another open file description holds it.
+fn try_lock(file: &File) -> Outcome<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(true);
+        }
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+            Ok(false)
+        } else {
+            bad!("flock: {}", err)
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = file;
+        Ok(true)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs;
+
+    use super::{is_locked, write_pid, InstanceLock, RuntimeFiles};
+
+    #[test]
+    fn second_acquire_is_refused_until_first_is_dropped() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let files = RuntimeFiles::new(tmp.path(), "client-default");
+        let first = InstanceLock::acquire(&files).expect("first");
+        write_pid(&files.pid).expect("pid");
+        let err = InstanceLock::acquire(&files).expect_err("second start must fail");
+        assert!(
+            err.to_string()
+                .contains(&format!("pid {}", std::process::id())),
+            "{err}"
+        );
+        assert!(is_locked(&files).expect("probe"));
+        drop(first);
+        assert!(!files.pid.exists());
+        assert!(
+            !is_locked(&files).expect("probe"),
+            "probing must not keep it"
+        );
+        InstanceLock::acquire(&files).expect("after release");
+    }
+
+    #[test]
+    fn stale_pid_file_is_removed() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let files = RuntimeFiles::new(tmp.path(), "server-default");
+        fs::write(&files.pid, format!("{}\n", std::process::id())).expect("seed");
+        let _lock = InstanceLock::acquire(&files).expect("acquire");
+        assert!(!files.pid.exists());
+    }
+}
@@ -5,6 +5,7 @@
 use log::{debug, error, info, warn};
 
 use std::{
+    collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
     process,
@@ -20,35 +21,67 @@ use std::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    config, ipc,
+    apply::ApplyQueue,
+    auth::{Authorizer, Pull},
+    changes::ChangeSet,
+    config, durable,
+    ipc::{
+        self,
+        control::{self, Reply, Request, Response},
+    },
+    journal::{self, JournalEntry, JOURNAL_FILE},
     outcome::Outcome,
-    parameters::{DaemonParameters, ServerParameters},
-    rsync::rsync,
+    parameters::{DaemonParameters, ServerParameters, SharedDaemonParams},
+    registry::{ClientRecord, Registry, REGISTRY_FILE},
+    rsync::rsync_itemized,
+    runtime::{self, InstanceLock, RuntimeFiles},
+    snapshot::Snapshotter,
+    staging::{self, Stage},
+    time,
+    usage::{Ledger, QuotaUsage, UserUsage},
 };
 
+const GENERATION_FILE: &str = "generation_state.toml";
 const GENERATION_HISTORY_TTL_SECS: i64 = 7 * 24 * 3600;
 const GENERATION_HISTORY_MAX: usize = 4096;
+/// History entries `server ls` shows.
+const LS_HISTORY: usize = 10;
 
 enum PostApply {
     Applied {
         writer_client_id: String,
+        push_id: u64,
+        paths: Vec<PathBuf>,
         head_generation: u64,
     },
     StaleAtApply {
         head_generation: u64,
     },
+    /// Nothing of the push landed; tell its writer why.
+    Failed {
+        writer_client_id: String,
+        push_id: u64,
+        paths: Vec<PathBuf>,
+        error: String,
+    },
 }
 
+/// What each generation changed lives in the journal; the state file only says when and who.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct HistoryEntry {
     generation: u64,
     saved_at_unix: i64,
+    /// Client whose push this was; empty for entries written before this was recorded.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    writer_client_id: String,
 }
 
 #[derive(Debug, Default)]
 struct GenerationState {
     current_generation: u64,
     history: Vec<HistoryEntry>,
+    /// The journal beside the state file, which [`GenerationState::changes_since`] reads.
+    journal: PathBuf,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -76,82 +109,525 @@ impl GenerationState {
     }
 
     /// Returns the new head generation.
-    fn bump(&mut self, now_unix: i64) -> u64 {
+    fn bump(&mut self, now_unix: i64, writer_client_id: &str) -> u64 {
         self.current_generation = self.current_generation.saturating_add(1);
         let g = self.current_generation;
         self.history.push(HistoryEntry {
             generation: g,
             saved_at_unix: now_unix,
+            writer_client_id: writer_client_id.to_string(),
         });
         self.prune_history(now_unix);
         g
     }
+
+    /// Everything applied after `since` up to the head that `client_id` did not write itself
+    /// (disjoint pushes land out of order, so its own generations can sit past its
+    /// `acked_generation`), or `None` when part of that range is missing from the journal.
+    fn changes_since(&self, since: u64, client_id: &str) -> Option<ChangeSet> {
+        // a client ahead of the head saw a different history (e.g. a reset server)
+        if since >= self.current_generation {
+            return (since == self.current_generation).then(ChangeSet::default);
+        }
+        let entries = journal::read_range(&self.journal, since + 1, Some(self.current_generation))
+            .map_err(|e| warn!("server: {e}"))
+            .ok()?;
+        // after a reset a generation can be journaled twice; the later line is the live one
+        let by_generation: BTreeMap<u64, JournalEntry> =
+            entries.into_iter().map(|e| (e.generation, e)).collect();
+        let mut merged = ChangeSet::default();
+        for generation in since + 1..=self.current_generation {
+            let entry = by_generation.get(&generation)?;
+            if client_id.is_empty() || entry.client_id != client_id {
+                merged.merge(&entry.changes());
+            }
+        }
+        Some(merged)
+    }
+
+    /// Whether a push based on `basis` would overwrite something applied after it. Pushes to
+    /// untouched anchors may land on a newer head; unknown history counts as touched.
+    fn touched_since(&self, basis: u64, paths: &[PathBuf], client_id: &str) -> bool {
+        basis != self.current_generation
+            && self
+                .changes_since(basis, client_id)
+                .is_none_or(|set| set.touches(paths))
+    }
 }
 
-fn load_generation_state(path: &Path) -> GenerationState {
-    let Ok(content) = fs::read_to_string(path) else {
-        return GenerationState::default();
-    };
-    let Ok(p) = toml::from_str::<PersistedGeneration>(&content) else {
-        warn!(
-            "server: unable to parse generation state '{}'",
-            path.display()
-        );
-        return GenerationState::default();
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    PathBuf::from(name)
+}
+
+fn journal_beside(state_path: &Path) -> PathBuf {
+    state_path.with_file_name(JOURNAL_FILE)
+}
+
+fn parse_generation_state(path: &Path) -> Outcome<Option<GenerationState>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return bad!("read '{}': {e}", path.display()),
     };
+    let p = toml::from_str::<PersistedGeneration>(&content)
+        .map_err(|e| format!("parse '{}': {e}", path.display()))?;
     let mut st = GenerationState {
         current_generation: p.current_generation,
         history: p.history,
+        journal: journal_beside(path),
     };
     st.prune_history(now_unix_secs());
-    st
+    Ok(Some(st))
+}
+
+/// The persisted state; none at all is generation 0. A corrupt or missing file is an error
+/// rather than a reset, which would put every client out of step (see [`reset_generation_state`]).
+fn load_generation_state(path: &Path) -> Outcome<GenerationState> {
+    let backup = backup_path(path);
+    let problem = match parse_generation_state(path) {
+        Ok(Some(st)) => return Ok(st),
+        Ok(None) if !backup.exists() => {
+            return Ok(GenerationState {
+                journal: journal_beside(path),
+                ..GenerationState::default()
+            })
+        }
+        Ok(None) => "is missing".to_string(),
+        Err(e) => format!("is unreadable ({e})"),
+    };
+    let fallback = match parse_generation_state(&backup) {
+        Ok(Some(st)) => format!(
+            "will restore generation {} from '{}'",
+            st.current_generation,
+            backup.display()
+        ),
+        _ => "will start over at generation 0".to_string(),
+    };
+    bad!(
+        "generation state '{}' {problem}; refusing to start. `sinkd server start --force-reset` {fallback}",
+        path.display()
+    )
 }
 
+/// Sets a corrupt state file aside and loads the backup, or starts over when that is unusable
+/// too.
+fn reset_generation_state(path: &Path) -> Outcome<GenerationState> {
+    if let Ok(Some(st)) = parse_generation_state(path) {
+        return Ok(st);
+    }
+    if path.exists() {
+        let aside = path.with_extension(format!("toml.corrupt-{}", now_unix_secs()));
+        fs::rename(path, &aside).map_err(|e| format!("move '{}' aside: {e}", path.display()))?;
+        warn!(
+            "server: moved corrupt generation state to '{}'",
+            aside.display()
+        );
+    }
+    let st = match parse_generation_state(&backup_path(path)) {
+        Ok(Some(st)) => GenerationState {
+            journal: journal_beside(path),
+            ..st
+        },
+        Ok(None) | Err(_) => GenerationState {
+            journal: journal_beside(path),
+            ..GenerationState::default()
+        },
+    };
+    warn!(
+        "server: generation state reset to generation {}",
+        st.current_generation
+    );
+    persist_generation_state(path, &st)?;
+    Ok(st)
+}
+
+/// Keeps the state being replaced as `<path>.bak` (when it is intact), then writes `state`
+/// atomically.
 fn persist_generation_state(path: &Path, state: &GenerationState) -> Outcome<()> {
     let p = PersistedGeneration {
         current_generation: state.current_generation,
         history: state.history.clone(),
     };
     let serialized = toml::to_string(&p).map_err(|e| format!("serialize generation state: {e}"))?;
-    fs::write(path, serialized)?;
-    Ok(())
+    if let Ok(previous) = fs::read_to_string(path) {
+        if toml::from_str::<PersistedGeneration>(&previous).is_ok() {
+            durable::write_atomic(&backup_path(path), previous.as_bytes())?;
+        }
+    }
+    durable::write_atomic(path, serialized.as_bytes())
 }
 
 fn needs_push_basis_check(payload: &ipc::Payload) -> bool {
     matches!(payload.status, ipc::Status::Ready) && !payload.src_paths.is_empty()
 }
 
-pub fn start(params: &ServerParameters) -> Outcome<()> {
+/// `force_reset` replaces a corrupt generation state instead of refusing to start.
+pub fn start(params: &ServerParameters, force_reset: bool) -> Outcome<()> {
     // No need to start mosquitto - Zenoh is peer-to-peer
+    // held across the fork: the daemon keeps the lock until it exits
+    let _lock = InstanceLock::acquire(&runtime_files(&params.shared))?;
+    // checked here, where the refusal can still be seen, rather than in the daemon
+    let generation_state_path = get_srv_dir(params.shared.debug).join(GENERATION_FILE);
+    if force_reset {
+        reset_generation_state(&generation_state_path)?;
+    } else {
+        load_generation_state(&generation_state_path)?;
+    }
     println!("logging to: {}", params.shared.log_path.display());
     ipc::daemon(&DaemonParameters::Server(params.clone()))
 }
 
-pub fn stop() -> Outcome<()> {
-    ipc::send_terminate_signal()
+fn runtime_files(shared: &SharedDaemonParams) -> RuntimeFiles {
+    RuntimeFiles::new(&runtime::runtime_dir(shared.debug), &shared.daemon_name())
+}
+
+pub fn stop(params: &ServerParameters) -> Outcome<()> {
+    let message = ipc::stop_daemon(&params.shared, &runtime_files(&params.shared))?;
+    println!("{message}");
+    Ok(())
+}
+
+/// Has the running daemon re-read its generation state from the sync root.
+pub fn reload(params: &ServerParameters) -> Outcome<()> {
+    let resp = control::request(&runtime_files(&params.shared).socket, &Request::Reload)?;
+    println!("{}", resp.message);
+    Ok(())
 }
 
-pub fn restart(params: &ServerParameters) -> Outcome<()> {
-    match stop() {
-        Ok(()) => start(params),
+pub fn restart(params: &ServerParameters, force_reset: bool) -> Outcome<()> {
+    match stop(params) {
+        Ok(()) => start(params, force_reset),
         Err(e) => bad!(e),
     }
 }
 
-pub fn ls(params: &ServerParameters) -> Outcome<()> {
-    let srv_dir = get_srv_dir(params.shared.debug);
-    println!("server sync root: {}", srv_dir.display());
-    let gen_path = srv_dir.join("generation_state.toml");
-    if gen_path.exists() {
-        let st = load_generation_state(&gen_path);
-        println!("current_generation: {}", st.current_generation);
+/// What `sinkd server ls` shows. A running daemon builds it for [`Request::Status`]; without
+/// one, `ls` fills in what the sync root alone can tell.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerReport {
+    pub running: bool,
+    pub pid: Option<u32>,
+    pub sync_root: PathBuf,
+    /// `Ready`, `Applying` while any push is being applied, or `stopped`.
+    pub status: String,
+    /// Anchors being applied right now.
+    pub busy_anchors: Vec<PathBuf>,
+    /// Pushes accepted but not started yet.
+    pub queue_depth: usize,
+    pub current_generation: u64,
+    /// Most recent generations, newest first.
+    pub history: Vec<HistoryLine>,
+    pub last_writer: Option<LastWriter>,
+    pub clients: Vec<ClientRecord>,
+    pub usage: Vec<UserUsage>,
+    /// Users and shares with a `[quotas]` limit.
+    pub quotas: Vec<QuotaUsage>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryLine {
+    pub generation: u64,
+    pub saved_at_unix: i64,
+    /// File counts, when the apply recorded them.
+    pub changed: Option<usize>,
+    pub deleted: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LastWriter {
+    pub generation: u64,
+    pub applied_at_unix: i64,
+    pub client_id: String,
+    pub hostname: String,
+    pub username: String,
+}
+
+/// The parts of a [`ServerReport`] that live in the sync root.
+fn stored_report(srv_dir: &Path, st: &GenerationState) -> ServerReport {
+    let shown: Vec<&HistoryEntry> = st.history.iter().rev().take(LS_HISTORY).collect();
+    let head = st.current_generation;
+    let oldest = shown.last().map_or(head, |e| e.generation);
+    let journaled: BTreeMap<u64, JournalEntry> =
+        journal::read_range(&srv_dir.join(JOURNAL_FILE), oldest, Some(head))
+            .unwrap_or_else(|e| {
+                warn!("server: {e}");
+                Vec::new()
+            })
+            .into_iter()
+            .map(|e| (e.generation, e))
+            .collect();
+    let history = shown
+        .iter()
+        .map(|e| {
+            let record = journaled.get(&e.generation);
+            HistoryLine {
+                generation: e.generation,
+                saved_at_unix: e.saved_at_unix,
+                changed: record.map(|r| r.changed.len()),
+                deleted: record.map(|r| r.deleted.len()),
+            }
+        })
+        .collect();
+    let last_writer = journaled.get(&head).cloned().map(|e| LastWriter {
+        generation: e.generation,
+        applied_at_unix: e.applied_at_unix,
+        client_id: e.client_id,
+        hostname: e.hostname,
+        username: e.username,
+    });
+    ServerReport {
+        sync_root: srv_dir.to_path_buf(),
+        current_generation: head,
+        history,
+        last_writer,
+        ..ServerReport::default()
+    }
+}
+
+pub fn ls(params: &ServerParameters, json: bool) -> Outcome<()> {
+    let live = control::request(&runtime_files(&params.shared).socket, &Request::Status)
+        .ok()
+        .and_then(|resp| resp.data)
+        .and_then(|data| serde_json::from_value::<ServerReport>(data).ok());
+    let report = if let Some(report) = live {
+        report
     } else {
-        println!("(no generation_state.toml yet)");
+        let srv_dir = get_srv_dir(params.shared.debug);
+        let st = load_generation_state(&srv_dir.join(GENERATION_FILE))?;
+        let ledger = Ledger::scan(config::load_server_config(&params.config)?.quotas, &srv_dir)?;
+        ServerReport {
+            status: "stopped".to_string(),
+            clients: Registry::load(&srv_dir.join(REGISTRY_FILE))?.clients(),
+            usage: ledger.users(),
+            quotas: ledger.quotas(),
+            ..stored_report(&srv_dir, &st)
+        }
+    };
+    if json {
+        let out = serde_json::to_string_pretty(&report)
+            .map_err(|e| format!("encode server report: {e}"))?;
+        println!("{out}");
+        return Ok(());
     }
+    print_report(&report);
     Ok(())
 }
 
-fn get_srv_dir(debug: u8) -> PathBuf {
+fn print_report(r: &ServerReport) {
+    println!("server sync root: {}", r.sync_root.display());
+    match r.pid {
+        Some(pid) => println!("status: {} (pid {pid})", r.status),
+        None => println!("status: {}", r.status),
+    }
+    println!("current_generation: {}", r.current_generation);
+    if r.running {
+        println!("queue: {} waiting", r.queue_depth);
+        for anchor in &r.busy_anchors {
+            println!("  applying {}", anchor.display());
+        }
+    }
+    if let Some(w) = &r.last_writer {
+        println!(
+            "last writer: {}@{} ({}) at generation {}, {}",
+            w.username,
+            w.hostname,
+            w.client_id,
+            w.generation,
+            time::local_stamp(w.applied_at_unix)
+        );
+    }
+    if !r.history.is_empty() {
+        println!("history:");
+    }
+    for h in &r.history {
+        let counts = match (h.changed, h.deleted) {
+            (Some(c), Some(d)) => format!("{c} changed, {d} deleted"),
+            _ => "(no change record)".to_string(),
+        };
+        println!(
+            "  {:>6}  {}  {counts}",
+            h.generation,
+            time::local_stamp(h.saved_at_unix)
+        );
+    }
+    if !r.clients.is_empty() {
+        println!("clients:");
+    }
+    for c in &r.clients {
+        print_client(c);
+    }
+    if !r.usage.is_empty() {
+        println!("disk usage:");
+    }
+    for u in &r.usage {
+        println!("  {:<16} {} bytes", u.user, u.bytes);
+    }
+    if !r.quotas.is_empty() {
+        println!("quotas:");
+    }
+    for q in &r.quotas {
+        let over = if q.used_bytes >= q.limit_bytes {
+            "  (exceeded)"
+        } else {
+            ""
+        };
+        println!(
+            "  {:<16} {} of {} bytes{over}",
+            q.owner, q.used_bytes, q.limit_bytes
+        );
+    }
+}
+
+fn print_client(c: &ClientRecord) {
+    println!(
+        "  {}  {} ({}@{})  last seen {}  pushed {}  acked {}",
+        c.client_id,
+        c.name(),
+        c.username,
+        c.hostname,
+        time::local_stamp(c.last_seen_unix),
+        c.last_pushed_generation,
+        c.acked_generation
+    );
+}
+
+/// The registry, from the running daemon if there is one (it holds the live copy), else from
+/// the sync root.
+fn with_registry<T>(
+    params: &ServerParameters,
+    request: &Request,
+    offline: impl FnOnce(&mut Registry) -> Outcome<T>,
+    online: impl FnOnce(Response) -> Outcome<T>,
+) -> Outcome<T> {
+    let files = runtime_files(&params.shared);
+    if runtime::is_locked(&files)? {
+        return online(control::request(&files.socket, request)?);
+    }
+    let srv_dir = get_srv_dir(params.shared.debug);
+    offline(&mut Registry::load(&srv_dir.join(REGISTRY_FILE))?)
+}
+
+/// Lists every client the server knows about.
+pub fn clients(params: &ServerParameters, json: bool) -> Outcome<()> {
+    let clients = with_registry(
+        params,
+        &Request::Clients,
+        |reg| Ok(reg.clients()),
+        |resp| {
+            serde_json::from_value::<Vec<ClientRecord>>(resp.data.unwrap_or_default())
+                .map_err(|e| format!("malformed client list: {e}").into())
+        },
+    )?;
+    if json {
+        let out = serde_json::to_string_pretty(&clients)
+            .map_err(|e| format!("encode client list: {e}"))?;
+        println!("{out}");
+        return Ok(());
+    }
+    if clients.is_empty() {
+        println!("(no clients yet)");
+    }
+    for c in &clients {
+        print_client(c);
+        println!("      first seen {}", time::local_stamp(c.first_seen_unix));
+    }
+    Ok(())
+}
+
+/// Names a client (`None` clears the name); `client` is an id, a label or an id prefix.
+pub fn label_client(params: &ServerParameters, client: &str, label: Option<&str>) -> Outcome<()> {
+    let label = label.map(str::to_string);
+    let message = with_registry(
+        params,
+        &Request::LabelClient {
+            client: client.to_string(),
+            label: label.clone(),
+        },
+        |reg| {
+            Ok(format!(
+                "labelled {}",
+                reg.label(client, label, now_unix_secs())?
+            ))
+        },
+        |resp| Ok(resp.message),
+    )?;
+    println!("{message}");
+    Ok(())
+}
+
+/// Drops a client from the registry; it reappears if it talks to the server again.
+pub fn forget_client(params: &ServerParameters, client: &str) -> Outcome<()> {
+    let message = with_registry(
+        params,
+        &Request::ForgetClient {
+            client: client.to_string(),
+        },
+        |reg| {
+            let gone = reg.forget(client, now_unix_secs())?;
+            Ok(format!("forgot {} ({})", gone.client_id, gone.name()))
+        },
+        |resp| Ok(resp.message),
+    )?;
+    println!("{message}");
+    Ok(())
+}
+
+/// Prints the change journal for generations `from..=to`, asking the running server if there is
+/// one and reading `journal.jsonl` directly otherwise.
+pub fn changes(params: &ServerParameters, from: u64, to: Option<u64>, json: bool) -> Outcome<()> {
+    let socket = runtime_files(&params.shared).socket;
+    let live = control::request(&socket, &Request::Changes { from, to })
+        .ok()
+        .and_then(|resp| resp.data)
+        .and_then(|data| serde_json::from_value::<Vec<JournalEntry>>(data).ok());
+    let entries = match live {
+        Some(entries) => entries,
+        None => journal::read_range(
+            &get_srv_dir(params.shared.debug).join(JOURNAL_FILE),
+            from,
+            to,
+        )?,
+    };
+    if json {
+        let out =
+            serde_json::to_string_pretty(&entries).map_err(|e| format!("encode journal: {e}"))?;
+        println!("{out}");
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("(no journal entries in that range)");
+    }
+    for e in &entries {
+        println!(
+            "generation {} {} {}@{} ({}): {} changed, {} deleted, {} bytes ({} sent)",
+            e.generation,
+            time::local_stamp(e.applied_at_unix),
+            e.username,
+            e.hostname,
+            e.client_id,
+            e.changed.len(),
+            e.deleted.len(),
+            e.size_bytes,
+            e.sent_bytes
+        );
+        for p in &e.changed {
+            println!("  + {}", p.display());
+        }
+        for p in &e.deleted {
+            println!("  - {}", p.display());
+        }
+    }
+    Ok(())
+}
+
+/// The server's sync root on this platform (a fixed scratch path under `-d`).
+#[must_use]
+pub fn get_srv_dir(debug: u8) -> PathBuf {
     if debug > 0 {
         PathBuf::from("/tmp/sinkd/srv")
     } else if cfg!(target_os = "windows") {
@@ -180,80 +656,254 @@ fn create_srv_dir(debug: u8, path: &Path) -> Outcome<()> {
 }
 
 // Daemonized call, stdin/stdout/stderr are closed
+#[allow(clippy::too_many_lines)]
 pub fn init(params: &ServerParameters) -> Outcome<()> {
+    runtime::write_pid(&runtime_files(&params.shared).pid)?;
+    let server_cfg = config::load_server_config(&params.config)?;
     let srv_dir = get_srv_dir(params.shared.debug);
     create_srv_dir(params.shared.debug, &srv_dir)?;
-    let generation_state_path = srv_dir.join("generation_state.toml");
+    let generation_state_path = srv_dir.join(GENERATION_FILE);
+    staging::sweep(&srv_dir)?;
 
-    let (synch_tx, synch_rx): (mpsc::Sender<ipc::Payload>, mpsc::Receiver<ipc::Payload>) =
-        mpsc::channel();
+    let applies = Arc::new(ApplyQueue::<ipc::Payload>::new());
     let (post_apply_tx, post_apply_rx) = mpsc::channel::<PostApply>();
 
     let fatal = Arc::new(AtomicBool::new(false));
     signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&fatal))?;
-    let status = Arc::new(Mutex::new(ipc::Status::Ready));
-    let generation_state = Arc::new(Mutex::new(load_generation_state(&generation_state_path)));
+    let generation_state = Arc::new(Mutex::new(load_generation_state(&generation_state_path)?));
+    let registry = Arc::new(Mutex::new(Registry::load(&srv_dir.join(REGISTRY_FILE))?));
+    let auth = Authorizer::new(server_cfg.auth.clone(), &srv_dir);
+    let ledger = Arc::new(Mutex::new(Ledger::scan(
+        server_cfg.quotas.clone(),
+        &srv_dir,
+    )?));
+
+    #[cfg(unix)]
+    let control_thread = {
+        let socket = runtime_files(&params.shared).socket;
+        if let Some(dir) = socket.parent() {
+            runtime::ensure_dir(dir)?;
+        }
+        let fatal = Arc::clone(&fatal);
+        let applies = Arc::clone(&applies);
+        let generation_state = Arc::clone(&generation_state);
+        let generation_state_path = generation_state_path.clone();
+        let registry = Arc::clone(&registry);
+        let ledger = Arc::clone(&ledger);
+        let srv_dir = srv_dir.clone();
+        control::serve(socket, Arc::clone(&fatal), move |request, reply| {
+            answer_control(
+                request,
+                &reply,
+                &fatal,
+                &applies,
+                &generation_state,
+                &generation_state_path,
+                &registry,
+                &ledger,
+                &srv_dir,
+            );
+        })?
+    };
 
     let zenoh_thread = thread::spawn({
         let fatal = Arc::clone(&fatal);
-        let status = Arc::clone(&status);
+        let applies = Arc::clone(&applies);
         let generation_state = Arc::clone(&generation_state);
         let generation_state_path = generation_state_path.clone();
+        let registry = Arc::clone(&registry);
+        let ledger = Arc::clone(&ledger);
+        let shared = params.shared.clone();
         move || {
             if let Err(err) = zenoh_entry(
-                synch_tx,
+                &shared,
+                &applies,
                 post_apply_rx,
                 fatal,
-                status,
                 generation_state,
                 generation_state_path,
+                &registry,
+                &auth,
+                &ledger,
             ) {
                 error!("{err}");
             }
         }
     });
 
-    let synch_thread = thread::spawn({
-        let post_apply_tx = post_apply_tx.clone();
-        let generation_state = Arc::clone(&generation_state);
-        let generation_state_path = generation_state_path.clone();
-        move || {
-            if let Err(err) = synch_entry(
-                synch_rx,
-                fatal,
-                status,
-                srv_dir,
-                post_apply_tx,
-                generation_state,
-                generation_state_path,
-            ) {
-                error!("{err}");
-            }
-        }
-    });
+    let (snapshotter, snapshot_thread) = if server_cfg.snapshots.enabled {
+        let (snapshotter, thread) = Snapshotter::spawn(srv_dir.clone(), server_cfg.snapshots);
+        (Some(snapshotter), Some(thread))
+    } else {
+        (None, None)
+    };
+    let workers = server_cfg.apply.workers.max(1);
+    info!("server: applying with {workers} worker(s)");
+    let apply_threads: Vec<_> = (0..workers)
+        .map(|_| {
+            let fatal = Arc::clone(&fatal);
+            let applies = Arc::clone(&applies);
+            let srv_dir = srv_dir.clone();
+            let post_apply_tx = post_apply_tx.clone();
+            let generation_state = Arc::clone(&generation_state);
+            let generation_state_path = generation_state_path.clone();
+            let registry = Arc::clone(&registry);
+            let ledger = Arc::clone(&ledger);
+            let snapshots = snapshotter.clone();
+            thread::spawn(move || {
+                if let Err(err) = apply_entry(
+                    &applies,
+                    &fatal,
+                    &srv_dir,
+                    &post_apply_tx,
+                    &generation_state,
+                    &generation_state_path,
+                    &registry,
+                    &ledger,
+                    snapshots.as_ref(),
+                ) {
+                    error!("{err}");
+                }
+            })
+        })
+        .collect();
 
     if let Err(zenoh_thread_err) = zenoh_thread.join() {
         error!("server:zenoh_thread join error! >> {zenoh_thread_err:?}");
         process::exit(1);
     }
-    if let Err(synch_thread_err) = synch_thread.join() {
-        error!("server::synch_thread join error! >> {synch_thread_err:?}");
+    for apply_thread in apply_threads {
+        if let Err(apply_thread_err) = apply_thread.join() {
+            error!("server::apply_thread join error! >> {apply_thread_err:?}");
+            process::exit(1);
+        }
+    }
+    drop(snapshotter);
+    if snapshot_thread.is_some_and(|t| t.join().is_err()) {
+        error!("server::snapshot_thread join error!");
+        process::exit(1);
+    }
+    #[cfg(unix)]
+    if control_thread.join().is_err() {
+        error!("server::control_thread join error!");
         process::exit(1);
     }
     Ok(())
 }
 
+/// Re-reads the persisted generation state; on error the one in memory stays.
+fn reload_generation_state(generation_state: &Mutex<GenerationState>, path: &Path) -> Outcome<u64> {
+    let loaded = load_generation_state(path)?;
+    let head = loaded.current_generation;
+    *generation_state
+        .lock()
+        .map_err(|e| format!("generation_state lock: {e}"))? = loaded;
+    info!("server: reloaded generation state from {}", path.display());
+    Ok(head)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn answer_control(
+    request: Request,
+    reply: &Reply,
+    fatal: &AtomicBool,
+    applies: &ApplyQueue<ipc::Payload>,
+    generation_state: &Mutex<GenerationState>,
+    generation_state_path: &Path,
+    registry: &Mutex<Registry>,
+    ledger: &Mutex<Ledger>,
+    srv_dir: &Path,
+) {
+    let resp = match request {
+        Request::Stop => {
+            info!("server: stop requested over control socket");
+            fatal.store(true, Ordering::Relaxed);
+            Response::ok("server daemon stopping")
+        }
+        Request::Reload => match reload_generation_state(generation_state, generation_state_path) {
+            Ok(head) => Response::ok(format!("generation state reloaded (head {head})")),
+            Err(e) => Response::error(format!("reload failed: {e}")),
+        },
+        Request::Status => {
+            let stored = match generation_state.lock() {
+                Ok(st) => stored_report(srv_dir, &st),
+                Err(e) => {
+                    let _ = reply.send(Response::error(format!("generation_state lock: {e}")));
+                    return;
+                }
+            };
+            let busy_anchors = applies.busy_paths();
+            let report = ServerReport {
+                running: true,
+                pid: Some(process::id()),
+                status: if busy_anchors.is_empty() {
+                    "Ready"
+                } else {
+                    "Applying"
+                }
+                .to_string(),
+                busy_anchors,
+                queue_depth: applies.depth(),
+                clients: registry.lock().map(|r| r.clients()).unwrap_or_default(),
+                usage: ledger.lock().map(|l| l.users()).unwrap_or_default(),
+                quotas: ledger.lock().map(|l| l.quotas()).unwrap_or_default(),
+                ..stored
+            };
+            match serde_json::to_value(&report) {
+                Ok(data) => Response::ok("running").data(data),
+                Err(e) => Response::error(format!("status json: {e}")),
+            }
+        }
+        Request::Changes { from, to } => {
+            match journal::read_range(&srv_dir.join(JOURNAL_FILE), from, to) {
+                Ok(entries) => Response::ok(format!("{} generation(s)", entries.len()))
+                    .data(serde_json::json!(entries)),
+                Err(e) => Response::error(e.to_string()),
+            }
+        }
+        Request::Clients => match registry.lock() {
+            Ok(reg) => {
+                let clients = reg.clients();
+                Response::ok(format!("{} client(s)", clients.len()))
+                    .data(serde_json::json!(clients))
+            }
+            Err(e) => Response::error(format!("registry lock: {e}")),
+        },
+        Request::LabelClient { client, label } => match registry.lock() {
+            Ok(mut reg) => match reg.label(&client, label, now_unix_secs()) {
+                Ok(id) => Response::ok(format!("labelled {id}")),
+                Err(e) => Response::error(e.to_string()),
+            },
+            Err(e) => Response::error(format!("registry lock: {e}")),
+        },
+        Request::ForgetClient { client } => match registry.lock() {
+            Ok(mut reg) => match reg.forget(&client, now_unix_secs()) {
+                Ok(gone) => Response::ok(format!("forgot {} ({})", gone.client_id, gone.name())),
+                Err(e) => Response::error(e.to_string()),
+            },
+            Err(e) => Response::error(format!("registry lock: {e}")),
+        },
+        other => Response::error(format!("{other:?} is not supported by the server")),
+    };
+    let _ = reply.send(resp);
+}
+
 fn publish_post_apply(zenoh_client: &ipc::ZenohClient, pa: PostApply) -> Outcome<()> {
     match pa {
         PostApply::Applied {
             writer_client_id,
+            push_id,
+            paths,
             head_generation,
         } => {
+            // the push's id and anchors let `sync --wait` tell its own push from another
             let mut p = ipc::Payload::new()?
                 .dest_path("sinkd_status")
                 .status(ipc::Status::Ready)
                 .head_generation(head_generation)
-                .last_writer_client_id(writer_client_id);
+                .last_writer_client_id(writer_client_id)
+                .push_id(push_id)
+                .src_paths(paths);
             zenoh_client.publish(&mut p)
         }
         PostApply::StaleAtApply { head_generation } => {
@@ -263,6 +913,21 @@ fn publish_post_apply(zenoh_client: &ipc::ZenohClient, pa: PostApply) -> Outcome
                 .head_generation(head_generation);
             zenoh_client.publish(&mut p)
         }
+        PostApply::Failed {
+            writer_client_id,
+            push_id,
+            paths,
+            error,
+        } => {
+            let mut p = ipc::Payload::new()?
+                .dest_path("sinkd_status")
+                .status(ipc::Status::NotReady(ipc::Reason::Failed))
+                .client_id(writer_client_id)
+                .push_id(push_id)
+                .src_paths(paths)
+                .error(error);
+            zenoh_client.publish(&mut p)
+        }
     }
 }
 
@@ -277,16 +942,20 @@ fn drain_post_apply(
 }
 
 #[allow(clippy::needless_pass_by_value)]
+#[allow(clippy::too_many_arguments)]
 fn zenoh_entry(
-    synch_tx: mpsc::Sender<ipc::Payload>,
+    shared: &SharedDaemonParams,
+    applies: &ApplyQueue<ipc::Payload>,
     post_apply_rx: mpsc::Receiver<PostApply>,
     fatal: Arc<AtomicBool>,
-    status: Arc<Mutex<ipc::Status>>,
     generation_state: Arc<Mutex<GenerationState>>,
     generation_state_path: PathBuf,
+    registry: &Mutex<Registry>,
+    auth: &Authorizer,
+    ledger: &Mutex<Ledger>,
 ) -> Outcome<()> {
     let (zenoh_client, zenoh_rx, terminal_topic): (ipc::ZenohClient, ipc::Rx, String) =
-        match ipc::connect_with_terminate_topic(&[ipc::TOPIC_CLIENTS], ipc::TOPIC_SERVER) {
+        match ipc::connect_with_terminate_topic(&[ipc::TOPIC_CLIENTS], ipc::TOPIC_SERVER, shared) {
             Ok(conn) => conn,
             Err(e) => {
                 fatal.store(true, Ordering::Relaxed);
@@ -296,6 +965,7 @@ fn zenoh_entry(
 
     loop {
         if fatal.load(Ordering::Relaxed) {
+            ipc::announce_exit(&zenoh_client, shared);
             zenoh_client.disconnect();
             info!("server:zenoh_entry>> aborting");
             return Ok(());
@@ -308,7 +978,7 @@ fn zenoh_entry(
             return Err(e);
         }
 
-        if let Err(e) = broadcast_status(&zenoh_client, &status, &generation_state) {
+        if let Err(e) = broadcast_status(&zenoh_client, &generation_state) {
             error!("{e}");
             fatal.store(true, Ordering::Relaxed);
             zenoh_client.disconnect();
@@ -321,11 +991,13 @@ fn zenoh_entry(
                     msg,
                     terminal_topic.as_str(),
                     &fatal,
-                    &synch_tx,
+                    applies,
                     &zenoh_client,
                     &generation_state,
                     generation_state_path.as_path(),
-                    &status,
+                    registry,
+                    auth,
+                    ledger,
                 ) {
                     error!("{e}");
                 }
@@ -348,11 +1020,13 @@ fn handle_incoming_transport_message(
     message: Option<ipc::ZenohMessage>,
     terminal_topic: &str,
     fatal: &Arc<AtomicBool>,
-    synch_tx: &mpsc::Sender<ipc::Payload>,
+    applies: &ApplyQueue<ipc::Payload>,
     zenoh_client: &ipc::ZenohClient,
     generation_state: &Arc<Mutex<GenerationState>>,
     generation_state_path: &Path,
-    status: &Arc<Mutex<ipc::Status>>,
+    registry: &Mutex<Registry>,
+    auth: &Authorizer,
+    ledger: &Mutex<Ledger>,
 ) -> Outcome<()> {
     let Some(msg) = message else {
         debug!("server:zenoh_entry>> recv empty msg");
@@ -366,29 +1040,25 @@ fn handle_incoming_transport_message(
     }
 
     if msg.topic == ipc::TOPIC_CONTROL_RELOAD {
-        let loaded = load_generation_state(generation_state_path);
-        match generation_state.lock() {
-            Ok(mut g) => *g = loaded,
-            Err(e) => {
-                error!("server:reload>> generation_state lock poisoned: {e}");
-                fatal.store(true, Ordering::Relaxed);
-                return bad!("server:reload>> generation_state lock poisoned: {e}");
-            }
+        if let Err(e) = reload_generation_state(generation_state, generation_state_path) {
+            error!("server:reload>> keeping the current generation state: {e}");
         }
-        info!(
-            "server: reloaded generation state from {}",
-            generation_state_path.display()
-        );
         return Ok(());
     }
 
+    if msg.payload.dest_path == Path::new(ipc::CHANGES_DEST) {
+        return answer_changes(zenoh_client, &msg.payload, generation_state, auth, registry);
+    }
+
     debug!("server:zenoh_entry>> ⛵ received payload ⛵");
     match queue(
-        synch_tx,
+        applies,
         zenoh_client,
         msg.payload,
         generation_state,
-        status,
+        auth,
+        registry,
+        ledger,
     ) {
         Ok(()) => Ok(()),
         Err(e) => {
@@ -398,201 +1068,330 @@ fn handle_incoming_transport_message(
     }
 }
 
-// check state of server and queue up payload if ready
+/// Tells a `Behind` client what changed since its `basis_generation` so it pulls only that.
+fn answer_changes(
+    zenoh_client: &ipc::ZenohClient,
+    query: &ipc::Payload,
+    generation_state: &Arc<Mutex<GenerationState>>,
+    auth: &Authorizer,
+    registry: &Mutex<Registry>,
+) -> Outcome<()> {
+    let (head, changes) = {
+        let st = generation_state
+            .lock()
+            .map_err(|e| format!("server:changes>> generation_state lock: {e}"))?;
+        (
+            st.current_generation,
+            st.changes_since(query.basis_generation, &query.client_id),
+        )
+    };
+    let Ok(pull) = auth.pull(query, changes, now_unix_secs()) else {
+        return refuse(zenoh_client, query, ipc::Reason::Unauthorized, head);
+    };
+    observe(registry, query);
+    let mut answer = ipc::Payload::new()?
+        .dest_path(ipc::CHANGES_DEST)
+        .status(ipc::Status::NotReady(ipc::Reason::Behind))
+        .client_id(query.client_id.clone())
+        .basis_generation(query.basis_generation)
+        .head_generation(head);
+    answer = match pull {
+        Pull::Changes(set) => {
+            debug!(
+                "server:changes>> {} {}..{head}: {} changed, {} deleted",
+                query.client_id,
+                query.basis_generation,
+                set.changed.len(),
+                set.deleted.len()
+            );
+            answer.changes(Some(set))
+        }
+        // no change record: `src_paths` limits the full pull to what the client may read
+        Pull::Full(roots) => {
+            info!(
+                "server:changes>> no complete history for {} since {}; full pull",
+                query.client_id, query.basis_generation
+            );
+            answer.src_paths(roots)
+        }
+    };
+    zenoh_client.publish(&mut answer)
+}
+
+/// Turns a push away (`Unauthorized` without a write grant, `QuotaExceeded` into a full tree,
+/// `Behind` on a stale basis) or queues it for the apply workers, behind any push it overlaps.
+/// A push without paths is dropped before any of that.
 fn queue(
-    synch_tx: &mpsc::Sender<ipc::Payload>,
+    applies: &ApplyQueue<ipc::Payload>,
     zenoh_client: &ipc::ZenohClient,
     payload: ipc::Payload,
     generation_state: &Arc<Mutex<GenerationState>>,
-    status: &Arc<Mutex<ipc::Status>>,
+    auth: &Authorizer,
+    registry: &Mutex<Registry>,
+    ledger: &Mutex<Ledger>,
 ) -> Outcome<()> {
-    match status.lock() {
-        Ok(state) => {
-            if *state == ipc::Status::Ready {
-                if needs_push_basis_check(&payload) {
-                    if payload.client_id.is_empty() {
-                        let head = generation_state
-                            .lock()
-                            .map_err(|e| format!("server:queue>> generation_state lock: {e}"))?
-                            .current_generation;
-                        let mut response = ipc::Payload::new()?
-                            .dest_path("sinkd_status")
-                            .status(ipc::Status::NotReady(ipc::Reason::Behind))
-                            .head_generation(head);
-                        if let Err(e) = zenoh_client.publish(&mut response) {
-                            error!("server:queue>> unable to publish response {e}");
-                        }
-                        return Ok(());
-                    }
-                    let head = generation_state
-                        .lock()
-                        .map_err(|e| format!("server:queue>> generation_state lock: {e}"))?
-                        .current_generation;
-                    if payload.basis_generation != head {
-                        let mut response = ipc::Payload::new()?
-                            .dest_path("sinkd_status")
-                            .status(ipc::Status::NotReady(ipc::Reason::Behind))
-                            .head_generation(head);
-                        if let Err(e) = zenoh_client.publish(&mut response) {
-                            error!("server:queue>> unable to publish response {e}");
-                        }
-                        return Ok(());
-                    }
-                }
-                debug!("queuing payload: {payload:#?}");
-                if let Err(e) = synch_tx.send(payload) {
-                    bad!("server:process>> unable to send on synch_tx {}", e)
-                } else {
-                    Ok(())
-                }
-            } else {
-                let head = generation_state
-                    .lock()
-                    .map_err(|e| format!("server:queue>> generation_state lock: {e}"))?
-                    .current_generation;
-                let mut response = ipc::Payload::new()?
-                    .dest_path("sinkd_status")
-                    .status(*state)
-                    .head_generation(head);
-                if let Err(e) = zenoh_client.publish(&mut response) {
-                    error!("server:queue>> unable to publish response {e}");
-                }
-                Ok(())
-            }
-        }
-        Err(e) => bad!("server:queue>> status lock poisoned {}", e),
+    if payload.src_paths.is_empty() {
+        // nothing to apply; queuing it would still bump the generation
+        warn!(
+            "server:queue>> ignoring a push with no paths from {}@{} ({})",
+            payload.username, payload.hostname, payload.client_id
+        );
+        return Ok(());
     }
+    // auth, claims and staleness go by `src_paths`; the scope must stay inside them
+    if let Some(outside) = payload
+        .scope
+        .iter()
+        .find(|p| !payload.src_paths.iter().any(|a| p.starts_with(a)))
+    {
+        warn!(
+            "server:queue>> ignoring a push from {} whose scope '{}' is outside its paths",
+            payload.client_id,
+            outside.display()
+        );
+        return Ok(());
+    }
+    let (head, stale) = {
+        let st = generation_state
+            .lock()
+            .map_err(|e| format!("server:queue>> generation_state lock: {e}"))?;
+        let stale = needs_push_basis_check(&payload)
+            && (payload.client_id.is_empty()
+                || st.touched_since(
+                    payload.basis_generation,
+                    &payload.src_paths,
+                    &payload.client_id,
+                ));
+        (st.current_generation, stale)
+    };
+    let authorized = auth.push(&payload, now_unix_secs()).is_ok();
+    if authorized {
+        observe(registry, &payload);
+    }
+    let refusal = if !authorized {
+        Some(ipc::Reason::Unauthorized)
+    } else if let Some(q) = ledger
+        .lock()
+        .ok()
+        .and_then(|l| l.exceeded(&payload.src_paths))
+    {
+        info!(
+            "server:queue>> {} is over quota ({} of {} bytes); refusing {}",
+            q.owner, q.used_bytes, q.limit_bytes, payload.client_id
+        );
+        Some(ipc::Reason::QuotaExceeded)
+    } else if stale {
+        Some(ipc::Reason::Behind)
+    } else {
+        None
+    };
+    if let Some(reason) = refusal {
+        return refuse(zenoh_client, &payload, reason, head);
+    }
+    // a push overlapping one already queued or running waits behind it in the apply queue
+    debug!("queuing payload: {payload:#?}");
+    applies.push(payload.src_paths.clone(), payload);
+    Ok(())
+}
+
+/// Notes a client the authorizer let in; refused senders never reach the registry.
+fn observe(registry: &Mutex<Registry>, payload: &ipc::Payload) {
+    if let Ok(mut reg) = registry.lock() {
+        reg.observe(payload, now_unix_secs());
+    }
+}
+
+/// Answers `payload` with `NotReady(reason)`; only `Behind` concerns every client.
+fn refuse(
+    zenoh_client: &ipc::ZenohClient,
+    payload: &ipc::Payload,
+    reason: ipc::Reason,
+    head: u64,
+) -> Outcome<()> {
+    let mut response = ipc::Payload::new()?
+        .dest_path("sinkd_status")
+        .status(ipc::Status::NotReady(reason))
+        .head_generation(head);
+    if reason != ipc::Reason::Behind {
+        response = response.client_id(payload.client_id.clone());
+    }
+    if let Err(e) = zenoh_client.publish(&mut response) {
+        error!("server:queue>> unable to publish response {e}");
+    }
+    Ok(())
 }
 
 // The engine behind sinkd is rsync — bump global generation only after successful apply.
-#[allow(clippy::needless_pass_by_value)]
-fn synch_entry(
-    synch_rx: mpsc::Receiver<ipc::Payload>,
-    fatal: Arc<AtomicBool>,
-    status: Arc<Mutex<ipc::Status>>,
-    srv_dir: PathBuf,
-    post_apply_tx: mpsc::Sender<PostApply>,
-    generation_state: Arc<Mutex<GenerationState>>,
-    generation_state_path: PathBuf,
+// Each worker runs this loop; the queue keeps overlapping pushes apart.
+#[allow(clippy::too_many_arguments)]
+fn apply_entry(
+    applies: &ApplyQueue<ipc::Payload>,
+    fatal: &AtomicBool,
+    srv_dir: &Path,
+    post_apply_tx: &mpsc::Sender<PostApply>,
+    generation_state: &Mutex<GenerationState>,
+    generation_state_path: &Path,
+    registry: &Mutex<Registry>,
+    ledger: &Mutex<Ledger>,
+    snapshots: Option<&Snapshotter>,
 ) -> Outcome<()> {
     loop {
         if fatal.load(Ordering::Relaxed) {
-            info!("server:synch_entry>> aborting");
+            info!("server:apply_entry>> aborting");
             return Ok(());
         }
+        let Some(claim) = applies.next(Duration::from_secs(1)) else {
+            debug!("server:apply_entry>> waiting...");
+            continue;
+        };
+        // release the paths only after the bump, so a push queued behind this one sees it
+        let outcome = apply_one(
+            &claim.job,
+            srv_dir,
+            post_apply_tx,
+            generation_state,
+            generation_state_path,
+            registry,
+            ledger,
+            snapshots,
+        );
+        applies.finish(claim.ticket);
+        if let Err(e) = outcome {
+            fatal.store(true, Ordering::Relaxed);
+            return Err(e);
+        }
+    }
+}
 
-        match synch_rx.recv_timeout(Duration::from_secs(1)) {
-            Err(recv_err) => match recv_err {
-                RecvTimeoutError::Timeout => {
-                    debug!("server:synch_entry>> waiting...");
-                }
-                RecvTimeoutError::Disconnected => {
-                    fatal.store(true, Ordering::Relaxed);
-                    return bad!("server>> _rx channel disconnected");
-                }
-            },
-            Ok(payload) => {
-                if needs_push_basis_check(&payload) {
-                    let ok_basis = match generation_state.lock() {
-                        Ok(st) => payload.basis_generation == st.current_generation,
-                        Err(e) => {
-                            error!("server:synch_entry>> generation_state lock: {e}");
-                            fatal.store(true, Ordering::Relaxed);
-                            return bad!("server:synch_entry>> generation_state lock: {e}");
-                        }
-                    };
-                    if !ok_basis {
-                        match status.lock() {
-                            Ok(mut state) => *state = ipc::Status::Ready,
-                            Err(e) => {
-                                error!("server:synch_entry>> status mutex poisoned: {e}");
-                                fatal.store(true, Ordering::Relaxed);
-                                return bad!("server:synch_entry>> status lock poisoned: {e}");
-                            }
-                        }
-                        let head = generation_state
-                            .lock()
-                            .map_err(|e| {
-                                format!("server:synch_entry>> generation_state lock: {e}")
-                            })?
-                            .current_generation;
-                        let _ = post_apply_tx.send(PostApply::StaleAtApply {
-                            head_generation: head,
-                        });
-                        continue;
-                    }
-                }
-
-                let dest = PathBuf::from(format!("{}/", &srv_dir.display()));
-
-                match status.lock() {
-                    Ok(mut state) => *state = ipc::Status::NotReady(ipc::Reason::Busy),
-                    Err(e) => {
-                        error!("server:synch_entry>> status mutex poisoned before rsync: {e}");
-                        fatal.store(true, Ordering::Relaxed);
-                        return bad!("server:synch_entry>> status lock poisoned before rsync: {e}");
-                    }
-                }
-                let rsync_cfg = payload.rsync.clone().unwrap_or_default();
-                let rsync_ok = rsync(&payload.src_paths, &dest, &rsync_cfg, None).is_ok();
-                if !rsync_ok {
-                    error!("server:synch_entry>> rsync failed");
-                }
+#[allow(clippy::too_many_arguments)]
+fn apply_one(
+    payload: &ipc::Payload,
+    srv_dir: &Path,
+    post_apply_tx: &mpsc::Sender<PostApply>,
+    generation_state: &Mutex<GenerationState>,
+    generation_state_path: &Path,
+    registry: &Mutex<Registry>,
+    ledger: &Mutex<Ledger>,
+    snapshots: Option<&Snapshotter>,
+) -> Outcome<()> {
+    if needs_push_basis_check(payload) {
+        let st = generation_state
+            .lock()
+            .map_err(|e| format!("server:apply_entry>> generation_state lock: {e}"))?;
+        if st.touched_since(
+            payload.basis_generation,
+            &payload.src_paths,
+            &payload.client_id,
+        ) {
+            let _ = post_apply_tx.send(PostApply::StaleAtApply {
+                head_generation: st.current_generation,
+            });
+            return Ok(());
+        }
+    }
 
-                match status.lock() {
-                    Ok(mut state) => *state = ipc::Status::Ready,
-                    Err(e) => {
-                        error!("server:synch_entry>> status mutex poisoned after rsync: {e}");
-                        fatal.store(true, Ordering::Relaxed);
-                        return bad!("server:synch_entry>> status lock poisoned after rsync: {e}");
-                    }
-                }
+    let failed = |error: String| {
+        error!(
+            "server:apply_entry>> push {} from {}: {error}; live tree untouched",
+            payload.push_id, payload.client_id
+        );
+        let _ = post_apply_tx.send(PostApply::Failed {
+            writer_client_id: payload.client_id.clone(),
+            push_id: payload.push_id,
+            paths: payload.src_paths.clone(),
+            error,
+        });
+    };
+    // the live tree only changes when the stage is promoted, after a complete rsync
+    let rsync_cfg = payload.rsync.clone().unwrap_or_default();
+    let sources = payload.sources();
+    let staged = Stage::prepare(srv_dir, sources).and_then(|stage| {
+        let itemized = rsync_itemized(sources, &stage.dest(), &rsync_cfg)?;
+        Ok((stage, itemized))
+    });
+    let (stage, itemized) = match staged {
+        Ok(staged) => staged,
+        Err(e) => {
+            failed(e.to_string());
+            return Ok(());
+        }
+    };
 
-                if rsync_ok {
-                    let writer = payload.client_id.clone();
-                    let new_gen = match generation_state.lock() {
-                        Ok(mut st) => {
-                            let new_gen = st.bump(now_unix_secs());
-                            if let Err(e) = persist_generation_state(&generation_state_path, &st) {
-                                error!(
-                                    "server: unable to persist generation state '{}': {}",
-                                    generation_state_path.display(),
-                                    e
-                                );
-                            }
-                            new_gen
-                        }
-                        Err(e) => {
-                            error!("server:synch_entry>> generation_state lock: {e}");
-                            fatal.store(true, Ordering::Relaxed);
-                            return bad!("server:synch_entry>> generation_state lock: {e}");
-                        }
-                    };
-                    let _ = post_apply_tx.send(PostApply::Applied {
-                        writer_client_id: writer,
-                        head_generation: new_gen,
-                    });
-                }
-            }
+    // the claim keeps these anchors still, so the difference is this push alone
+    let measure = || {
+        ledger
+            .lock()
+            .map(|l| l.measure(srv_dir, sources))
+            .unwrap_or_default()
+    };
+    let before = measure();
+    let (new_gen, snapshot) = {
+        let mut st = generation_state
+            .lock()
+            .map_err(|e| format!("server:apply_entry>> generation_state lock: {e}"))?;
+        // promoted under the lock: nobody sees the new tree under the old generation for long
+        if let Err(e) = stage.promote(srv_dir) {
+            drop(st);
+            failed(e.to_string());
+            return Ok(());
         }
+        let now = now_unix_secs();
+        let new_gen = st.bump(now, &payload.client_id);
+        if let Err(e) = persist_generation_state(generation_state_path, &st) {
+            error!(
+                "server: unable to persist generation state '{}': {}",
+                generation_state_path.display(),
+                e
+            );
+        }
+        // still under the lock, so journal lines stay in generation order
+        let entry = JournalEntry::new(new_gen, now, &itemized).writer(
+            &payload.client_id,
+            &payload.hostname,
+            &payload.username,
+        );
+        let journal_path = srv_dir.join(JOURNAL_FILE);
+        if let Err(e) = journal::append(&journal_path, &entry) {
+            error!(
+                "server: unable to append to journal '{}': {e}",
+                journal_path.display()
+            );
+        }
+        // asked under the lock so snapshots follow generation order, then taken without it
+        let snapshot = snapshots.map(|s| s.request(new_gen, now, sources));
+        (new_gen, snapshot)
+    };
+    if let Some(taken) = snapshot {
+        let _ = taken.recv();
+    }
+    let after = measure();
+    if let Ok(mut l) = ledger.lock() {
+        l.adjust(&before, &after);
     }
+    if let Ok(mut reg) = registry.lock() {
+        reg.pushed(&payload.client_id, new_gen, now_unix_secs());
+    }
+    let _ = post_apply_tx.send(PostApply::Applied {
+        writer_client_id: payload.client_id.clone(),
+        push_id: payload.push_id,
+        paths: payload.src_paths.clone(),
+        head_generation: new_gen,
+    });
+    Ok(())
 }
 
+/// Per-anchor `Busy` goes to the pusher alone, so the broadcast only says the server is up.
 fn broadcast_status(
     zenoh_client: &ipc::ZenohClient,
-    status: &Arc<Mutex<ipc::Status>>,
     generation_state: &Arc<Mutex<GenerationState>>,
 ) -> Outcome<()> {
-    let Ok(state) = status.lock() else {
-        return bad!("status lock poisoned");
-    };
     let Ok(gen) = generation_state.lock() else {
         return bad!("generation_state lock poisoned");
     };
     let head = gen.current_generation;
     let mut status_payload = ipc::Payload::new()?
         .dest_path("sinkd_status")
-        .status(*state)
+        .status(ipc::Status::Ready)
         .head_generation(head);
     if let Err(e) = zenoh_client.publish(&mut status_payload) {
         bad!("server:broadcast_status>> couldn't publish status? '{}'", e)
@@ -603,9 +1402,37 @@ fn broadcast_status(
 
 #[cfg(test)]
 mod tests {
-    use std::time::{SystemTime, UNIX_EPOCH};
+    use std::{
+        path::{Path, PathBuf},
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use super::{
+        backup_path, load_generation_state, now_unix_secs, persist_generation_state,
+        reset_generation_state, GenerationState,
+    };
+    use crate::{
+        changes::Itemized,
+        journal::{self, JournalEntry, JOURNAL_FILE},
+    };
+
+    fn state_in(dir: &Path) -> GenerationState {
+        GenerationState {
+            journal: dir.join(JOURNAL_FILE),
+            ..GenerationState::default()
+        }
+    }
 
-    use super::{load_generation_state, persist_generation_state, GenerationState};
+    /// Bumps `st` the way an apply does, journaling `changed` unless it is `None`.
+    fn apply(st: &mut GenerationState, changed: Option<&str>, writer: &str) {
+        let generation = st.bump(now_unix_secs(), writer);
+        if let Some(path) = changed {
+            let mut itemized = Itemized::default();
+            itemized.changes.record_changed(PathBuf::from(path));
+            let entry = JournalEntry::new(generation, 0, &itemized).writer(writer, "", "");
+            journal::append(&st.journal, &entry).expect("journal");
+        }
+    }
 
     #[test]
     fn generation_state_roundtrip_persists_data() {
@@ -620,14 +1447,124 @@ mod tests {
             ..Default::default()
         };
         let now = super::now_unix_secs();
-        st.bump(now);
+        st.bump(now, "id-1");
 
         persist_generation_state(&path, &st).expect("persist should succeed");
-        let loaded = load_generation_state(&path);
+        let raw = std::fs::read_to_string(&path).expect("written");
+        assert!(
+            !raw.contains("changes"),
+            "change records stay in the journal"
+        );
+        let loaded = load_generation_state(&path).expect("load should succeed");
         std::fs::remove_file(path).expect("temp file should be removable");
 
         assert_eq!(loaded.current_generation, 5);
         assert_eq!(loaded.history.len(), 1);
         assert_eq!(loaded.history[0].generation, 5);
     }
+
+    #[test]
+    fn corrupt_generation_state_is_refused_until_reset_from_backup() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("generation_state.toml");
+        assert_eq!(
+            load_generation_state(&path)
+                .expect("fresh")
+                .current_generation,
+            0
+        );
+        let now = super::now_unix_secs();
+        let mut st = GenerationState::default();
+        for _ in 0..3 {
+            st.bump(now, "");
+            persist_generation_state(&path, &st).expect("persist");
+        }
+        let backup = load_generation_state(&backup_path(&path)).expect("backup");
+        assert_eq!(backup.current_generation, 2, "backup is the previous state");
+
+        std::fs::write(&path, "current_generation = 3\nhistory = [").expect("tear");
+        let err = load_generation_state(&path).expect_err("corrupt");
+        assert!(err.to_string().contains("--force-reset"), "{err}");
+        let reset = reset_generation_state(&path).expect("reset");
+        assert_eq!(reset.current_generation, 2);
+        assert_eq!(
+            load_generation_state(&path)
+                .expect("reset state")
+                .current_generation,
+            2
+        );
+
+        std::fs::remove_file(&path).expect("rm");
+        assert!(
+            load_generation_state(&path).is_err(),
+            "gone with a backup left"
+        );
+    }
+
+    #[test]
+    fn changes_since_merges_journaled_generations_only() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let mut st = state_in(tmp.path());
+        apply(&mut st, None, "");
+        apply(&mut st, Some("/w/a/x"), "");
+        apply(&mut st, Some("/w/a/y"), "");
+
+        let since_1 = st
+            .changes_since(1, "")
+            .expect("generations 2..=3 journaled");
+        assert_eq!(since_1.changed.len(), 2);
+        assert_eq!(st.changes_since(2, "").expect("journaled").changed.len(), 1);
+        assert!(st.changes_since(3, "").expect("up to date").is_empty());
+        assert!(
+            st.changes_since(0, "").is_none(),
+            "generation 1 has no journal line"
+        );
+        assert!(st.changes_since(7, "").is_none(), "client ahead of head");
+
+        // a reset back to generation 2 journals 3 again; the newer line counts
+        st.current_generation = 2;
+        apply(&mut st, Some("/w/a/z"), "");
+        let redone = st.changes_since(2, "").expect("journaled");
+        assert_eq!(
+            redone.changed.into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/w/a/z")]
+        );
+    }
+
+    #[test]
+    fn pushes_to_untouched_anchors_are_not_stale() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let mut st = state_in(tmp.path());
+        apply(&mut st, None, "");
+        apply(&mut st, Some("/home/a/docs/x"), "");
+
+        let docs = [PathBuf::from("/home/a/docs")];
+        let music = [PathBuf::from("/home/a/music")];
+        assert!(!st.touched_since(2, &docs, ""), "basis is the head");
+        assert!(st.touched_since(1, &docs, ""));
+        assert!(!st.touched_since(1, &music, ""));
+        assert!(
+            st.touched_since(0, &music, ""),
+            "generation 1 has no journal line"
+        );
+    }
+
+    #[test]
+    fn own_generations_do_not_make_a_push_stale() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let mut st = state_in(tmp.path());
+        apply(&mut st, None, "");
+        // generation 2 from another client, 3 from us: acked stays at 1
+        apply(&mut st, Some("/home/b/music/y"), "other");
+        apply(&mut st, Some("/home/a/docs/x"), "us");
+
+        let docs = [PathBuf::from("/home/a/docs")];
+        assert!(!st.touched_since(1, &docs, "us"));
+        assert!(st.touched_since(1, &docs, "other"));
+        let pulled = st.changes_since(1, "us").expect("journaled");
+        assert_eq!(
+            pulled.changed.into_iter().collect::<Vec<_>>(),
+            vec![PathBuf::from("/home/b/music/y")]
+        );
+    }
 }
@@ -0,0 +1,520 @@
+//! Hardlink snapshots of the server's sync root, one per generation.
+//!
+//! With `[snapshots] enabled = true` in the server config, every apply leaves
+//! `srv_dir/.snapshots/<generation>`: a tree of hardlinks, so unchanged files cost no space.
+//! A snapshot starts as a link copy of the previous one, and only the paths the apply
+//! copied are relinked from the live tree. Other workers may be mid-rsync elsewhere in the
+//! tree, so a snapshot never reads outside the paths its own apply holds, except for the very
+//! first one (or the first after a gap), which links the whole live tree.
+//!
+//! Applies never write to a live inode: rsync runs against a stage of copied files, which
+//! replaces the live paths whole (see [`crate::staging`]). So every change, attribute-only
+//! updates (mode, mtime) included, lands on a new inode, and a snapshot's files stay exactly
+//! as they were when it was taken.
+//!
+//! Retention works like rsnapshot: keep the newest snapshot of each of the last `hourly`
+//! hours, `daily` days and `weekly` weeks. The newest snapshot is always kept.
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{durable, outcome::Outcome};
+
+pub const SNAPSHOT_DIR: &str = ".snapshots";
+/// Inside each snapshot: when it was taken. A directory's mtime moves whenever something
+/// touches it, so age never comes from there.
+pub const MANIFEST: &str = ".snapshot.toml";
+/// rsync filter rules that copy only the manifests out of a snapshot root.
+pub const MANIFEST_FILTER: [&str; 3] = ["+ /*/", "+ /*/.snapshot.toml", "- *"];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    generation: u64,
+    /// Unix seconds of the generation's apply.
+    taken_at: i64,
+}
+
+/// `[snapshots]` table in the server config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    pub enabled: bool,
+    /// Hours (newest snapshot in each) to keep.
+    pub hourly: usize,
+    /// Days to keep.
+    pub daily: usize,
+    /// Weeks to keep.
+    pub weekly: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            enabled: false,
+            hourly: 24,
+            daily: 7,
+            weekly: 4,
+        }
+    }
+}
+
+#[must_use]
+pub fn root(srv_dir: &Path) -> PathBuf {
+    srv_dir.join(SNAPSHOT_DIR)
+}
+
+/// Finished snapshots as `(generation, dir)`, oldest first.
+pub fn list(srv_dir: &Path) -> Outcome<Vec<(u64, PathBuf)>> {
+    list_in(&root(srv_dir))
+}
+
+fn list_in(dir: &Path) -> Outcome<Vec<(u64, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return bad!("read '{}': {e}", dir.display()),
+    };
+    let mut snaps: Vec<(u64, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|e| {
+            let generation = e.file_name().to_str()?.parse().ok()?;
+            Some((generation, e.path()))
+        })
+        .collect();
+    snaps.sort();
+    Ok(snaps)
+}
+
+/// Finished snapshots as `(generation, taken at)` in unix seconds, oldest first.
+pub fn taken(srv_dir: &Path) -> Outcome<Vec<(u64, i64)>> {
+    taken_in(&root(srv_dir))
+}
+
+/// [`taken`] for a copy of a snapshot root holding at least each snapshot's [`MANIFEST`]
+/// (what [`MANIFEST_FILTER`] lets through).
+pub fn taken_in(dir: &Path) -> Outcome<Vec<(u64, i64)>> {
+    list_in(dir)?
+        .into_iter()
+        .map(|(generation, snap)| Ok((generation, taken_at(&snap)?)))
+        .collect()
+}
+
+fn taken_at(snap: &Path) -> Outcome<i64> {
+    let path = snap.join(MANIFEST);
+    match fs::read_to_string(&path) {
+        Ok(text) => {
+            let manifest: Manifest =
+                toml::from_str(&text).map_err(|e| format!("parse '{}': {e}", path.display()))?;
+            Ok(manifest.taken_at)
+        }
+        // snapshots from before manifests: the directory time is all there is
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Ok(unix_secs(fs::metadata(snap)?.modified()?))
+        }
+        Err(e) => bad!("read '{}': {e}", path.display()),
+    }
+}
+
+/// Which snapshot a restore wants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pick {
+    Generation(u64),
+    /// The newest snapshot taken at or before this unix time.
+    At(i64),
+}
+
+/// Finds `want` among `snaps` (`(generation, taken at)`, oldest first).
+pub fn pick(snaps: &[(u64, i64)], want: Pick) -> Outcome<(u64, i64)> {
+    let found = match want {
+        Pick::Generation(g) => snaps.iter().find(|(s, _)| *s == g),
+        Pick::At(t) => snaps.iter().rev().find(|(_, at)| *at <= t),
+    };
+    if let Some(snap) = found {
+        return Ok(*snap);
+    }
+    let kept = snaps
+        .iter()
+        .map(|(g, _)| g.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    match want {
+        Pick::Generation(g) => bad!("no snapshot of generation {}; kept: [{}]", g, kept),
+        Pick::At(_) => bad!("no snapshot that old; kept generations: [{}]", kept),
+    }
+}
+
+fn unix_secs(t: SystemTime) -> i64 {
+    t.duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|d| i64::try_from(d.as_secs()).ok())
+        .unwrap_or(0)
+}
+
+/// A snapshot still to take; `done` hangs up once it is on disk (or has failed).
+struct Job {
+    generation: u64,
+    taken_at: i64,
+    paths: Vec<PathBuf>,
+    done: mpsc::Sender<()>,
+}
+
+/// Takes snapshots on a thread of its own, one at a time and in the order they were asked for.
+///
+/// A worker asks while it holds the generation lock, so requests arrive in generation order,
+/// and waits for the answer after letting go of it: the link copy stalls no one else, while
+/// the worker's claim keeps the applied paths still until the snapshot has read them.
+#[derive(Debug, Clone)]
+pub struct Snapshotter {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Snapshotter {
+    /// The thread ends once every clone of the returned handle is dropped.
+    #[must_use]
+    pub fn spawn(srv_dir: PathBuf, cfg: SnapshotConfig) -> (Snapshotter, thread::JoinHandle<()>) {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let thread = thread::spawn(move || {
+            for job in rx {
+                keep(&srv_dir, &job, &cfg);
+                drop(job.done);
+            }
+        });
+        (Snapshotter { jobs }, thread)
+    }
+
+    /// Queues the snapshot of `generation`, applied at `taken_at` (unix seconds); the
+    /// receiver disconnects once it is taken.
+    #[must_use]
+    pub fn request(&self, generation: u64, taken_at: i64, paths: &[PathBuf]) -> mpsc::Receiver<()> {
+        let (done, taken) = mpsc::channel();
+        let job = Job {
+            generation,
+            taken_at,
+            paths: paths.to_vec(),
+            done,
+        };
+        if self.jobs.send(job).is_err() {
+            error!("snapshot: thread gone; generation {generation} is not kept");
+        }
+        taken
+    }
+}
+
+/// Failing to snapshot costs history, not the apply: log it and carry on.
+fn keep(srv_dir: &Path, job: &Job, cfg: &SnapshotConfig) {
+    if let Err(e) = take(srv_dir, job.generation, job.taken_at, &job.paths) {
+        error!(
+            "snapshot: unable to snapshot generation {}: {e}",
+            job.generation
+        );
+        return;
+    }
+    match prune(srv_dir, cfg) {
+        Ok(removed) if !removed.is_empty() => {
+            info!("snapshot: pruned {} snapshot(s)", removed.len());
+        }
+        Ok(_) => {}
+        Err(e) => error!("snapshot: unable to prune snapshots: {e}"),
+    }
+}
+
+/// Records the tree as of `generation`, which applied `paths` (client paths, as in the
+/// payload) at `taken_at`. Snapshots must be taken in generation order, while `paths` are held still; see
+/// [`Snapshotter`].
+pub fn take(srv_dir: &Path, generation: u64, taken_at: i64, paths: &[PathBuf]) -> Outcome<PathBuf> {
+    let root = root(srv_dir);
+    fs::create_dir_all(&root)?;
+    let partial = root.join(format!(".{generation}.partial"));
+    if partial.exists() {
+        fs::remove_dir_all(&partial)?;
+    }
+    let previous = list(srv_dir)?.pop().filter(|(g, _)| g + 1 == generation);
+    if let Some((_, base)) = previous {
+        link_tree(&base, &partial)?;
+        for path in paths {
+            let rel = path.strip_prefix("/").unwrap_or(path);
+            let (live, snap) = (srv_dir.join(rel), partial.join(rel));
+            remove_any(&snap)?;
+            if fs::symlink_metadata(&live).is_ok() {
+                if let Some(parent) = snap.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                link_tree(&live, &snap)?;
+            }
+        }
+    } else {
+        info!(
+            "snapshot: no snapshot of generation {}; linking the whole tree",
+            generation.saturating_sub(1)
+        );
+        fs::create_dir(&partial)?;
+        for entry in fs::read_dir(srv_dir)? {
+            let entry = entry?;
+            // the top level holds client roots (`home`, ...) and the server's own files
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && entry.file_type()?.is_dir() {
+                link_tree(&entry.path(), &partial.join(entry.file_name()))?;
+            }
+        }
+    }
+    // replaced, not rewritten: the previous snapshot's manifest came along as a hardlink
+    let manifest = toml::to_string(&Manifest {
+        generation,
+        taken_at,
+    })
+    .map_err(|e| format!("snapshot manifest: {e}"))?;
+    durable::write_atomic(&partial.join(MANIFEST), manifest.as_bytes())?;
+    let done = root.join(generation.to_string());
+    fs::rename(&partial, &done)?;
+    debug!("snapshot: {}", done.display());
+    Ok(done)
+}
+
+/// Removes the snapshots `cfg` no longer keeps; returns their dirs.
+pub fn prune(srv_dir: &Path, cfg: &SnapshotConfig) -> Outcome<Vec<PathBuf>> {
+    let mut dated = Vec::new();
+    for (generation, dir) in list(srv_dir)? {
+        dated.push((generation, taken_at(&dir)?, dir));
+    }
+    let stamps: Vec<(u64, i64)> = dated.iter().map(|(g, t, _)| (*g, *t)).collect();
+    let keep = keep_set(&stamps, cfg);
+    let mut removed = Vec::new();
+    for (generation, _, dir) in dated {
+        if !keep.contains(&generation) {
+            fs::remove_dir_all(&dir)?;
+            removed.push(dir);
+        }
+    }
+    Ok(removed)
+}
+
+/// Generations to keep out of `snaps` (`(generation, taken at)`).
+fn keep_set(snaps: &[(u64, i64)], cfg: &SnapshotConfig) -> BTreeSet<u64> {
+    let mut newest_first: Vec<(u64, u64)> = snaps
+        .iter()
+        .map(|(g, t)| (*g, u64::try_from(*t).unwrap_or(0)))
+        .collect();
+    newest_first.sort_by(|a, b| b.cmp(a));
+    let mut keep: BTreeSet<u64> = newest_first.first().map(|(g, _)| *g).into_iter().collect();
+    for (period, count) in [
+        (3600, cfg.hourly),
+        (86_400, cfg.daily),
+        (7 * 86_400, cfg.weekly),
+    ] {
+        let mut buckets = BTreeSet::new();
+        for (generation, secs) in &newest_first {
+            if buckets.len() == count && !buckets.contains(&(secs / period)) {
+                break;
+            }
+            if buckets.insert(secs / period) {
+                keep.insert(*generation);
+            }
+        }
+    }
+    keep
+}
+
+/// Recreates `src` at `dest` with files hardlinked, directories created and symlinks copied.
+pub(crate) fn link_tree(src: &Path, dest: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    if meta.is_dir() {
+        fs::create_dir(dest)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            link_tree(&entry.path(), &dest.join(entry.file_name()))?;
+        }
+        fs::set_permissions(dest, meta.permissions())
+    } else if meta.file_type().is_symlink() {
+        copy_symlink(src, dest)
+    } else {
+        fs::hard_link(src, dest)
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dest: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dest)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, dest: &Path) -> io::Result<()> {
+    fs::copy(src, dest).map(|_| ())
+}
+
+pub(crate) fn remove_any(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, time::SystemTime};
+
+    use super::{
+        keep_set, list, pick, root, take, taken, taken_in, Pick, SnapshotConfig, Snapshotter,
+        MANIFEST,
+    };
+
+    #[test]
+    fn snapshots_relink_only_the_applied_anchor() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let srv = tmp.path();
+        for dir in ["home/a/docs", "home/b"] {
+            fs::create_dir_all(srv.join(dir)).expect("mkdir");
+        }
+        fs::write(srv.join("home/a/docs/x.txt"), "one").expect("write");
+        fs::write(srv.join("home/b/y.txt"), "b").expect("write");
+        fs::write(srv.join("generation_state.toml"), "current_generation = 1").expect("write");
+
+        let first = take(srv, 1, 1, &[PathBuf::from("/home/a/docs")]).expect("first");
+        assert!(!first.join("generation_state.toml").exists());
+        assert_eq!(
+            fs::read_to_string(first.join("home/b/y.txt")).expect("read"),
+            "b"
+        );
+
+        // rsync replaces files by rename; an in-flight apply elsewhere must not leak in
+        fs::remove_file(srv.join("home/a/docs/x.txt")).expect("rm");
+        fs::write(srv.join("home/a/docs/x.txt"), "two").expect("write");
+        fs::write(srv.join("home/b/y.txt.tmp"), "half").expect("write");
+        let second = take(srv, 2, 2, &[PathBuf::from("/home/a/docs")]).expect("second");
+        assert_eq!(
+            fs::read_to_string(second.join("home/a/docs/x.txt")).expect("read"),
+            "two"
+        );
+        assert_eq!(
+            fs::read_to_string(first.join("home/a/docs/x.txt")).expect("read"),
+            "one"
+        );
+        assert!(!second.join("home/b/y.txt.tmp").exists());
+        assert_eq!(
+            list(srv)
+                .expect("list")
+                .iter()
+                .map(|(g, _)| *g)
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let ino = |p: PathBuf| fs::metadata(p).expect("meta").ino();
+            assert_eq!(
+                ino(first.join("home/b/y.txt")),
+                ino(second.join("home/b/y.txt"))
+            );
+        }
+    }
+
+    #[test]
+    fn snapshotter_answers_each_request_once_taken() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let srv = tmp.path();
+        fs::create_dir_all(srv.join("home/a")).expect("mkdir");
+        fs::write(srv.join("home/a/x"), "x").expect("write");
+        let cfg = SnapshotConfig {
+            enabled: true,
+            ..SnapshotConfig::default()
+        };
+        let (snapshotter, thread) = Snapshotter::spawn(srv.to_path_buf(), cfg);
+        let first = snapshotter.request(1, 1, &[PathBuf::from("/home/a")]);
+        let second = snapshotter.request(2, 2, &[PathBuf::from("/home/a")]);
+        assert!(second.recv().is_err(), "answered by hanging up");
+        assert!(first.recv().is_err());
+        // both fall in this hour, so retention keeps only the newer
+        let kept = list(srv).expect("list");
+        assert_eq!(kept.iter().map(|(g, _)| *g).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(
+            fs::read_to_string(kept[0].1.join("home/a/x")).expect("read"),
+            "x"
+        );
+        drop(snapshotter);
+        thread
+            .join()
+            .expect("snapshot thread ends with its last handle");
+    }
+
+    #[test]
+    fn retention_keeps_newest_per_hour_day_and_week() {
+        let hour = 3600;
+        let base = 100 * 7 * 86_400; // a week boundary
+        let at = |secs: i64| base + secs;
+        let snaps = vec![
+            (1, at(0)),               // week 0
+            (2, at(86_400)),          // day 1
+            (3, at(2 * 86_400)),      // day 2
+            (4, at(2 * 86_400 + 10)), // same hour as 3
+            (5, at(2 * 86_400 + hour)),
+            (6, at(2 * 86_400 + 2 * hour)),
+        ];
+        let cfg = SnapshotConfig {
+            enabled: true,
+            hourly: 2,
+            daily: 2,
+            weekly: 0,
+        };
+        // hours: 6, 5; days: 6 (day 2), 2 (day 1)
+        assert_eq!(
+            keep_set(&snaps, &cfg).into_iter().collect::<Vec<_>>(),
+            vec![2, 5, 6]
+        );
+
+        let weekly = SnapshotConfig {
+            enabled: true,
+            hourly: 0,
+            daily: 0,
+            weekly: 1,
+        };
+        assert_eq!(
+            keep_set(&snaps, &weekly).into_iter().collect::<Vec<_>>(),
+            vec![6]
+        );
+    }
+
+    #[test]
+    fn restore_picks_by_generation_or_time_from_manifests_not_dir_times() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let srv = tmp.path();
+        fs::create_dir_all(srv.join("home/a")).expect("mkdir");
+        let (seven, twelve) = (1_790_000_000, 1_790_009_000);
+        let first = take(srv, 7, seven, &[PathBuf::from("/home/a")]).expect("7");
+        take(srv, 12, twelve, &[PathBuf::from("/home/a")]).expect("12");
+        fs::create_dir(root(srv).join(".13.partial")).expect("mkdir");
+        // e.g. a backup tool copying the tree: the snapshot's own time must not move
+        fs::File::open(&first)
+            .and_then(|d| d.set_modified(SystemTime::now()))
+            .expect("touch");
+
+        let snaps = taken(srv).expect("taken");
+        assert_eq!(snaps, vec![(7, seven), (12, twelve)]);
+        assert_eq!(pick(&snaps, Pick::Generation(12)).expect("12").0, 12);
+        assert!(pick(&snaps, Pick::Generation(9)).is_err());
+        assert_eq!(pick(&snaps, Pick::At(twelve - 1)).expect("at").0, 7);
+        assert_eq!(pick(&snaps, Pick::At(twelve)).expect("at").0, 12);
+        assert!(pick(&snaps, Pick::At(seven - 1)).is_err());
+
+        // what a client copies from a remote root: only the manifests
+        let copy = tmp.path().join("copy");
+        fs::create_dir_all(copy.join("12")).expect("mkdir");
+        fs::copy(
+            root(srv).join("12").join(MANIFEST),
+            copy.join("12").join(MANIFEST),
+        )
+        .expect("copy");
+        assert_eq!(taken_in(&copy).expect("taken"), vec![(12, twelve)]);
+    }
+}
@@ -0,0 +1,291 @@
+//! Server applies land in a staging copy and reach the live tree only whole.
+//!
+//! Before rsync runs, each path the push copies is copied into `.staging/<n>/` under the sync
+//! root, and nothing else: rsync compares against and `--delete`s within exactly those paths.
+//! That is the push's scope, the directories under its anchors the client saw change, so a
+//! one-file edit stages one directory rather than the whole anchor. The copy's files are their own inodes (cloned copy-on-write where the filesystem can, else
+//! written out), never hardlinks: rsync changes the mode, owner or times of a file it would
+//! not otherwise rewrite in place, which would reach the live tree and every snapshot sharing
+//! it. So nothing rsync does in the stage can touch the live tree. Once rsync succeeds each
+//! anchor is swapped into place with one rename; if that fails partway the swaps already made
+//! are undone. A stage that is not promoted is removed when dropped, and [`sweep`] clears what
+//! a crash left behind.
+
+use std::{
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use log::{info, warn};
+
+use crate::{outcome::Outcome, snapshot::remove_any};
+
+pub const STAGING_DIR: &str = ".staging";
+
+static NEXT_STAGE: AtomicU64 = AtomicU64::new(0);
+
+/// One apply's staging copy; see the module docs.
+#[derive(Debug)]
+pub struct Stage {
+    dir: PathBuf,
+    tree: PathBuf,
+    anchors: Vec<PathBuf>,
+}
+
+impl Stage {
+    /// Copies the live copy of each of `anchors` (client paths; a push's scope) into a fresh
+    /// stage.
+    pub fn prepare(srv_dir: &Path, anchors: &[PathBuf]) -> Outcome<Stage> {
+        let dir = srv_dir.join(STAGING_DIR).join(format!(
+            "{}-{}",
+            std::process::id(),
+            NEXT_STAGE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let stage = Stage {
+            tree: dir.join("tree"),
+            dir,
+            // a nested anchor comes along with the one holding it
+            anchors: anchors
+                .iter()
+                .filter(|a| !anchors.iter().any(|b| b != *a && a.starts_with(b)))
+                .map(|a| a.strip_prefix("/").unwrap_or(a).to_path_buf())
+                .collect(),
+        };
+        fs::create_dir_all(&stage.tree)
+            .map_err(|e| format!("create stage '{}': {e}", stage.dir.display()))?;
+        for rel in &stage.anchors {
+            let live = srv_dir.join(rel);
+            if fs::symlink_metadata(&live).is_err() {
+                continue;
+            }
+            let staged = stage.tree.join(rel);
+            if let Some(parent) = staged.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            copy_tree(&live, &staged).map_err(|e| format!("stage '{}': {e}", live.display()))?;
+        }
+        Ok(stage)
+    }
+
+    /// Where rsync writes (`rsync -R` recreates the anchors' full paths under it).
+    #[must_use]
+    pub fn dest(&self) -> PathBuf {
+        PathBuf::from(format!("{}/", self.tree.display()))
+    }
+
+    /// Puts every staged anchor in place of the live one. On error the live tree is as it was.
+    pub fn promote(self, srv_dir: &Path) -> Outcome<()> {
+        let mut swapped: Vec<(PathBuf, PathBuf, bool)> = Vec::new();
+        for rel in &self.anchors {
+            let (staged, live) = (self.tree.join(rel), srv_dir.join(rel));
+            if fs::symlink_metadata(&staged).is_err() {
+                continue;
+            }
+            let existed = fs::symlink_metadata(&live).is_ok();
+            let moved = if existed {
+                exchange(&staged, &live)
+            } else {
+                live.parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|()| fs::rename(&staged, &live))
+            };
+            if let Err(e) = moved {
+                for (staged, live, existed) in swapped.iter().rev() {
+                    let undo = if *existed {
+                        exchange(staged, live)
+                    } else {
+                        fs::rename(live, staged)
+                    };
+                    if let Err(undo) = undo {
+                        warn!("staging: unable to undo '{}': {undo}", live.display());
+                    }
+                }
+                return bad!("promote '{}': {e}", live.display());
+            }
+            swapped.push((staged, live, existed));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Stage {
+    // after a promotion this holds the replaced copies; otherwise the abandoned apply
+    fn drop(&mut self) {
+        if let Err(e) = remove_any(&self.dir) {
+            warn!("staging: unable to remove '{}': {e}", self.dir.display());
+        }
+    }
+}
+
+/// Removes stages a crashed server left behind; call before the first apply.
+pub fn sweep(srv_dir: &Path) -> Outcome<()> {
+    let root = srv_dir.join(STAGING_DIR);
+    let entries = match fs::read_dir(&root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return bad!("read '{}': {e}", root.display()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        info!("staging: removing leftover '{}'", path.display());
+        remove_any(&path)?;
+    }
+    Ok(())
+}
+
+/// Recreates `src` at `dest`: directories created, symlinks copied and files copied with
+/// their mode, owner and times, so rsync's quick check still sees them as unchanged.
+fn copy_tree(src: &Path, dest: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    if meta.is_dir() {
+        fs::create_dir(dest)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &dest.join(entry.file_name()))?;
+        }
+        fs::set_permissions(dest, meta.permissions())
+    } else if meta.file_type().is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(src)?, dest)
+    } else {
+        copy_file(src, dest)?;
+        let times = fs::FileTimes::new()
+            .set_accessed(meta.accessed()?)
+            .set_modified(meta.modified()?);
+        fs::File::options()
+            .write(true)
+            .open(dest)?
+            .set_times(times)?;
+        // only root can give files away; anyone else owns what rsync writes anyway
+        match std::os::unix::fs::chown(dest, Some(meta.uid()), Some(meta.gid())) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+            other => other,
+        }
+    }
+}
+
+/// Copies `src` to `dest` with its mode; a copy-on-write clone where the filesystem has them.
+#[cfg(target_os = "linux")]
+fn copy_file(src: &Path, dest: &Path) -> io::Result<()> {
+    use std::os::{fd::AsRawFd, unix::fs::PermissionsExt};
+
+    let from = fs::File::open(src)?;
+    let mode = from.metadata()?.permissions().mode();
+    let to = fs::File::create(dest)?;
+    if unsafe { libc::ioctl(to.as_raw_fd(), libc::FICLONE, from.as_raw_fd()) } == 0 {
+        return to.set_permissions(fs::Permissions::from_mode(mode));
+    }
+    drop(to);
+    fs::copy(src, dest).map(|_| ())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_file(src: &Path, dest: &Path) -> io::Result<()> {
+    fs::copy(src, dest).map(|_| ())
+}
+
+/// Swaps two paths in one step.
+#[cfg(target_os = "linux")]
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    let rc = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Swaps two paths; without `renameat2` the live path is briefly absent.
+#[cfg(not(target_os = "linux"))]
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    let aside = a.with_extension("swap");
+    fs::rename(b, &aside)?;
+    fs::rename(a, b)?;
+    fs::rename(&aside, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        os::unix::fs::{MetadataExt, PermissionsExt},
+        path::PathBuf,
+    };
+
+    use super::{sweep, Stage, STAGING_DIR};
+
+    #[test]
+    fn promoted_stage_replaces_anchor_and_dropped_stage_leaves_live_alone() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let srv = tmp.path();
+        fs::create_dir_all(srv.join("home/a/docs")).expect("mkdir");
+        fs::write(srv.join("home/a/docs/keep"), "keep").expect("write");
+        fs::write(srv.join("home/a/docs/old"), "old").expect("write");
+        let anchors = [PathBuf::from("/home/a/docs"), PathBuf::from("/home/a/new")];
+
+        // what rsync would do in the stage: write a new file, delete one
+        let stage = Stage::prepare(srv, &anchors).expect("prepare");
+        let staged = stage.dest().join("home/a/docs");
+        fs::write(staged.join("fresh"), "fresh").expect("write");
+        fs::remove_file(staged.join("old")).expect("rm");
+        fs::create_dir_all(stage.dest().join("home/a/new")).expect("mkdir");
+        assert!(srv.join("home/a/docs/old").exists(), "live untouched");
+        drop(stage);
+        assert!(!srv.join("home/a/docs/fresh").exists());
+        assert_eq!(
+            fs::read_dir(srv.join(STAGING_DIR)).expect("dir").count(),
+            0,
+            "abandoned stage removed"
+        );
+
+        let stage = Stage::prepare(srv, &anchors).expect("prepare");
+        let staged = stage.dest().join("home/a/docs");
+        fs::write(staged.join("fresh"), "fresh").expect("write");
+        fs::remove_file(staged.join("old")).expect("rm");
+        fs::create_dir_all(stage.dest().join("home/a/new")).expect("mkdir");
+        stage.promote(srv).expect("promote");
+        let docs = srv.join("home/a/docs");
+        assert!(docs.join("fresh").exists() && docs.join("keep").exists());
+        assert!(!docs.join("old").exists());
+        assert!(srv.join("home/a/new").is_dir());
+
+        // rsync changes attributes in place; in the stage that reaches no live file
+        let stage = Stage::prepare(srv, &anchors).expect("prepare");
+        let keep = stage.dest().join("home/a/docs/keep");
+        let (staged_meta, live_meta) = (
+            fs::metadata(&keep).expect("meta"),
+            fs::metadata(docs.join("keep")).expect("meta"),
+        );
+        assert_ne!(staged_meta.ino(), live_meta.ino());
+        assert_eq!(
+            staged_meta.mtime(),
+            live_meta.mtime(),
+            "quick check still matches"
+        );
+        fs::set_permissions(&keep, fs::Permissions::from_mode(0o600)).expect("chmod");
+        assert_ne!(
+            fs::metadata(docs.join("keep")).expect("meta").mode() & 0o777,
+            0o600,
+            "live mode untouched"
+        );
+        assert_eq!(fs::read_to_string(&keep).expect("read"), "keep");
+        drop(stage);
+
+        fs::create_dir_all(srv.join(STAGING_DIR).join("crashed/tree")).expect("mkdir");
+        sweep(srv).expect("sweep");
+        assert_eq!(fs::read_dir(srv.join(STAGING_DIR)).expect("dir").count(), 0);
+    }
+}
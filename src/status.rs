@@ -0,0 +1,188 @@
+//! Live snapshot of the client daemon for `sinkd client status`.
+//!
+//! The Zenoh thread refreshes a [`StatusBoard`] every loop and rewrites `status.toml` in the
+//! client state dir whenever something other than the timestamp changed. The CLI reads that
+//! file and checks whether the recorded pid is still alive.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{durable, outcome::Outcome, time};
+
+const STATUS_FILE: &str = "status.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientStatus {
+    pub pid: u32,
+    pub client_id: String,
+    pub acked_generation: u64,
+    /// Last `head_generation` the server broadcast (`None` until we hear from it).
+    pub server_head_generation: Option<u64>,
+    /// Last server status as shown in the logs (`Ready`, `NotReady(Busy)`, ...).
+    pub server_status: Option<String>,
+    /// Why the server turned our last push away (quota, authorization), until one lands.
+    pub refused: Option<String>,
+    pub behind: bool,
+    pub dirty_files: usize,
+    /// Anchors queued for the next push.
+    pub pending_anchors: Vec<PathBuf>,
+    /// Anchors published and waiting for the server ack.
+    pub in_flight_anchors: Vec<PathBuf>,
+    /// Local rsync currently running (e.g. a `Behind` pull).
+    pub rsync: Option<String>,
+    pub paused_anchors: Vec<PathBuf>,
+    /// Anchors configured but not watched (the watcher could not be set up).
+    pub degraded_anchors: Vec<PathBuf>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<String>,
+    pub changed_at: String,
+}
+
+impl ClientStatus {
+    fn fingerprint(&self) -> ClientStatus {
+        ClientStatus {
+            changed_at: String::new(),
+            ..self.clone()
+        }
+    }
+}
+
+/// What `sinkd client status` prints: the snapshot plus whether its daemon still runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusReport {
+    pub alive: bool,
+    #[serde(flatten)]
+    pub status: ClientStatus,
+}
+
+#[derive(Debug)]
+pub struct StatusBoard {
+    path: PathBuf,
+    current: Mutex<ClientStatus>,
+    written: Mutex<Option<ClientStatus>>,
+}
+
+impl StatusBoard {
+    #[must_use]
+    pub fn new(client_state_dir: &Path, client_id: &str) -> Self {
+        StatusBoard {
+            path: client_state_dir.join(STATUS_FILE),
+            current: Mutex::new(ClientStatus {
+                pid: std::process::id(),
+                client_id: client_id.to_string(),
+                ..ClientStatus::default()
+            }),
+            written: Mutex::new(None),
+        }
+    }
+
+    pub fn update<F: FnOnce(&mut ClientStatus)>(&self, f: F) {
+        if let Ok(mut st) = self.current.lock() {
+            f(&mut st);
+        }
+    }
+
+    pub fn record_error(&self, error: &str) {
+        self.update(|st| {
+            st.last_error = Some(error.to_string());
+            st.last_error_at = Some(time::stamp(Some("%F %T")));
+        });
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> ClientStatus {
+        self.current.lock().map(|st| st.clone()).unwrap_or_default()
+    }
+
+    /// Writes `status.toml` if anything changed since the last flush.
+    pub fn flush(&self) -> Outcome<()> {
+        let mut st = self.snapshot();
+        let mut written = self
+            .written
+            .lock()
+            .map_err(|e| format!("status board lock: {e}"))?;
+        let fingerprint = st.fingerprint();
+        if written.as_ref() == Some(&fingerprint) {
+            return Ok(());
+        }
+        st.changed_at = time::stamp(Some("%F %T"));
+        let serialized = toml::to_string(&st).map_err(|e| format!("serialize status: {e}"))?;
+        durable::write_atomic(&self.path, serialized.as_bytes())?;
+        *written = Some(fingerprint);
+        Ok(())
+    }
+}
+
+/// Last snapshot left in `client_state_dir`, if any daemon ever wrote one.
+#[must_use]
+pub fn read(client_state_dir: &Path) -> Option<ClientStatus> {
+    let path = client_state_dir.join(STATUS_FILE);
+    let raw = fs::read_to_string(&path).ok()?;
+    toml::from_str(&raw)
+        .map_err(|e| warn!("unreadable status file '{}': {e}", path.display()))
+        .ok()
+}
+
+/// True when a process with `pid` exists (it may belong to another user).
+#[must_use]
+pub fn pid_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        use nix::{errno::Errno, sys::signal::kill, unistd::Pid};
+        let Ok(raw) = i32::try_from(pid) else {
+            return false;
+        };
+        raw > 0 && matches!(kill(Pid::from_raw(raw), None), Ok(()) | Err(Errno::EPERM))
+    }
+    #[cfg(not(unix))]
+    {
+        pid != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{pid_alive, read, StatusBoard};
+
+    #[test]
+    fn flush_round_trips_and_skips_unchanged_snapshots() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let board = StatusBoard::new(tmp.path(), "abc");
+        board.update(|st| {
+            st.acked_generation = 4;
+            st.server_head_generation = Some(6);
+            st.behind = true;
+            st.pending_anchors = vec![PathBuf::from("/w/a")];
+        });
+        board.flush().expect("flush");
+        let first = read(tmp.path()).expect("status");
+        assert_eq!(first.client_id, "abc");
+        assert_eq!(first.server_head_generation, Some(6));
+        assert!(first.behind);
+        assert_eq!(first.pending_anchors, vec![PathBuf::from("/w/a")]);
+
+        std::fs::remove_file(tmp.path().join("status.toml")).expect("rm");
+        board.flush().expect("flush unchanged");
+        assert!(read(tmp.path()).is_none());
+
+        board.record_error("rsync exited 23");
+        board.flush().expect("flush error");
+        let second = read(tmp.path()).expect("status");
+        assert_eq!(second.last_error.as_deref(), Some("rsync exited 23"));
+    }
+
+    #[test]
+    fn own_pid_is_alive() {
+        assert!(pid_alive(std::process::id()));
+        assert!(!pid_alive(0));
+    }
+}
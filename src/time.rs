@@ -14,6 +14,15 @@ pub fn stamp(fmt: Option<&str>) -> String {
     now.format(fmt.unwrap_or(DEFAULT_FORMAT)).to_string()
 }
 
+/// Unix seconds as local `YYYY-MM-DD HH:MM:SS` (the number itself if out of range).
+#[must_use]
+pub fn local_stamp(unix_secs: i64) -> String {
+    DateTime::from_timestamp(unix_secs, 0).map_or_else(
+        || unix_secs.to_string(),
+        |t| t.with_timezone(&Local).format("%F %T").to_string(),
+    )
+}
+
 #[derive(Debug, PartialEq)]
 struct LastSync {
     timestamp: String,
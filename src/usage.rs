@@ -0,0 +1,316 @@
+//! Disk usage of the server's sync root, per user and per share, and the quotas on it.
+//!
+//! The sync root mirrors client paths, so a user's files live under `home/<user>` (or
+//! `Users/<user>` from macOS clients); a share is any other tree named in `[quotas]`. The
+//! server walks everything once at startup into a [`Ledger`], then after each apply re-measures
+//! only the anchors that push touched.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::outcome::Outcome;
+
+/// Where user trees start inside the sync root.
+const USER_ROOTS: [&str; 2] = ["home", "Users"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserUsage {
+    pub user: String,
+    pub bytes: u64,
+}
+
+/// `[quotas]` table in the server config. Limits are in bytes; 0 means none.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaConfig {
+    /// Limit for every user without an entry in `users`.
+    pub user_bytes: u64,
+    pub users: BTreeMap<String, u64>,
+    pub shares: Vec<ShareQuota>,
+}
+
+/// A tree outside the user homes, as clients name it (e.g. `/srv/share/team`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShareQuota {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// Whose usage a path counts against.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Owner {
+    User(String),
+    Share(PathBuf),
+}
+
+impl Owner {
+    #[must_use]
+    pub fn name(&self) -> String {
+        match self {
+            Owner::User(user) => user.clone(),
+            Owner::Share(path) => path.display().to_string(),
+        }
+    }
+}
+
+impl QuotaConfig {
+    /// The share holding `path`, else the user whose home holds it.
+    #[must_use]
+    pub fn owner(&self, path: &Path) -> Option<Owner> {
+        if let Some(share) = self
+            .shares
+            .iter()
+            .filter(|s| path.starts_with(&s.path))
+            .max_by_key(|s| s.path.components().count())
+        {
+            return Some(Owner::Share(share.path.clone()));
+        }
+        let rel = path.strip_prefix("/").unwrap_or(path);
+        let mut parts = rel.components();
+        let root = parts.next()?.as_os_str().to_str()?;
+        let user = parts.next()?.as_os_str().to_string_lossy().into_owned();
+        USER_ROOTS.contains(&root).then_some(Owner::User(user))
+    }
+
+    fn limit(&self, owner: &Owner) -> u64 {
+        match owner {
+            Owner::User(user) => self.users.get(user).copied().unwrap_or(self.user_bytes),
+            Owner::Share(path) => self
+                .shares
+                .iter()
+                .find(|s| &s.path == path)
+                .map_or(0, |s| s.bytes),
+        }
+    }
+}
+
+/// Usage against a limit, for `sinkd server ls`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaUsage {
+    pub owner: String,
+    pub used_bytes: u64,
+    pub limit_bytes: u64,
+}
+
+/// Bytes used per [`Owner`], kept current apply by apply.
+#[derive(Debug, Default)]
+pub struct Ledger {
+    cfg: QuotaConfig,
+    used: BTreeMap<Owner, u64>,
+}
+
+impl Ledger {
+    /// Walks the whole sync root once.
+    pub fn scan(cfg: QuotaConfig, srv_dir: &Path) -> Outcome<Ledger> {
+        let mut used: BTreeMap<Owner, u64> = by_user(srv_dir)?
+            .into_iter()
+            .map(|u| (Owner::User(u.user), u.bytes))
+            .collect();
+        for share in &cfg.shares {
+            used.insert(
+                Owner::Share(share.path.clone()),
+                tree_size(&in_root(srv_dir, &share.path)),
+            );
+        }
+        Ok(Ledger { cfg, used })
+    }
+
+    /// Size of each of `anchors` in the sync root, by owner. Measure before and after an apply
+    /// and hand both to [`Ledger::adjust`].
+    #[must_use]
+    pub fn measure(&self, srv_dir: &Path, anchors: &[PathBuf]) -> BTreeMap<Owner, u64> {
+        let mut sizes = BTreeMap::new();
+        for anchor in anchors {
+            // a nested anchor is already counted with the one holding it
+            if anchors.iter().any(|a| a != anchor && anchor.starts_with(a)) {
+                continue;
+            }
+            if let Some(owner) = self.cfg.owner(anchor) {
+                *sizes.entry(owner).or_insert(0) += tree_size(&in_root(srv_dir, anchor));
+            }
+        }
+        sizes
+    }
+
+    pub fn adjust(&mut self, before: &BTreeMap<Owner, u64>, after: &BTreeMap<Owner, u64>) {
+        for (owner, bytes) in after {
+            let was = before.get(owner).copied().unwrap_or(0);
+            let used = self.used.entry(owner.clone()).or_insert(0);
+            *used = used.saturating_sub(was).saturating_add(*bytes);
+        }
+    }
+
+    /// The first owner of `paths` already at or over its limit. A push is not sized up front,
+    /// so the last one let through may overshoot.
+    #[must_use]
+    pub fn exceeded(&self, paths: &[PathBuf]) -> Option<QuotaUsage> {
+        paths
+            .iter()
+            .filter_map(|p| self.cfg.owner(p))
+            .map(|owner| self.usage(&owner))
+            .find(|q| q.limit_bytes > 0 && q.used_bytes >= q.limit_bytes)
+    }
+
+    fn usage(&self, owner: &Owner) -> QuotaUsage {
+        QuotaUsage {
+            owner: owner.name(),
+            used_bytes: self.used.get(owner).copied().unwrap_or(0),
+            limit_bytes: self.cfg.limit(owner),
+        }
+    }
+
+    /// Users by bytes, largest first, like [`by_user`].
+    #[must_use]
+    pub fn users(&self) -> Vec<UserUsage> {
+        let mut users: Vec<UserUsage> = self
+            .used
+            .iter()
+            .filter_map(|(owner, bytes)| match owner {
+                Owner::User(user) => Some(UserUsage {
+                    user: user.clone(),
+                    bytes: *bytes,
+                }),
+                Owner::Share(_) => None,
+            })
+            .collect();
+        users.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.user.cmp(&b.user)));
+        users
+    }
+
+    /// Every owner with a limit.
+    #[must_use]
+    pub fn quotas(&self) -> Vec<QuotaUsage> {
+        let mut owners: Vec<Owner> = self.used.keys().cloned().collect();
+        owners.extend(self.cfg.users.keys().cloned().map(Owner::User));
+        owners.sort();
+        owners.dedup();
+        owners
+            .iter()
+            .map(|o| self.usage(o))
+            .filter(|q| q.limit_bytes > 0)
+            .collect()
+    }
+}
+
+/// Where a client path lands in the sync root (`rsync -R` keeps the full path).
+fn in_root(srv_dir: &Path, path: &Path) -> PathBuf {
+    srv_dir.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// Bytes under each user tree, largest first.
+pub fn by_user(srv_dir: &Path) -> Outcome<Vec<UserUsage>> {
+    let mut usage: Vec<UserUsage> = Vec::new();
+    for root in USER_ROOTS {
+        let entries = match fs::read_dir(srv_dir.join(root)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return bad!("read '{}': {e}", srv_dir.join(root).display()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let user = entry.file_name().to_string_lossy().into_owned();
+            let bytes = tree_size(&entry.path());
+            match usage.iter_mut().find(|u| u.user == user) {
+                Some(u) => u.bytes += bytes,
+                None => usage.push(UserUsage { user, bytes }),
+            }
+        }
+    }
+    usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.user.cmp(&b.user)));
+    Ok(usage)
+}
+
+/// Apparent size of the files under `path` (symlinks not followed); unreadable parts count 0.
+#[must_use]
+pub fn tree_size(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path).map_or(0, |entries| {
+        entries
+            .filter_map(Result::ok)
+            .map(|e| tree_size(&e.path()))
+            .sum()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use super::{by_user, Ledger, Owner, QuotaConfig, ShareQuota};
+
+    #[test]
+    fn usage_sums_each_user_tree() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let srv = tmp.path();
+        fs::create_dir_all(srv.join("home/alice/docs")).expect("mkdir");
+        fs::create_dir_all(srv.join("home/bob")).expect("mkdir");
+        fs::create_dir_all(srv.join("Users/alice")).expect("mkdir");
+        fs::write(srv.join("home/alice/docs/a"), [0u8; 100]).expect("write");
+        fs::write(srv.join("Users/alice/b"), [0u8; 20]).expect("write");
+        fs::write(srv.join("home/bob/c"), [0u8; 30]).expect("write");
+        fs::write(srv.join("generation_state.toml"), "x").expect("write");
+
+        let usage = by_user(srv).expect("usage");
+        let pairs: Vec<(&str, u64)> = usage.iter().map(|u| (u.user.as_str(), u.bytes)).collect();
+        assert_eq!(pairs, vec![("alice", 120), ("bob", 30)]);
+    }
+
+    #[test]
+    fn ledger_follows_applies_and_flags_owners_over_quota() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let srv = tmp.path();
+        fs::create_dir_all(srv.join("home/alice/docs")).expect("mkdir");
+        fs::create_dir_all(srv.join("srv/share")).expect("mkdir");
+        fs::write(srv.join("home/alice/docs/a"), [0u8; 60]).expect("write");
+        fs::write(srv.join("srv/share/s"), [0u8; 10]).expect("write");
+        let cfg = QuotaConfig {
+            user_bytes: 100,
+            users: [("bob".to_string(), 0)].into_iter().collect(),
+            shares: vec![ShareQuota {
+                path: PathBuf::from("/srv/share"),
+                bytes: 50,
+            }],
+        };
+        assert_eq!(
+            cfg.owner(Path::new("/home/alice/docs")),
+            Some(Owner::User("alice".to_string()))
+        );
+        assert_eq!(cfg.owner(Path::new("/opt/x")), None);
+
+        let mut ledger = Ledger::scan(cfg, srv).expect("scan");
+        let docs = [PathBuf::from("/home/alice/docs")];
+        assert!(ledger.exceeded(&docs).is_none());
+
+        let before = ledger.measure(srv, &docs);
+        fs::write(srv.join("home/alice/docs/b"), [0u8; 50]).expect("write");
+        let after = ledger.measure(srv, &docs);
+        ledger.adjust(&before, &after);
+        let over = ledger.exceeded(&docs).expect("110 of 100");
+        assert_eq!((over.used_bytes, over.limit_bytes), (110, 100));
+        assert!(
+            ledger.exceeded(&[PathBuf::from("/home/bob")]).is_none(),
+            "no limit"
+        );
+        assert!(ledger.exceeded(&[PathBuf::from("/srv/share/x")]).is_none());
+        assert_eq!(ledger.users()[0].bytes, 110);
+        assert_eq!(ledger.quotas().len(), 2);
+    }
+}